@@ -0,0 +1,52 @@
+use pinocchio::error::ProgramError;
+use pinocchio_log::log;
+
+/// Escrow failures, surfaced to clients as `ProgramError::Custom(code)`
+#[repr(u32)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EscrowError {
+    /// The program was invoked under an address other than `crate::ID`
+    InvalidProgramId = 0,
+    /// The escrow account is not the PDA derived from the maker, seed and bump
+    InvalidPda = 1,
+    /// The maker account or its token account does not match the escrow
+    InvalidMaker = 2,
+    /// A mint does not match the one recorded in the escrow
+    MintMismatch = 3,
+    /// The paying token account holds less than the transfer needs
+    InsufficientFunds = 4,
+    /// The escrow account has already been created
+    AlreadyInitialized = 5,
+    /// The vault is not the escrow's token account for mint A
+    InvalidVault = 6,
+    /// The fill is zero or larger than what is still outstanding
+    InvalidFillAmount = 7,
+    /// The fill is too small to release any of the deposit
+    FillTooSmall = 8,
+    /// An amount overflowed or underflowed
+    MathOverflow = 9,
+}
+
+impl EscrowError {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EscrowError::InvalidProgramId => "Invalid program id",
+            EscrowError::InvalidPda => "Escrow account does not match the expected PDA",
+            EscrowError::InvalidMaker => "Maker does not match the escrow",
+            EscrowError::MintMismatch => "Mint does not match the escrow",
+            EscrowError::InsufficientFunds => "Insufficient funds for the transfer",
+            EscrowError::AlreadyInitialized => "Escrow account already initialized",
+            EscrowError::InvalidVault => "Vault does not belong to the escrow",
+            EscrowError::InvalidFillAmount => "Fill amount must be between one and the outstanding amount",
+            EscrowError::FillTooSmall => "Fill amount is too small to release any of the deposit",
+            EscrowError::MathOverflow => "Arithmetic overflow",
+        }
+    }
+}
+
+impl From<EscrowError> for ProgramError {
+    fn from(error: EscrowError) -> Self {
+        log!("EscrowError: {}", error.as_str());
+        ProgramError::Custom(error as u32)
+    }
+}
@@ -0,0 +1,93 @@
+//! Events for indexers, written to the transaction logs with `sol_log_data`.
+//!
+//! Each event shows up as a single `Program data: <base64>` line holding the
+//! event discriminator followed by the event struct. Like the instruction args,
+//! every field is a little-endian byte array so the structs have no padding.
+
+macro_rules! impl_event {
+    ($event:ty, $discriminator:expr) => {
+        impl $event {
+            pub const DISCRIMINATOR: u8 = $discriminator;
+
+            pub const LEN: usize = core::mem::size_of::<$event>();
+
+            pub fn emit(&self) {
+                let mut data = [0u8; 1 + core::mem::size_of::<$event>()];
+                data[0] = Self::DISCRIMINATOR;
+                data[1..].copy_from_slice(unsafe {
+                    core::slice::from_raw_parts(self as *const Self as *const u8, Self::LEN)
+                });
+
+                log_data(&[&data]);
+            }
+
+            /// Reads the event back from the decoded `Program data` payload
+            pub fn from_log_data(data: &[u8]) -> Option<&Self> {
+                match data.split_first() {
+                    Some((&Self::DISCRIMINATOR, event)) if event.len() == Self::LEN => {
+                        Some(unsafe { &*(event.as_ptr() as *const Self) })
+                    }
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+fn log_data(data: &[&[u8]]) {
+    #[cfg(target_os = "solana")]
+    unsafe {
+        pinocchio::syscalls::sol_log_data(data as *const _ as *const u8, data.len() as u64);
+    }
+
+    #[cfg(not(target_os = "solana"))]
+    core::hint::black_box(data);
+}
+
+#[repr(C)]
+pub struct EscrowCreated {
+    pub escrow: [u8; 32],
+    pub maker: [u8; 32],
+    pub mint_a: [u8; 32],
+    pub mint_b: [u8; 32],
+    pub seed: [u8; 8],
+    /// Mint A that landed in the vault
+    pub amount_to_give: [u8; 8],
+    pub amount_to_receive: [u8; 8],
+    pub timestamp: [u8; 8],
+}
+
+impl_event!(EscrowCreated, 0);
+
+/// Emitted for every fill, `outstanding` is zero once the escrow is closed
+#[repr(C)]
+pub struct EscrowTaken {
+    pub escrow: [u8; 32],
+    pub maker: [u8; 32],
+    pub taker: [u8; 32],
+    pub mint_a: [u8; 32],
+    pub mint_b: [u8; 32],
+    pub seed: [u8; 8],
+    /// Mint B credited to the maker
+    pub amount_paid: [u8; 8],
+    /// Mint A sent out of the vault to the taker
+    pub amount_released: [u8; 8],
+    pub outstanding: [u8; 8],
+    pub timestamp: [u8; 8],
+}
+
+impl_event!(EscrowTaken, 1);
+
+#[repr(C)]
+pub struct EscrowRefunded {
+    pub escrow: [u8; 32],
+    pub maker: [u8; 32],
+    pub mint_a: [u8; 32],
+    pub mint_b: [u8; 32],
+    pub seed: [u8; 8],
+    /// Mint A sent back to the maker
+    pub amount: [u8; 8],
+    pub timestamp: [u8; 8],
+}
+
+impl_event!(EscrowRefunded, 2);
@@ -0,0 +1,95 @@
+use pinocchio::error::ProgramError;
+
+/// Instruction payloads are read in place: every field is a byte array so the
+/// structs have an alignment of 1 and can be cast from any offset of the input.
+macro_rules! impl_from_bytes {
+    ($args:ty) => {
+        impl $args {
+            pub const LEN: usize = core::mem::size_of::<$args>();
+
+            pub fn from_bytes(data: &[u8]) -> Result<&Self, ProgramError> {
+                if data.len() != Self::LEN {
+                    return Err(ProgramError::InvalidInstructionData);
+                }
+
+                Ok(unsafe { &*(data.as_ptr() as *const Self) })
+            }
+        }
+    };
+}
+
+#[repr(C)]
+pub struct MakeArgs {
+    pub bump: u8,
+    amount_to_receive: [u8; 8],
+    amount_to_give: [u8; 8],
+}
+
+impl_from_bytes!(MakeArgs);
+
+impl MakeArgs {
+    pub fn amount_to_receive(&self) -> u64 {
+        u64::from_le_bytes(self.amount_to_receive)
+    }
+
+    pub fn amount_to_give(&self) -> u64 {
+        u64::from_le_bytes(self.amount_to_give)
+    }
+}
+
+#[repr(C)]
+pub struct MakeV2Args {
+    pub bump: u8,
+    seed: [u8; 8],
+    amount_to_receive: [u8; 8],
+    amount_to_give: [u8; 8],
+}
+
+impl_from_bytes!(MakeV2Args);
+
+impl MakeV2Args {
+    pub fn seed(&self) -> u64 {
+        u64::from_le_bytes(self.seed)
+    }
+
+    pub fn amount_to_receive(&self) -> u64 {
+        u64::from_le_bytes(self.amount_to_receive)
+    }
+
+    pub fn amount_to_give(&self) -> u64 {
+        u64::from_le_bytes(self.amount_to_give)
+    }
+}
+
+#[repr(C)]
+pub struct TakeArgs {
+    pub bump: u8,
+    /// How many of the remaining accounts go to the mint A transfer, the rest go to mint B
+    pub extra_accounts_a: u8,
+}
+
+impl_from_bytes!(TakeArgs);
+
+#[repr(C)]
+pub struct TakePartialArgs {
+    pub bump: u8,
+    /// How many of the remaining accounts go to the mint A transfer, the rest go to mint B
+    pub extra_accounts_a: u8,
+    amount: [u8; 8],
+}
+
+impl_from_bytes!(TakePartialArgs);
+
+impl TakePartialArgs {
+    /// Mint B the taker pays the maker in this fill
+    pub fn amount(&self) -> u64 {
+        u64::from_le_bytes(self.amount)
+    }
+}
+
+#[repr(C)]
+pub struct CancelArgs {
+    pub bump: u8,
+}
+
+impl_from_bytes!(CancelArgs);
@@ -0,0 +1,119 @@
+use pinocchio::{
+    AccountView, ProgramResult, cpi::{Seed, Signer}, error::ProgramError, sysvars::{Sysvar, clock::Clock}
+};
+
+use crate::{error::EscrowError, events::EscrowRefunded, instructions::CancelArgs, state::Escrow, token_interface};
+
+pub fn process_cancel_instruction(
+    accounts: &[AccountView],
+    data: &[u8],
+) -> ProgramResult {
+    let args = CancelArgs::from_bytes(data)?;
+
+    let [
+        maker,
+        mint_a,
+        escrow_account,
+        maker_ata_a,
+        escrow_ata,
+        _system_program,
+        token_program,
+        remaining_accounts @ ..
+    ] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    token_interface::check_token_program(token_program)?;
+
+    if !maker.is_signer() {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    unsafe {
+        if escrow_account.owner() != &crate::ID {
+            return Err(ProgramError::IllegalOwner);
+        }
+    }
+
+    Escrow::migrate(escrow_account, maker, args.bump)?;
+
+    let bump = args.bump;
+
+    let (is_seeded, escrow_seed, mint_b) = {
+        let escrow_state = Escrow::from_account_info(escrow_account)?;
+
+        // Only the maker that opened the escrow can cancel it
+        if escrow_state.maker() != *maker.address() {
+            return Err(EscrowError::InvalidMaker.into());
+        }
+        if escrow_state.mint_a() != *mint_a.address() {
+            return Err(EscrowError::MintMismatch.into());
+        }
+
+        // Escrows opened with `MakeV2` have the maker's seed in their PDA
+        (escrow_state.is_seeded_pda(escrow_account.address(), bump)?, escrow_state.seed().to_le_bytes(), escrow_state.mint_b())
+    };
+
+    let maker_ata_state = token_interface::token_account(maker_ata_a, token_program)?;
+    if maker_ata_state.owner != *maker.address() {
+        return Err(EscrowError::InvalidMaker.into());
+    }
+    if maker_ata_state.mint != *mint_a.address() {
+        return Err(EscrowError::MintMismatch.into());
+    }
+
+    let vault_amount = token_interface::token_account(escrow_ata, token_program)?.amount;
+
+    let bump = [bump.to_le()];
+    let seed_v1 = [Seed::from(b"escrow"), Seed::from(maker.address().as_array()), Seed::from(&bump)];
+    let seed_v2 = [Seed::from(b"escrow"), Seed::from(maker.address().as_array()), Seed::from(&escrow_seed), Seed::from(&bump)];
+    let seeds = if is_seeded {
+        Signer::from(&seed_v2[..])
+    } else {
+        Signer::from(&seed_v1[..])
+    };
+
+    token_interface::TransferChecked {
+        from: escrow_ata,
+        mint: mint_a,
+        to: maker_ata_a,
+        authority: escrow_account,
+        token_program,
+        amount: vault_amount,
+        decimals: token_interface::mint_decimals(mint_a, token_program)?,
+        extra_accounts: remaining_accounts,
+    }.invoke_signed(core::slice::from_ref(&seeds))?;
+
+    // Fees withheld in the vault would keep Token-2022 from closing it
+    if token_interface::has_transfer_fee(mint_a)? {
+        token_interface::HarvestWithheldTokensToMint {
+            mint: mint_a,
+            account: escrow_ata,
+            token_program,
+        }.invoke()?;
+    }
+
+    token_interface::CloseAccount {
+        account: escrow_ata,
+        destination: maker,
+        authority: escrow_account,
+        token_program,
+    }.invoke_signed(&[seeds])?;
+
+    EscrowRefunded {
+        escrow: *escrow_account.address().as_array(),
+        maker: *maker.address().as_array(),
+        mint_a: *mint_a.address().as_array(),
+        mint_b: *mint_b.as_array(),
+        seed: escrow_seed,
+        amount: vault_amount.to_le_bytes(),
+        timestamp: Clock::get()?.unix_timestamp.to_le_bytes(),
+    }.emit();
+
+    // Wipe the escrow state before handing its rent back to the maker
+    escrow_account.try_borrow_mut()?.fill(0);
+    maker.set_lamports(maker.lamports() + escrow_account.lamports());
+    escrow_account.close()?;
+
+    Ok(())
+}
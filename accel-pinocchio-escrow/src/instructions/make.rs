@@ -1,15 +1,36 @@
 use pinocchio::{
-    AccountView, ProgramResult, cpi::{Seed, Signer}, error::ProgramError, sysvars::{Sysvar, rent::Rent}
+    AccountView, ProgramResult, cpi::{Seed, Signer}, error::ProgramError, sysvars::{Sysvar, clock::Clock, rent::Rent}
 };
 use pinocchio_pubkey::derive_address;
 use pinocchio_system::instructions::CreateAccount;
 
-use crate::state::Escrow;
+use crate::{error::EscrowError, events::EscrowCreated, instructions::{MakeArgs, MakeV2Args}, state::Escrow, token_interface};
 
 pub fn process_make_instruction(
     accounts: &[AccountView],
     data: &[u8],
 ) -> ProgramResult {
+    let args = MakeArgs::from_bytes(data)?;
+
+    make_escrow(accounts, args.bump, None, args.amount_to_receive(), args.amount_to_give())
+}
+
+pub fn process_make_instruction_v2(
+    accounts: &[AccountView],
+    data: &[u8],
+) -> ProgramResult {
+    let args = MakeV2Args::from_bytes(data)?;
+
+    make_escrow(accounts, args.bump, Some(args.seed()), args.amount_to_receive(), args.amount_to_give())
+}
+
+fn make_escrow(
+    accounts: &[AccountView],
+    bump: u8,
+    seed: Option<u64>,
+    amount_to_receive: u64,
+    amount_to_give: u64,
+) -> ProgramResult {
 
     let [
         maker,
@@ -20,32 +41,54 @@ pub fn process_make_instruction(
         escrow_ata,
         system_program,
         token_program,
-        _associated_token_program@ ..
+        _associated_token_program,
+        remaining_accounts @ ..
     ] = accounts else {
         return Err(ProgramError::NotEnoughAccountKeys);
     };
 
-    let maker_ata_state = pinocchio_token::state::TokenAccount::from_account_view(&maker_ata)?;
-    if maker_ata_state.owner() != maker.address() {
-        return Err(ProgramError::IllegalOwner);
+    token_interface::check_token_program(token_program)?;
+
+    let maker_ata_state = token_interface::token_account(maker_ata, token_program)?;
+    if maker_ata_state.owner != *maker.address() {
+        return Err(EscrowError::InvalidMaker.into());
     }
-    if maker_ata_state.mint() != mint_a.address() {
-        return Err(ProgramError::InvalidAccountData);
+    if maker_ata_state.mint != *mint_a.address() {
+        return Err(EscrowError::MintMismatch.into());
+    }
+    if maker_ata_state.amount < amount_to_give {
+        return Err(EscrowError::InsufficientFunds.into());
     }
 
-    let bump = data[0];
-    let seed = [b"escrow".as_ref(), maker.address().as_ref(), &[bump]];
-    let seeds = &seed[..];
+    // A transfer-fee mint credits the vault with less than the maker sends
+    let decimals_a = token_interface::mint_decimals(mint_a, token_program)?;
+    let amount_in_vault = amount_to_give - token_interface::transfer_fee(mint_a, amount_to_give)?;
 
-    let escrow_account_pda = derive_address(&seed, None, &crate::ID.to_bytes());
-    assert_eq!(escrow_account_pda, *escrow_account.address().as_array());
+    let seed_bytes = seed.unwrap_or_default().to_le_bytes();
 
-    let amount_to_receive = unsafe{ *(data.as_ptr().add(1) as *const u64) };
-    let amount_to_give = unsafe{ *(data.as_ptr().add(9) as *const u64) };
+    let escrow_account_pda = match seed {
+        Some(_) => derive_address(
+            &[b"escrow".as_ref(), maker.address().as_ref(), &seed_bytes, &[bump]],
+            None,
+            &crate::ID.to_bytes(),
+        ),
+        None => derive_address(
+            &[b"escrow".as_ref(), maker.address().as_ref(), &[bump]],
+            None,
+            &crate::ID.to_bytes(),
+        ),
+    };
+    if escrow_account_pda != *escrow_account.address().as_array() {
+        return Err(EscrowError::InvalidPda.into());
+    }
 
     let bump = [bump.to_le()];
-    let seed = [Seed::from(b"escrow"), Seed::from(maker.address().as_array()), Seed::from(&bump)];
-    let seeds = Signer::from(&seed);
+    let seed_v1 = [Seed::from(b"escrow"), Seed::from(maker.address().as_array()), Seed::from(&bump)];
+    let seed_v2 = [Seed::from(b"escrow"), Seed::from(maker.address().as_array()), Seed::from(&seed_bytes), Seed::from(&bump)];
+    let seeds = match seed {
+        Some(_) => Signer::from(&seed_v2[..]),
+        None => Signer::from(&seed_v1[..]),
+    };
 
     unsafe {
         if escrow_account.owner() != &crate::ID {
@@ -55,22 +98,24 @@ pub fn process_make_instruction(
                 lamports: Rent::get()?.try_minimum_balance(Escrow::LEN)?,
                 space: Escrow::LEN as u64,
                 owner: &crate::ID,
-            }.invoke_signed(&[seeds.clone()])?;
+            }.invoke_signed(core::slice::from_ref(&seeds))?;
 
 
             {
-                let escrow_state = Escrow::from_account_info(escrow_account)?;
+                let escrow_state = Escrow::init(escrow_account)?;
             
                 escrow_state.set_maker(maker.address());
                 escrow_state.set_mint_a(mint_a.address());
                 escrow_state.set_mint_b(mint_b.address());
                 escrow_state.set_amount_to_receive(amount_to_receive);
-                escrow_state.set_amount_to_give(amount_to_give);  
-                escrow_state.bump = data[0];
+                escrow_state.set_amount_to_give(amount_in_vault);
+                escrow_state.set_remaining(amount_in_vault);
+                escrow_state.set_seed(seed.unwrap_or_default());
+                escrow_state.bump = bump[0];
             }
         }
         else {
-            return Err(ProgramError::IllegalOwner);
+            return Err(EscrowError::AlreadyInitialized.into());
         }
     }
 
@@ -79,16 +124,31 @@ pub fn process_make_instruction(
         account: escrow_ata,
         wallet: escrow_account,
         mint: mint_a,
-        token_program: token_program,
-        system_program: system_program,
+        token_program,
+        system_program,
     }.invoke()?;
 
-    pinocchio_token::instructions::Transfer {
+    token_interface::TransferChecked {
         from: maker_ata,
+        mint: mint_a,
         to: escrow_ata,
         authority: maker,
+        token_program,
         amount: amount_to_give,
+        decimals: decimals_a,
+        extra_accounts: remaining_accounts,
     }.invoke()?;
 
+    EscrowCreated {
+        escrow: *escrow_account.address().as_array(),
+        maker: *maker.address().as_array(),
+        mint_a: *mint_a.address().as_array(),
+        mint_b: *mint_b.address().as_array(),
+        seed: seed_bytes,
+        amount_to_give: amount_in_vault.to_le_bytes(),
+        amount_to_receive: amount_to_receive.to_le_bytes(),
+        timestamp: Clock::get()?.unix_timestamp.to_le_bytes(),
+    }.emit();
+
     Ok(())
-}
\ No newline at end of file
+}
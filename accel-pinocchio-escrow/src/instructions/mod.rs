@@ -1,6 +1,12 @@
+pub mod args;
+pub mod cancel;
 pub mod make;
+pub mod take;
 
+pub use args::*;
+pub use cancel::*;
 pub use make::*;
+pub use take::*;
 use pinocchio::error::ProgramError;
 
 pub enum EscrowInstrctions {
@@ -8,6 +14,7 @@ pub enum EscrowInstrctions {
     Take = 1,
     Cancel = 2,
     MakeV2 = 3,
+    TakePartial = 4,
 }
 
 impl TryFrom<&u8> for EscrowInstrctions {
@@ -19,6 +26,7 @@ impl TryFrom<&u8> for EscrowInstrctions {
             1 => Ok(EscrowInstrctions::Take),
             2 => Ok(EscrowInstrctions::Cancel),
             3 => Ok(EscrowInstrctions::MakeV2),
+            4 => Ok(EscrowInstrctions::TakePartial),
             _ => Err(ProgramError::InvalidInstructionData),
         }
     }
@@ -0,0 +1,206 @@
+use pinocchio::{
+    AccountView, ProgramResult, cpi::{Seed, Signer}, error::ProgramError, sysvars::{Sysvar, clock::Clock}
+};
+
+use crate::{error::EscrowError, events::EscrowTaken, instructions::{TakeArgs, TakePartialArgs}, state::Escrow, token_interface};
+
+pub fn process_take_instruction(
+    accounts: &[AccountView],
+    data: &[u8],
+) -> ProgramResult {
+    let args = TakeArgs::from_bytes(data)?;
+
+    take(accounts, args.bump, args.extra_accounts_a, None)
+}
+
+pub fn process_take_partial_instruction(
+    accounts: &[AccountView],
+    data: &[u8],
+) -> ProgramResult {
+    let args = TakePartialArgs::from_bytes(data)?;
+
+    take(accounts, args.bump, args.extra_accounts_a, Some(args.amount()))
+}
+
+/// Pays `amount` of mint B to the maker and releases the matching share of the
+/// vault, or fills whatever is outstanding when `amount` is `None`. The vault and
+/// escrow are closed once the escrow is completely filled.
+///
+/// Each mint has its own token program, and the first `extra_accounts_a` remaining
+/// accounts are the transfer-hook accounts of mint A, the others those of mint B.
+fn take(accounts: &[AccountView], bump: u8, extra_accounts_a: u8, amount: Option<u64>) -> ProgramResult {
+    let [
+        taker,
+        maker,
+        mint_a,
+        mint_b,
+        escrow_account,
+        taker_ata_a,
+        taker_ata_b,
+        maker_ata_b,
+        escrow_ata,
+        system_program,
+        token_program_a,
+        _associated_token_program,
+        token_program_b,
+        remaining_accounts @ ..
+    ] = accounts else {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    };
+
+    token_interface::check_token_program(token_program_a)?;
+    token_interface::check_token_program(token_program_b)?;
+
+    let extra_accounts_a = extra_accounts_a as usize;
+    if extra_accounts_a > remaining_accounts.len() {
+        return Err(ProgramError::NotEnoughAccountKeys);
+    }
+    let (extra_accounts_a, extra_accounts_b) = remaining_accounts.split_at(extra_accounts_a);
+
+    if !taker.is_signer() {
+        return Err(ProgramError::MissingRequiredSignature);
+    }
+
+    unsafe {
+        if escrow_account.owner() != &crate::ID {
+            return Err(ProgramError::IllegalOwner);
+        }
+    }
+
+    Escrow::migrate(escrow_account, taker, bump)?;
+
+    let (amount_to_fill, amount_to_release, outstanding, is_seeded, escrow_seed) = {
+        let escrow_state = Escrow::from_account_info(escrow_account)?;
+
+        if escrow_state.maker() != *maker.address() {
+            return Err(EscrowError::InvalidMaker.into());
+        }
+        if escrow_state.mint_a() != *mint_a.address() || escrow_state.mint_b() != *mint_b.address() {
+            return Err(EscrowError::MintMismatch.into());
+        }
+
+        // Escrows opened with `MakeV2` have the maker's seed in their PDA
+        let is_seeded = escrow_state.is_seeded_pda(escrow_account.address(), bump)?;
+
+        let outstanding = escrow_state.outstanding()?;
+        let amount_to_fill = amount.unwrap_or(outstanding);
+        let amount_to_release = escrow_state.fill_amount(amount_to_fill)?;
+
+        (amount_to_fill, amount_to_release, outstanding - amount_to_fill, is_seeded, escrow_state.seed().to_le_bytes())
+    };
+    let is_final_fill = outstanding == 0;
+
+    // The taker pays for any token account that does not exist yet
+    pinocchio_associated_token_account::instructions::CreateIdempotent {
+        funding_account: taker,
+        account: maker_ata_b,
+        wallet: maker,
+        mint: mint_b,
+        token_program: token_program_b,
+        system_program,
+    }.invoke()?;
+
+    pinocchio_associated_token_account::instructions::CreateIdempotent {
+        funding_account: taker,
+        account: taker_ata_a,
+        wallet: taker,
+        mint: mint_a,
+        token_program: token_program_a,
+        system_program,
+    }.invoke()?;
+
+    // The taker covers any transfer fee so the maker is credited the full amount
+    let amount_to_pay = token_interface::pre_fee_amount(mint_b, amount_to_fill)?;
+    let taker_ata_b_state = token_interface::token_account(taker_ata_b, token_program_b)?;
+    if taker_ata_b_state.mint != *mint_b.address() {
+        return Err(EscrowError::MintMismatch.into());
+    }
+    if taker_ata_b_state.amount < amount_to_pay {
+        return Err(EscrowError::InsufficientFunds.into());
+    }
+
+    token_interface::TransferChecked {
+        from: taker_ata_b,
+        mint: mint_b,
+        to: maker_ata_b,
+        authority: taker,
+        token_program: token_program_b,
+        amount: amount_to_pay,
+        decimals: token_interface::mint_decimals(mint_b, token_program_b)?,
+        extra_accounts: extra_accounts_b,
+    }.invoke()?;
+
+    let escrow_ata_state = token_interface::token_account(escrow_ata, token_program_a)?;
+    if escrow_ata_state.owner != *escrow_account.address() || escrow_ata_state.mint != *mint_a.address() {
+        return Err(EscrowError::InvalidVault.into());
+    }
+
+    let bump = [bump.to_le()];
+    let seed_v1 = [Seed::from(b"escrow"), Seed::from(maker.address().as_array()), Seed::from(&bump)];
+    let seed_v2 = [Seed::from(b"escrow"), Seed::from(maker.address().as_array()), Seed::from(&escrow_seed), Seed::from(&bump)];
+    let seeds = if is_seeded {
+        Signer::from(&seed_v2[..])
+    } else {
+        Signer::from(&seed_v1[..])
+    };
+
+    // The final fill empties the vault so it can be closed
+    let amount_to_release = if is_final_fill { escrow_ata_state.amount } else { amount_to_release };
+
+    token_interface::TransferChecked {
+        from: escrow_ata,
+        mint: mint_a,
+        to: taker_ata_a,
+        authority: escrow_account,
+        token_program: token_program_a,
+        amount: amount_to_release,
+        decimals: token_interface::mint_decimals(mint_a, token_program_a)?,
+        extra_accounts: extra_accounts_a,
+    }.invoke_signed(core::slice::from_ref(&seeds))?;
+
+    EscrowTaken {
+        escrow: *escrow_account.address().as_array(),
+        maker: *maker.address().as_array(),
+        taker: *taker.address().as_array(),
+        mint_a: *mint_a.address().as_array(),
+        mint_b: *mint_b.address().as_array(),
+        seed: escrow_seed,
+        amount_paid: amount_to_fill.to_le_bytes(),
+        amount_released: amount_to_release.to_le_bytes(),
+        outstanding: outstanding.to_le_bytes(),
+        timestamp: Clock::get()?.unix_timestamp.to_le_bytes(),
+    }.emit();
+
+    if !is_final_fill {
+        let escrow_state = Escrow::from_account_info(escrow_account)?;
+        let filled = escrow_state.filled().checked_add(amount_to_fill).ok_or(EscrowError::MathOverflow)?;
+        let remaining = escrow_state.remaining().checked_sub(amount_to_release).ok_or(EscrowError::MathOverflow)?;
+        escrow_state.set_filled(filled);
+        escrow_state.set_remaining(remaining);
+
+        return Ok(());
+    }
+
+    // Fees withheld in the vault would keep Token-2022 from closing it
+    if token_interface::has_transfer_fee(mint_a)? {
+        token_interface::HarvestWithheldTokensToMint {
+            mint: mint_a,
+            account: escrow_ata,
+            token_program: token_program_a,
+        }.invoke()?;
+    }
+
+    token_interface::CloseAccount {
+        account: escrow_ata,
+        destination: maker,
+        authority: escrow_account,
+        token_program: token_program_a,
+    }.invoke_signed(&[seeds])?;
+
+    // Wipe the escrow state before handing its rent back to the maker
+    escrow_account.try_borrow_mut()?.fill(0);
+    maker.set_lamports(maker.lamports() + escrow_account.lamports());
+    escrow_account.close()?;
+
+    Ok(())
+}
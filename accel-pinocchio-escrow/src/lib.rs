@@ -1,11 +1,14 @@
 #![allow(unexpected_cfgs)]
 use pinocchio::{AccountView, entrypoint, Address, ProgramResult, address::declare_id, error::ProgramError};
 
-use crate::instructions::EscrowInstrctions;
+use crate::{error::EscrowError, instructions::EscrowInstrctions};
 
+mod error;
+pub mod events;
 mod tests;
 mod state;
 mod instructions;
+mod token_interface;
 
 entrypoint!(process_instruction);
 
@@ -17,15 +20,19 @@ pub fn process_instruction(
     instruction_data: &[u8],
 ) -> ProgramResult {
 
-    assert_eq!(program_id, &ID);
+    if program_id != &ID {
+        return Err(EscrowError::InvalidProgramId.into());
+    }
 
     let (discriminator, data) = instruction_data.split_first()
         .ok_or(ProgramError::InvalidInstructionData)?;
 
     match EscrowInstrctions::try_from(discriminator)? {
         EscrowInstrctions::Make => instructions::process_make_instruction(accounts, data)?,
-        // EscrowInstrctions::MakeV2 => instructions::process_make_instruction_v2(accounts, data)?,
-        _ => return Err(ProgramError::InvalidInstructionData),
+        EscrowInstrctions::Take => instructions::process_take_instruction(accounts, data)?,
+        EscrowInstrctions::Cancel => instructions::process_cancel_instruction(accounts, data)?,
+        EscrowInstrctions::MakeV2 => instructions::process_make_instruction_v2(accounts, data)?,
+        EscrowInstrctions::TakePartial => instructions::process_take_partial_instruction(accounts, data)?,
     }
     Ok(())
 }
\ No newline at end of file
@@ -1,32 +1,134 @@
-use pinocchio::{AccountView, error::ProgramError};
+use pinocchio::{AccountView, Address, ProgramResult, error::ProgramError, sysvars::{Sysvar, rent::Rent}};
+use pinocchio_pubkey::derive_address;
 
+use crate::error::EscrowError;
+
+/// On-chain escrow state.
+///
+/// The account starts with a discriminator and a layout version. New fields are
+/// only ever appended after `bump`: bump `VERSION`, grow `LEN` and add a step to
+/// `migrate` that resizes older accounts and fills in the new fields.
 #[repr(C)]
 #[derive(Clone, Copy, Debug, Default, PartialEq)]
 pub struct Escrow {
+    discriminator: u8,
+    version: u8,
     maker: [u8; 32],
     mint_a: [u8; 32],
     mint_b: [u8; 32],
     amount_to_receive: [u8; 8],
     amount_to_give: [u8; 8],
+    seed: [u8; 8],
     pub bump: u8,
+    filled: [u8; 8],
+    remaining: [u8; 8],
 }
 
 impl Escrow {
-    pub const LEN: usize = 32 + 32 + 32 + 8 + 8;
+    pub const LEN: usize = core::mem::size_of::<Escrow>();
+
+    pub const DISCRIMINATOR: u8 = 1;
+
+    pub const VERSION: u8 = 2;
+
+    /// Size of the version 1 layout, which ended at `bump`
+    pub const V1_LEN: usize = Self::LEN - 8 - 8;
+
+    /// Size of the escrows opened before the account had a header, they hold the
+    /// fields from `maker` to `amount_to_give` and neither a seed nor a bump
+    pub const LEGACY_LEN: usize = 32 + 32 + 32 + 8 + 8;
 
     pub fn from_account_info(account_info: &AccountView) -> Result<&mut Self, ProgramError> {
+        let escrow = Self::cast(account_info)?;
+
+        if escrow.discriminator != Self::DISCRIMINATOR || escrow.version != Self::VERSION {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        Ok(escrow)
+    }
+
+    /// Writes the header of a freshly created escrow account
+    pub fn init(account_info: &AccountView) -> Result<&mut Self, ProgramError> {
+        let escrow = Self::cast(account_info)?;
+
+        if escrow.discriminator != 0 {
+            return Err(EscrowError::AlreadyInitialized.into());
+        }
+
+        escrow.discriminator = Self::DISCRIMINATOR;
+        escrow.version = Self::VERSION;
+
+        Ok(escrow)
+    }
+
+    /// Brings an escrow opened under an older layout up to the current one.
+    ///
+    /// `payer` covers the extra rent when the account grows. Legacy escrows did not
+    /// persist their bump, so the caller passes the one it verified against the PDA.
+    /// They could only be opened by `Make`, whose PDA has no seed, so theirs is zero.
+    pub fn migrate(account_info: &AccountView, payer: &AccountView, bump: u8) -> ProgramResult {
+        let mut data_len = account_info.data_len();
+        if data_len == Self::LEN {
+            return Ok(());
+        }
+
+        if data_len == Self::LEGACY_LEN {
+            account_info.resize(Self::V1_LEN)?;
+
+            let mut data = account_info.try_borrow_mut()?;
+            data.copy_within(0..Self::LEGACY_LEN, 2);
+            data[0] = Self::DISCRIMINATOR;
+            // Version 1 header, upgraded right below
+            data[1] = 1;
+            data[2 + Self::LEGACY_LEN..Self::V1_LEN - 1].fill(0);
+            data[Self::V1_LEN - 1] = bump;
+            data_len = Self::V1_LEN;
+        }
+
+        if data_len != Self::V1_LEN {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        // Version 2 tracks partial fills, nothing has been filled yet
+        account_info.resize(Self::LEN)?;
+        let escrow = Self::cast(account_info)?;
+        escrow.version = Self::VERSION;
+        escrow.filled = [0; 8];
+        escrow.remaining = escrow.amount_to_give;
+
+        let minimum_balance = Rent::get()?.try_minimum_balance(Self::LEN)?;
+        if account_info.lamports() < minimum_balance {
+            pinocchio_system::instructions::Transfer {
+                from: payer,
+                to: account_info,
+                lamports: minimum_balance - account_info.lamports(),
+            }.invoke()?;
+        }
+
+        Ok(())
+    }
+
+    // The runtime borrow flag is released on return, the caller holds the only view
+    #[allow(clippy::mut_from_ref)]
+    fn cast(account_info: &AccountView) -> Result<&mut Self, ProgramError> {
         let mut data = account_info.try_borrow_mut()?;
         if data.len() != Escrow::LEN {
             return Err(ProgramError::InvalidAccountData);
         }
 
-        if (data.as_ptr() as usize) % core::mem::align_of::<Self>() != 0 {
+        if !(data.as_ptr() as usize).is_multiple_of(core::mem::align_of::<Self>()) {
             return Err(ProgramError::InvalidAccountData);
         }
 
         Ok(unsafe { &mut *(data.as_mut_ptr() as *mut Self) })
     }
 
+    #[cfg(test)]
+    pub fn version(&self) -> u8 {
+        self.version
+    }
+
     pub fn maker(&self) -> pinocchio::Address {
         pinocchio::Address::from(self.maker)
     }
@@ -59,6 +161,7 @@ impl Escrow {
         self.amount_to_receive = amount.to_le_bytes();
     }
 
+    #[cfg(test)]
     pub fn amount_to_give(&self) -> u64 {
         u64::from_le_bytes(self.amount_to_give)
     }
@@ -66,4 +169,76 @@ impl Escrow {
     pub fn set_amount_to_give(&mut self, amount: u64) {
         self.amount_to_give = amount.to_le_bytes();
     }
+
+    /// Seed used in the PDA derivation of escrows opened with `MakeV2`, zero for `Make`
+    pub fn seed(&self) -> u64 {
+        u64::from_le_bytes(self.seed)
+    }
+
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed.to_le_bytes();
+    }
+
+    /// Mint B already paid to the maker by earlier fills
+    pub fn filled(&self) -> u64 {
+        u64::from_le_bytes(self.filled)
+    }
+
+    pub fn set_filled(&mut self, amount: u64) {
+        self.filled = amount.to_le_bytes();
+    }
+
+    /// Mint A still held in the vault for the outstanding fills
+    pub fn remaining(&self) -> u64 {
+        u64::from_le_bytes(self.remaining)
+    }
+
+    pub fn set_remaining(&mut self, amount: u64) {
+        self.remaining = amount.to_le_bytes();
+    }
+
+    /// Mint B still needed to fill the escrow completely
+    pub fn outstanding(&self) -> Result<u64, ProgramError> {
+        self.amount_to_receive()
+            .checked_sub(self.filled())
+            .ok_or(EscrowError::MathOverflow.into())
+    }
+
+    /// Mint A released for `amount` of mint B.
+    ///
+    /// Rounds down against the taker; the fill that completes the escrow gets
+    /// everything that is left, rounding dust included.
+    pub fn fill_amount(&self, amount: u64) -> Result<u64, ProgramError> {
+        let outstanding = self.outstanding()?;
+        if amount == 0 || amount > outstanding {
+            return Err(EscrowError::InvalidFillAmount.into());
+        }
+
+        if amount == outstanding {
+            return Ok(self.remaining());
+        }
+
+        let released = (amount as u128 * self.remaining() as u128) / outstanding as u128;
+        let released = u64::try_from(released).map_err(|_| ProgramError::from(EscrowError::MathOverflow))?;
+        if released == 0 {
+            return Err(EscrowError::FillTooSmall.into());
+        }
+
+        Ok(released)
+    }
+
+    /// Checks `address` against both PDA layouts and returns true when it is a `MakeV2` escrow
+    pub fn is_seeded_pda(&self, address: &Address, bump: u8) -> Result<bool, ProgramError> {
+        let v1 = derive_address(&[b"escrow".as_ref(), self.maker.as_ref(), &[bump]], None, &crate::ID.to_bytes());
+        if v1 == *address.as_array() {
+            return Ok(false);
+        }
+
+        let v2 = derive_address(&[b"escrow".as_ref(), self.maker.as_ref(), self.seed.as_ref(), &[bump]], None, &crate::ID.to_bytes());
+        if v2 == *address.as_array() {
+            return Ok(true);
+        }
+
+        Err(EscrowError::InvalidPda.into())
+    }
 }
\ No newline at end of file
@@ -0,0 +1,470 @@
+use std::path::{Path, PathBuf};
+
+use base64::{prelude::BASE64_STANDARD, Engine};
+use litesvm::{types::TransactionResult, LiteSVM};
+use litesvm_token::{CreateAssociatedTokenAccount, CreateMint, MintTo};
+
+use solana_instruction::{error::InstructionError, AccountMeta, Instruction};
+use solana_keypair::Keypair;
+use solana_message::Message;
+use solana_native_token::LAMPORTS_PER_SOL;
+use solana_pubkey::Pubkey;
+use solana_signer::Signer;
+use solana_transaction::Transaction;
+
+use escrow_client::pinocchio::{self as client, EscrowKeys};
+
+use crate::error::EscrowError;
+
+pub use client::{ata, escrow_address as escrow_pda, TOKEN_2022_PROGRAM_ID, TOKEN_PROGRAM_ID};
+
+/// Points the tests at a specific build of the program instead of the default locations
+pub const PROGRAM_SO_ENV: &str = "ESCROW_PROGRAM_SO";
+
+/// Points the transfer-hook tests at a build of `whitelist-transfer-hook`
+pub const HOOK_PROGRAM_SO_ENV: &str = "WHITELIST_HOOK_SO";
+
+/// `whitelist-transfer-hook`, which only lets whitelisted owners send tokens
+pub const HOOK_PROGRAM_ID: Pubkey = Pubkey::from_str_const("DhzyDgCmmQzVC4vEcj2zRGUyN8Mt5JynfdGLKkBcRGaX");
+
+/// Balance minted to the maker (mint A) and the taker (mint B) by `TestEscrow::new`
+pub const INITIAL_BALANCE: u64 = 1000000000;
+
+pub fn program_id() -> Pubkey {
+    client::PROGRAM_ID
+}
+
+/// Finds the built program, `$ESCROW_PROGRAM_SO` wins over the `cargo build-sbf` outputs
+pub fn program_so_path() -> PathBuf {
+    let manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let target_dir = std::env::var("CARGO_TARGET_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| manifest_dir.join("target"));
+
+    find_program_so(std::env::var(PROGRAM_SO_ENV).ok().map(PathBuf::from), &target_dir)
+        .unwrap_or_else(|err| panic!("{}", err))
+}
+
+pub fn find_program_so(override_path: Option<PathBuf>, target_dir: &Path) -> Result<PathBuf, String> {
+    let candidates = match override_path {
+        Some(path) => vec![path],
+        None => vec![
+            target_dir.join("deploy/escrow.so"),
+            target_dir.join("sbf-solana-solana/release/escrow.so"),
+        ],
+    };
+
+    candidates.iter().find(|path| path.exists()).cloned().ok_or_else(|| {
+        format!(
+            "escrow.so not found (looked in {}). Run `cargo build-sbf` in {} first, or set {} to the program path",
+            candidates.iter().map(|path| path.display().to_string()).collect::<Vec<_>>().join(", "),
+            env!("CARGO_MANIFEST_DIR"),
+            PROGRAM_SO_ENV,
+        )
+    })
+}
+
+/// Finds the built hook program, `$WHITELIST_HOOK_SO` wins over the `anchor build` output
+pub fn hook_program_so_path() -> PathBuf {
+    let default = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("../whitelist-transfer-hook/target/deploy/whitelist_transfer_hook.so");
+    let so_path = std::env::var(HOOK_PROGRAM_SO_ENV).map(PathBuf::from).unwrap_or(default);
+
+    if !so_path.exists() {
+        panic!(
+            "{} not found. Run `anchor build` in whitelist-transfer-hook first, or set {} to the program path",
+            so_path.display(),
+            HOOK_PROGRAM_SO_ENV,
+        );
+    }
+    so_path
+}
+
+pub fn setup() -> (LiteSVM, Keypair) {
+    let mut svm = LiteSVM::new();
+    let payer = Keypair::new();
+
+    svm
+        .airdrop(&payer.pubkey(), 10 * LAMPORTS_PER_SOL)
+        .expect("Airdrop failed");
+
+    let so_path = program_so_path();
+    let program_data = std::fs::read(&so_path)
+        .unwrap_or_else(|err| panic!("Failed to read program SO file {}: {}", so_path.display(), err));
+
+    svm.add_program(program_id(), &program_data).expect("Failed to add program");
+
+    (svm, payer)
+}
+
+pub fn send(svm: &mut LiteSVM, instructions: &[Instruction], signers: &[&Keypair]) -> TransactionResult {
+    let message = Message::new(instructions, Some(&signers[0].pubkey()));
+    let transaction = Transaction::new(signers, message, svm.latest_blockhash());
+    svm.send_transaction(transaction)
+}
+
+pub fn assert_instruction_error(result: TransactionResult, expected: InstructionError) {
+    let failed = result.expect_err("Transaction should have failed");
+    assert_eq!(format!("{:?}", failed.err), format!("InstructionError(0, {:?})", expected));
+}
+
+pub fn assert_escrow_error(result: TransactionResult, expected: EscrowError) {
+    assert_instruction_error(result, InstructionError::Custom(expected as u32));
+}
+
+// Deterministic filler so the fuzzed payloads are reproducible
+pub fn fuzz_bytes(len: usize, mut state: u64) -> Vec<u8> {
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect()
+}
+
+/// Decoded payloads of the `Program data: ` lines, which is where the events end up
+pub fn program_data(logs: &[String]) -> Vec<Vec<u8>> {
+    logs.iter()
+        .filter_map(|log| log.strip_prefix("Program data: "))
+        .map(|data| BASE64_STANDARD.decode(data).expect("Program data is not valid base64"))
+        .collect()
+}
+
+pub fn token_balance(svm: &LiteSVM, token_account: &Pubkey) -> u64 {
+    let account = svm.get_account(token_account).expect("Token account not found");
+    // The amount lives right after the mint and owner in the token account layout
+    u64::from_le_bytes(account.data[64..72].try_into().unwrap())
+}
+
+pub fn is_closed(svm: &LiteSVM, account: &Pubkey) -> bool {
+    svm.get_account(account).is_none_or(|acc| acc.lamports == 0)
+}
+
+/// Creates a 6 decimals mint owned by `token_program`, Token-2022 mints can carry a
+/// transfer fee of (basis points, maximum fee)
+pub fn create_mint(svm: &mut LiteSVM, payer: &Keypair, token_program: &Pubkey, transfer_fee: Option<(u16, u64)>) -> Pubkey {
+    if *token_program == TOKEN_PROGRAM_ID {
+        assert!(transfer_fee.is_none(), "Transfer fees need a Token-2022 mint");
+        return CreateMint::new(svm, payer)
+            .decimals(6)
+            .authority(&payer.pubkey())
+            .send()
+            .unwrap();
+    }
+
+    create_token_2022_mint(svm, payer, transfer_fee)
+}
+
+fn create_token_2022_mint(svm: &mut LiteSVM, payer: &Keypair, transfer_fee: Option<(u16, u64)>) -> Pubkey {
+    create_token_2022_mint_with(svm, payer, transfer_fee, None)
+}
+
+/// Creates a 6 decimals Token-2022 mint whose transfers go through `HOOK_PROGRAM_ID`
+pub fn create_hook_mint(svm: &mut LiteSVM, payer: &Keypair) -> Pubkey {
+    let mint = create_token_2022_mint_with(svm, payer, None, Some(HOOK_PROGRAM_ID));
+
+    // initialize_transfer_hook writes the mint's extra account metas
+    let ix = Instruction {
+        program_id: HOOK_PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new(payer.pubkey(), true),
+            AccountMeta::new(hook_extra_account_metas(&mint), false),
+            AccountMeta::new_readonly(mint, false),
+            AccountMeta::new_readonly(solana_sdk_ids::system_program::ID, false),
+        ],
+        data: vec![22, 166, 19, 247, 193, 62, 186, 143],
+    };
+    send(svm, &[ix], &[payer]).unwrap();
+
+    mint
+}
+
+/// Lets `owner` send tokens of every hook mint
+pub fn whitelist(svm: &mut LiteSVM, payer: &Keypair, owner: &Pubkey) {
+    // add_to_whitelist(owner)
+    let ix = Instruction {
+        program_id: HOOK_PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new(payer.pubkey(), true),
+            AccountMeta::new(hook_whitelist(owner), false),
+            AccountMeta::new_readonly(solana_sdk_ids::system_program::ID, false),
+        ],
+        data: [vec![157, 211, 52, 54, 144, 81, 5, 55], owner.to_bytes().to_vec()].concat(),
+    };
+    send(svm, &[ix], &[payer]).unwrap();
+}
+
+pub fn hook_extra_account_metas(mint: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"extra-account-metas", mint.as_ref()], &HOOK_PROGRAM_ID).0
+}
+
+pub fn hook_whitelist(owner: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"whitelist", owner.as_ref()], &HOOK_PROGRAM_ID).0
+}
+
+/// What Token-2022 needs to run the hook of `mint` when `owner` sends it
+pub fn hook_accounts(mint: &Pubkey, owner: &Pubkey) -> Vec<AccountMeta> {
+    vec![
+        AccountMeta::new_readonly(hook_whitelist(owner), false),
+        AccountMeta::new_readonly(HOOK_PROGRAM_ID, false),
+        AccountMeta::new_readonly(hook_extra_account_metas(mint), false),
+    ]
+}
+
+fn create_token_2022_mint_with(svm: &mut LiteSVM, payer: &Keypair, transfer_fee: Option<(u16, u64)>, transfer_hook: Option<Pubkey>) -> Pubkey {
+    use spl_token_2022::{extension::ExtensionType, state::Mint};
+
+    let mint = Keypair::new();
+    let mut extensions: Vec<ExtensionType> = transfer_fee
+        .map(|_| vec![ExtensionType::TransferFeeConfig])
+        .unwrap_or_default();
+    if transfer_hook.is_some() {
+        extensions.push(ExtensionType::TransferHook);
+    }
+    let space = ExtensionType::try_calculate_account_len::<Mint>(&extensions).unwrap();
+    let lamports = svm.minimum_balance_for_rent_exemption(space);
+
+    // System program CreateAccount, laid out by hand to avoid pulling in the system interface
+    let create_account_ix = Instruction {
+        program_id: solana_sdk_ids::system_program::ID,
+        accounts: vec![
+            AccountMeta::new(payer.pubkey(), true),
+            AccountMeta::new(mint.pubkey(), true),
+        ],
+        data: [
+            0u32.to_le_bytes().to_vec(),
+            lamports.to_le_bytes().to_vec(),
+            (space as u64).to_le_bytes().to_vec(),
+            TOKEN_2022_PROGRAM_ID.to_bytes().to_vec(),
+        ].concat(),
+    };
+
+    let mut instructions = vec![create_account_ix];
+    if let Some((basis_points, maximum_fee)) = transfer_fee {
+        instructions.push(
+            spl_token_2022::extension::transfer_fee::instruction::initialize_transfer_fee_config(
+                &TOKEN_2022_PROGRAM_ID,
+                &mint.pubkey(),
+                Some(&payer.pubkey()),
+                Some(&payer.pubkey()),
+                basis_points,
+                maximum_fee,
+            ).unwrap(),
+        );
+    }
+    if let Some(program_id) = transfer_hook {
+        instructions.push(
+            spl_token_2022::extension::transfer_hook::instruction::initialize(
+                &TOKEN_2022_PROGRAM_ID,
+                &mint.pubkey(),
+                Some(payer.pubkey()),
+                Some(program_id),
+            ).unwrap(),
+        );
+    }
+    instructions.push(
+        spl_token_2022::instruction::initialize_mint2(&TOKEN_2022_PROGRAM_ID, &mint.pubkey(), &payer.pubkey(), None, 6).unwrap(),
+    );
+
+    send(svm, &instructions, &[payer, &mint]).unwrap();
+
+    mint.pubkey()
+}
+
+/// Creates `owner`'s ATA for `mint` and mints `amount` into it, the mint authority is `payer`
+pub fn create_funded_ata(svm: &mut LiteSVM, payer: &Keypair, mint: &Pubkey, owner: &Pubkey, token_program: &Pubkey, amount: u64) -> Pubkey {
+    let ata = CreateAssociatedTokenAccount::new(svm, payer, mint)
+        .owner(owner)
+        .token_program_id(token_program)
+        .send()
+        .unwrap();
+
+    if amount > 0 {
+        MintTo::new(svm, payer, mint, &ata, amount)
+            .token_program_id(token_program)
+            .send()
+            .unwrap();
+    }
+
+    ata
+}
+
+/// A maker holding mint A, a taker holding mint B and the addresses of one escrow between them
+pub struct TestEscrow {
+    pub svm: LiteSVM,
+    pub maker: Keypair,
+    pub taker: Keypair,
+    pub token_program: Pubkey,
+    pub token_program_b: Pubkey,
+    pub mint_a: Pubkey,
+    pub mint_b: Pubkey,
+    pub maker_ata_a: Pubkey,
+    pub maker_ata_b: Pubkey,
+    pub taker_ata_a: Pubkey,
+    pub taker_ata_b: Pubkey,
+    pub seed: Option<u64>,
+    pub escrow: (Pubkey, u8),
+    pub vault: Pubkey,
+    /// Hook accounts for the next instruction, see `EscrowKeys::with_extra_accounts`
+    pub extra_accounts: (Vec<AccountMeta>, Vec<AccountMeta>),
+}
+
+impl TestEscrow {
+    pub fn new() -> Self {
+        Self::with_token_program(TOKEN_PROGRAM_ID, None)
+    }
+
+    pub fn with_token_program(token_program: Pubkey, transfer_fee: Option<(u16, u64)>) -> Self {
+        let (mut svm, maker) = setup();
+
+        let mint_a = create_mint(&mut svm, &maker, &token_program, transfer_fee);
+        let mint_b = create_mint(&mut svm, &maker, &token_program, transfer_fee);
+
+        Self::with_mints(svm, maker, (mint_a, token_program), (mint_b, token_program))
+    }
+
+    /// Mints run through the whitelist hook where `hook_a`/`hook_b` is set and are
+    /// legacy SPL mints otherwise. The maker, the taker and the unseeded escrow are
+    /// whitelisted.
+    pub fn with_hook_mints(hook_a: bool, hook_b: bool) -> Self {
+        let (mut svm, maker) = setup();
+
+        let program_data = std::fs::read(hook_program_so_path()).unwrap();
+        svm.add_program(HOOK_PROGRAM_ID, &program_data).expect("Failed to add hook program");
+
+        let mut mint = |hook: bool| {
+            if hook {
+                (create_hook_mint(&mut svm, &maker), TOKEN_2022_PROGRAM_ID)
+            } else {
+                (create_mint(&mut svm, &maker, &TOKEN_PROGRAM_ID, None), TOKEN_PROGRAM_ID)
+            }
+        };
+        let (mint_a, mint_b) = (mint(hook_a), mint(hook_b));
+
+        let mut test = Self::with_mints(svm, maker, mint_a, mint_b);
+        for owner in [test.maker.pubkey(), test.taker.pubkey(), test.escrow.0] {
+            whitelist(&mut test.svm, &test.maker, &owner);
+        }
+
+        test
+    }
+
+    fn with_mints(mut svm: LiteSVM, maker: Keypair, (mint_a, token_program): (Pubkey, Pubkey), (mint_b, token_program_b): (Pubkey, Pubkey)) -> Self {
+        let taker = Keypair::new();
+        svm
+            .airdrop(&taker.pubkey(), 10 * LAMPORTS_PER_SOL)
+            .expect("Airdrop failed");
+
+        let maker_ata_a = create_funded_ata(&mut svm, &maker, &mint_a, &maker.pubkey(), &token_program, INITIAL_BALANCE);
+        let taker_ata_b = create_funded_ata(&mut svm, &maker, &mint_b, &taker.pubkey(), &token_program_b, INITIAL_BALANCE);
+
+        let escrow = escrow_pda(&maker.pubkey(), None);
+
+        Self {
+            maker_ata_b: ata(&maker.pubkey(), &mint_b, &token_program_b),
+            taker_ata_a: ata(&taker.pubkey(), &mint_a, &token_program),
+            vault: ata(&escrow.0, &mint_a, &token_program),
+            svm,
+            maker,
+            taker,
+            token_program,
+            token_program_b,
+            mint_a,
+            mint_b,
+            maker_ata_a,
+            taker_ata_b,
+            seed: None,
+            escrow,
+            extra_accounts: (Vec::new(), Vec::new()),
+        }
+    }
+
+    /// Points the fixture at the `MakeV2` escrow for `seed`, or back at the `Make` one for `None`
+    pub fn use_seed(&mut self, seed: Option<u64>) {
+        self.seed = seed;
+        self.escrow = escrow_pda(&self.maker.pubkey(), seed);
+        self.vault = ata(&self.escrow.0, &self.mint_a, &self.token_program);
+    }
+
+    /// Client view of the escrow the fixture currently points at
+    pub fn keys(&self) -> EscrowKeys {
+        EscrowKeys::new(self.maker.pubkey(), self.mint_a, self.mint_b, self.token_program, self.seed)
+            .with_token_program_b(self.token_program_b)
+            .with_extra_accounts(self.extra_accounts.0.clone(), self.extra_accounts.1.clone())
+    }
+
+    /// `Make` or `MakeV2` depending on the current seed
+    pub fn make_ix(&self, amount_to_receive: u64, amount_to_give: u64) -> Instruction {
+        self.keys().make_ix(amount_to_receive, amount_to_give)
+    }
+
+    pub fn take_ix(&self) -> Instruction {
+        self.keys().take_ix(&self.taker.pubkey())
+    }
+
+    /// Take that only pays `amount` of mint B and releases the matching share of the vault
+    pub fn take_partial_ix(&self, amount: u64) -> Instruction {
+        self.keys().take_partial_ix(&self.taker.pubkey(), amount)
+    }
+
+    /// Cancel signed by `signer`, which is the maker unless a test wants it rejected
+    pub fn cancel_ix(&self, signer: &Pubkey) -> Instruction {
+        self.keys().cancel_ix(signer)
+    }
+
+    pub fn make(&mut self, amount_to_receive: u64, amount_to_give: u64) -> TransactionResult {
+        let ix = self.make_ix(amount_to_receive, amount_to_give);
+        send(&mut self.svm, &[ix], &[&self.maker])
+    }
+
+    pub fn take(&mut self) -> TransactionResult {
+        let ix = self.take_ix();
+        send(&mut self.svm, &[ix], &[&self.taker])
+    }
+
+    pub fn take_partial(&mut self, amount: u64) -> TransactionResult {
+        let ix = self.take_partial_ix(amount);
+        send(&mut self.svm, &[ix], &[&self.taker])
+    }
+
+    pub fn cancel(&mut self) -> TransactionResult {
+        let ix = self.cancel_ix(&self.maker.pubkey());
+        send(&mut self.svm, &[ix], &[&self.maker])
+    }
+
+    pub fn balance(&self, token_account: &Pubkey) -> u64 {
+        token_balance(&self.svm, token_account)
+    }
+
+    pub fn lamports(&self, account: &Pubkey) -> u64 {
+        self.svm.get_account(account).map_or(0, |acc| acc.lamports)
+    }
+
+    pub fn is_closed(&self, account: &Pubkey) -> bool {
+        is_closed(&self.svm, account)
+    }
+
+    /// Rewrites the escrow the way the first `Make` stored it: no header and no seed,
+    /// and a bump that never made it into the 112 bytes of the account
+    pub fn write_legacy_escrow(&mut self, amount_to_receive: u64, amount_to_give: u64) {
+        let legacy = [
+            self.maker.pubkey().as_ref(),
+            self.mint_a.as_ref(),
+            self.mint_b.as_ref(),
+            &amount_to_receive.to_le_bytes(),
+            &amount_to_give.to_le_bytes(),
+        ].concat();
+        assert_eq!(legacy.len(), crate::state::Escrow::LEGACY_LEN);
+
+        let mut escrow_account = self.svm.get_account(&self.escrow.0).expect("Escrow account not found");
+        escrow_account.data = legacy;
+        self.svm.set_account(self.escrow.0, escrow_account).unwrap();
+    }
+
+    pub fn escrow_state(&self) -> client::Escrow {
+        let account = self.svm.get_account(&self.escrow.0).expect("Escrow account not found");
+        client::Escrow::decode(&account.data).expect("Not an escrow at the current version")
+    }
+}
@@ -1,139 +1,644 @@
+// LiteSVM hands back the whole transaction metadata as the error
 #[cfg(test)]
-mod tests {
+#[allow(clippy::result_large_err)]
+mod harness;
 
-    use std::path::PathBuf;
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+mod tests {
 
-    use litesvm::LiteSVM;
-    use litesvm_token::{spl_token::{self}, CreateAssociatedTokenAccount, CreateMint, MintTo};
-    
-    use solana_instruction::{AccountMeta, Instruction};
+    use solana_clock::Clock;
+    use solana_instruction::{error::InstructionError, AccountMeta, Instruction};
     use solana_keypair::Keypair;
-    use solana_message::Message;
     use solana_native_token::LAMPORTS_PER_SOL;
-    use solana_pubkey::Pubkey;
     use solana_signer::Signer;
-    use solana_transaction::Transaction;
 
-    const PROGRAM_ID: &str = "4ibrEMW5F6hKnkW4jVedswYv6H6VtwPN6ar6dvXDN1nT";
-    const TOKEN_PROGRAM_ID: Pubkey = spl_token::ID;
-    const ASSOCIATED_TOKEN_PROGRAM_ID: &str = "ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL";
-    
-    fn program_id() -> Pubkey {
-        Pubkey::from(crate::ID)
-    }
+    use super::harness::*;
+    use crate::{error::EscrowError, state::Escrow};
 
-    fn setup() -> (LiteSVM, Keypair) {
+    #[test]
+    pub fn test_make_instruction() {
+        let mut test = TestEscrow::new();
 
-        let mut svm = LiteSVM::new();
-        let payer = Keypair::new();
+        assert_eq!(program_id(), crate::ID);
 
-        svm
-            .airdrop(&payer.pubkey(), 10 * LAMPORTS_PER_SOL)
-            .expect("Airdrop failed");
+        let amount_to_receive: u64 = 100000000; // 100 tokens with 6 decimal places
+        let amount_to_give: u64 = 500000000;    // 500 tokens with 6 decimal places
 
-        // Load program SO file
-        println!("The path is!! {}", env!("CARGO_MANIFEST_DIR"));
-        let so_path = PathBuf::from("/Users/andrecorreia/Documents/Solana/pinocchio-escrow-2025/escrow/target/sbf-solana-solana/release/escrow.so");
-    
-        let program_data = std::fs::read(so_path).expect("Failed to read program SO file");
-    
-        svm.add_program(program_id(), &program_data).expect("Failed to add program");
+        test.make(amount_to_receive, amount_to_give).unwrap();
 
-        (svm, payer)
-        
+        assert_eq!(test.balance(&test.vault), amount_to_give);
+        assert_eq!(test.balance(&test.maker_ata_a), INITIAL_BALANCE - amount_to_give);
     }
 
     #[test]
-    pub fn test_make_instruction() {
-        let (mut svm, payer) = setup();
-
-        let program_id = program_id();
-
-        assert_eq!(program_id.to_string(), PROGRAM_ID);
-
-        let mint_a = CreateMint::new(&mut svm, &payer)
-            .decimals(6)
-            .authority(&payer.pubkey())
-            .send()
-            .unwrap();
-        println!("Mint A: {}", mint_a);
-
-        let mint_b = CreateMint::new(&mut svm, &payer)
-            .decimals(6)
-            .authority(&payer.pubkey())
-            .send()
-            .unwrap();
-        println!("Mint B: {}", mint_b);
-
-        // Create the maker's associated token account for Mint A
-        let maker_ata_a = CreateAssociatedTokenAccount::new(&mut svm, &payer, &mint_a)
-            .owner(&payer.pubkey()).send().unwrap();
-        println!("Maker ATA A: {}\n", maker_ata_a);
-
-        // Derive the PDA for the escrow account using the maker's public key and a seed value
-        let escrow = Pubkey::find_program_address(
-            &[b"escrow".as_ref(), payer.pubkey().as_ref()],
-            &PROGRAM_ID.parse().unwrap(),
+    pub fn test_take_instruction() {
+        let mut test = TestEscrow::new();
+
+        let amount_to_receive: u64 = 100000000;
+        let amount_to_give: u64 = 500000000;
+        test.make(amount_to_receive, amount_to_give).unwrap();
+
+        let maker_lamports_before = test.lamports(&test.maker.pubkey());
+        let escrow_rent = test.lamports(&test.escrow.0);
+        let vault_rent = test.lamports(&test.vault);
+
+        // The maker's and taker's ATAs are created on the fly
+        test.take().unwrap();
+
+        assert_eq!(test.balance(&test.taker_ata_a), amount_to_give);
+        assert_eq!(test.balance(&test.maker_ata_b), amount_to_receive);
+        assert_eq!(test.balance(&test.taker_ata_b), INITIAL_BALANCE - amount_to_receive);
+
+        // Both the vault and the escrow are closed and their rent goes back to the maker
+        assert!(test.is_closed(&test.vault));
+        assert!(test.svm.get_account(&test.escrow.0).is_none_or(|acc| acc.lamports == 0 && acc.data.iter().all(|b| *b == 0)));
+        assert_eq!(
+            test.lamports(&test.maker.pubkey()),
+            maker_lamports_before + escrow_rent + vault_rent
         );
-        println!("Escrow PDA: {}\n", escrow.0);
+    }
+
+    #[test]
+    pub fn test_cancel_instruction() {
+        let mut test = TestEscrow::new();
 
-        // Derive the PDA for the vault associated token account using the escrow PDA and Mint A
-        let vault = spl_associated_token_account::get_associated_token_address(
-            &escrow.0,  // owner will be the escrow PDA
-            &mint_a     // mint
+        let amount_to_give: u64 = 500000000;
+        test.make(100000000, amount_to_give).unwrap();
+        assert_eq!(test.balance(&test.maker_ata_a), INITIAL_BALANCE - amount_to_give);
+
+        let maker_lamports_before = test.lamports(&test.maker.pubkey());
+        let escrow_rent = test.lamports(&test.escrow.0);
+        let vault_rent = test.lamports(&test.vault);
+
+        let tx = test.cancel().unwrap();
+
+        // The whole deposit is back with the maker and both accounts are closed
+        assert_eq!(test.balance(&test.maker_ata_a), INITIAL_BALANCE);
+        assert!(test.is_closed(&test.vault));
+        assert!(test.svm.get_account(&test.escrow.0).is_none_or(|acc| acc.lamports == 0 && acc.data.iter().all(|b| *b == 0)));
+
+        // The maker paid the fee for the cancel transaction
+        assert_eq!(
+            test.lamports(&test.maker.pubkey()),
+            maker_lamports_before + escrow_rent + vault_rent - tx.fee
         );
-        println!("Vault PDA: {}\n", vault);
+    }
+
+    #[test]
+    pub fn test_cancel_fails_for_non_maker() {
+        let mut test = TestEscrow::new();
 
-        // Define program IDs for associated token program, token program, and system program
-        let associated_token_program = ASSOCIATED_TOKEN_PROGRAM_ID.parse::<Pubkey>().unwrap();
-        let token_program = TOKEN_PROGRAM_ID;
-        let system_program = solana_sdk_ids::system_program::ID;
+        test.make(100000000, 500000000).unwrap();
 
-        // Mint 1,000 tokens (with 6 decimal places) of Mint A to the maker's associated token account
-        MintTo::new(&mut svm, &payer, &mint_a, &maker_ata_a, 1000000000)
-            .send()
-            .unwrap();
+        let attacker = Keypair::new();
+        test.svm
+            .airdrop(&attacker.pubkey(), 10 * LAMPORTS_PER_SOL)
+            .expect("Airdrop failed");
 
-        let amount_to_receive: u64 = 100000000; // 100 tokens with 6 decimal places
-        let amount_to_give: u64 = 500000000;    // 500 tokens with 6 decimal places
-        let bump: u8 = escrow.1;
-
-        println!("Bump: {}", bump);
-
-        // Create the "Make" instruction to deposit tokens into the escrow
-        let make_data = [
-            vec![0u8],              // Discriminator for "Make" instruction
-            bump.to_le_bytes().to_vec(),
-            amount_to_receive.to_le_bytes().to_vec(),
-            amount_to_give.to_le_bytes().to_vec(),
-        ].concat();
-        let make_ix = Instruction {
-            program_id: program_id,
-            accounts: vec![
-                AccountMeta::new(payer.pubkey(), true),
-                AccountMeta::new(mint_a, false),
-                AccountMeta::new(mint_b, false),
-                AccountMeta::new(escrow.0, false),
-                AccountMeta::new(maker_ata_a, false),
-                AccountMeta::new(vault, false),
-                AccountMeta::new(system_program, false),
-                AccountMeta::new(token_program, false),
-                AccountMeta::new(associated_token_program, false),
-            ],
-            data: make_data,
+        // The attacker signs as the "maker" but does not match the maker stored in the escrow
+        let cancel_ix = test.cancel_ix(&attacker.pubkey());
+        let result = send(&mut test.svm, &[cancel_ix], &[&attacker]);
+
+        assert_escrow_error(result, EscrowError::InvalidMaker);
+
+        // Nothing moved
+        assert_eq!(test.balance(&test.vault), 500000000);
+        assert!(!test.is_closed(&test.escrow.0));
+    }
+
+    #[test]
+    pub fn test_make_with_wrong_bump_fails_with_invalid_pda() {
+        let mut test = TestEscrow::new();
+
+        let mut make_ix = test.make_ix(100000000, 500000000);
+        make_ix.data[1] = test.escrow.1.wrapping_sub(1);
+
+        let result = send(&mut test.svm, &[make_ix], &[&test.maker]);
+        assert_escrow_error(result, EscrowError::InvalidPda);
+    }
+
+    #[test]
+    pub fn test_make_twice_fails_with_already_initialized() {
+        let mut test = TestEscrow::new();
+
+        test.make(100000000, 500000000).unwrap();
+        assert_escrow_error(test.make(100000000, 400000000), EscrowError::AlreadyInitialized);
+    }
+
+    #[test]
+    pub fn test_make_without_enough_tokens_fails_with_insufficient_funds() {
+        let mut test = TestEscrow::new();
+
+        assert_escrow_error(test.make(100000000, INITIAL_BALANCE + 1), EscrowError::InsufficientFunds);
+    }
+
+    #[test]
+    pub fn test_take_with_wrong_mint_fails_with_mint_mismatch() {
+        let mut test = TestEscrow::new();
+
+        test.make(100000000, 500000000).unwrap();
+
+        let other_mint = create_mint(&mut test.svm, &test.maker, &TOKEN_PROGRAM_ID, None);
+        let mut take_ix = test.take_ix();
+        take_ix.accounts[3] = AccountMeta::new(other_mint, false);
+
+        let result = send(&mut test.svm, &[take_ix], &[&test.taker]);
+        assert_escrow_error(result, EscrowError::MintMismatch);
+    }
+
+    #[test]
+    pub fn test_take_with_wrong_maker_fails_with_invalid_maker() {
+        let mut test = TestEscrow::new();
+
+        test.make(100000000, 500000000).unwrap();
+
+        let mut take_ix = test.take_ix();
+        take_ix.accounts[1] = AccountMeta::new(Keypair::new().pubkey(), false);
+
+        let result = send(&mut test.svm, &[take_ix], &[&test.taker]);
+        assert_escrow_error(result, EscrowError::InvalidMaker);
+    }
+
+    #[test]
+    pub fn test_take_without_enough_tokens_fails_with_insufficient_funds() {
+        let mut test = TestEscrow::new();
+
+        test.make(INITIAL_BALANCE + 1, 500000000).unwrap();
+
+        assert_escrow_error(test.take(), EscrowError::InsufficientFunds);
+        assert_eq!(test.balance(&test.vault), 500000000);
+    }
+
+    #[test]
+    pub fn test_wrong_program_id_fails_with_invalid_program_id() {
+        let mut test = TestEscrow::new();
+
+        // Deploy the same program under an address it was not built for
+        let other_program = Keypair::new().pubkey();
+        let program_data = std::fs::read(program_so_path()).unwrap();
+        test.svm.add_program(other_program, &program_data).expect("Failed to add program");
+
+        let mut make_ix = test.make_ix(100000000, 500000000);
+        make_ix.program_id = other_program;
+
+        let result = send(&mut test.svm, &[make_ix], &[&test.maker]);
+        assert_escrow_error(result, EscrowError::InvalidProgramId);
+    }
+
+    #[test]
+    pub fn test_make_v2_many_escrows_per_maker() {
+        let mut test = TestEscrow::new();
+
+        let amount_to_receive: u64 = 10000000;
+        let amount_to_give: u64 = 50000000;
+
+        // The same maker opens three escrows, each one under its own seed
+        for seed in [1u64, 2u64, 3u64] {
+            test.use_seed(Some(seed));
+            test.make(amount_to_receive, amount_to_give).unwrap();
+            assert_eq!(test.balance(&test.vault), amount_to_give);
+        }
+        assert_eq!(test.balance(&test.maker_ata_a), INITIAL_BALANCE - 3 * amount_to_give);
+
+        // Take the first escrow
+        test.use_seed(Some(1));
+        test.take().unwrap();
+
+        assert_eq!(test.balance(&test.taker_ata_a), amount_to_give);
+        assert_eq!(test.balance(&test.maker_ata_b), amount_to_receive);
+        assert!(test.is_closed(&test.escrow.0));
+
+        // Cancel the second escrow
+        test.use_seed(Some(2));
+        test.cancel().unwrap();
+
+        assert_eq!(test.balance(&test.maker_ata_a), INITIAL_BALANCE - 2 * amount_to_give);
+        assert!(test.is_closed(&test.escrow.0));
+
+        // The third escrow is untouched
+        test.use_seed(Some(3));
+        assert_eq!(test.balance(&test.vault), amount_to_give);
+        assert!(!test.is_closed(&test.escrow.0));
+    }
+
+    #[test]
+    pub fn test_make_v2_stores_bump() {
+        let mut test = TestEscrow::new();
+
+        test.use_seed(Some(9));
+        test.make(100000000, 500000000).unwrap();
+
+        // The bump is the last field of the version 1 layout and sits inside the account
+        let escrow_account = test.svm.get_account(&test.escrow.0).unwrap();
+        assert_eq!(Escrow::LEN, core::mem::size_of::<Escrow>());
+        assert_eq!(escrow_account.data.len(), Escrow::LEN);
+        assert_eq!(escrow_account.data[Escrow::V1_LEN - 1], test.escrow.1);
+
+        let escrow = test.escrow_state();
+        assert_eq!(escrow.seed, 9);
+        assert_eq!(escrow.bump, test.escrow.1);
+    }
+
+    #[test]
+    pub fn test_malformed_instruction_data_is_rejected() {
+        use crate::instructions::{CancelArgs, MakeArgs, MakeV2Args, TakeArgs, TakePartialArgs};
+
+        let mut test = TestEscrow::new();
+
+        // A live escrow so Take and Cancel would otherwise have everything they need
+        test.make(100000000, 500000000).unwrap();
+        let (escrow, vault) = (test.escrow, test.vault);
+
+        let take_ix = test.take_ix();
+        let take_partial_ix = test.take_partial_ix(1);
+        let cancel_ix = test.cancel_ix(&test.maker.pubkey());
+        test.use_seed(Some(7));
+        let make_ix = test.make_ix(0, 0);
+
+        let cases = [
+            (0u8, MakeArgs::LEN, &make_ix),
+            (1u8, TakeArgs::LEN, &take_ix),
+            (2u8, CancelArgs::LEN, &cancel_ix),
+            (3u8, MakeV2Args::LEN, &make_ix),
+            (4u8, TakePartialArgs::LEN, &take_partial_ix),
+        ];
+
+        for (discriminator, len, ix) in cases {
+            // Every truncated payload and a handful of oversized ones
+            for payload_len in (0..len).chain(len + 1..len + 9) {
+                let ix = Instruction {
+                    data: [
+                        vec![discriminator],
+                        fuzz_bytes(payload_len, 0x9e3779b97f4a7c15 ^ ((discriminator as u64) << 32 | payload_len as u64)),
+                    ].concat(),
+                    ..ix.clone()
+                };
+
+                let signer = if discriminator == 1 || discriminator == 4 { &test.taker } else { &test.maker };
+                assert_instruction_error(send(&mut test.svm, &[ix], &[signer]), InstructionError::InvalidInstructionData);
+            }
+        }
+
+        // An unknown discriminator is rejected as well
+        let ix = Instruction {
+            program_id: program_id(),
+            accounts: vec![AccountMeta::new(test.maker.pubkey(), true)],
+            data: vec![5u8],
         };
+        assert_instruction_error(send(&mut test.svm, &[ix], &[&test.maker]), InstructionError::InvalidInstructionData);
+
+        // None of the rejected payloads touched the open escrow
+        assert_eq!(test.balance(&vault), 500000000);
+        assert!(!test.is_closed(&escrow.0));
+        assert!(test.svm.get_account(&test.escrow.0).is_none());
+    }
+
+    #[test]
+    pub fn test_make_writes_versioned_layout() {
+        let mut test = TestEscrow::new();
 
-        // Create and send the transaction containing the "Make" instruction
-        let message = Message::new(&[make_ix], Some(&payer.pubkey()));
-        let recent_blockhash = svm.latest_blockhash();
+        test.make(100000000, 500000000).unwrap();
+
+        let escrow_account = test.svm.get_account(&test.escrow.0).unwrap();
+        assert_eq!(escrow_account.data.len(), Escrow::LEN);
+        assert_eq!(escrow_account.data[0], Escrow::DISCRIMINATOR);
+        assert_eq!(escrow_account.data[1], Escrow::VERSION);
+        assert_eq!(&escrow_account.data[2..34], test.maker.pubkey().as_ref());
+        assert_eq!(&escrow_account.data[34..66], test.mint_a.as_ref());
+
+        // The bump closes the version 1 fields, the fill progress follows it
+        assert_eq!(escrow_account.data[Escrow::V1_LEN - 1], test.escrow.1);
+        assert_eq!(&escrow_account.data[Escrow::V1_LEN..Escrow::V1_LEN + 8], &0u64.to_le_bytes());
+        assert_eq!(&escrow_account.data[Escrow::V1_LEN + 8..], &500000000u64.to_le_bytes());
+
+        // The client reads the same layout back
+        let escrow = test.escrow_state();
+        assert_eq!(escrow.maker, test.maker.pubkey());
+        assert_eq!(escrow.mint_a, test.mint_a);
+        assert_eq!(escrow.mint_b, test.mint_b);
+        assert_eq!(escrow.amount_to_receive, 100000000);
+        assert_eq!(escrow.amount_to_give, 500000000);
+        assert_eq!(escrow.bump, test.escrow.1);
+        assert_eq!((escrow.filled, escrow.remaining), (0, 500000000));
+    }
+
+    #[test]
+    pub fn test_client_layout_matches_the_program() {
+        use escrow_client::pinocchio::Escrow as ClientEscrow;
+
+        // The client decodes escrows with its own copy of the layout
+        assert_eq!(ClientEscrow::LEN, Escrow::LEN);
+        assert_eq!(ClientEscrow::DISCRIMINATOR, Escrow::DISCRIMINATOR);
+        assert_eq!(ClientEscrow::VERSION, Escrow::VERSION);
+
+        let mut test = TestEscrow::new();
+        test.use_seed(Some(3));
+        test.make(100000000, 500000000).unwrap();
+        test.take_partial(40000000).unwrap();
+
+        // Every field sits at the same offset on both sides
+        let escrow_account = test.svm.get_account(&test.escrow.0).unwrap();
+        let escrow = unsafe { core::ptr::read_unaligned(escrow_account.data.as_ptr() as *const Escrow) };
+        let decoded = test.escrow_state();
+        assert_eq!(decoded.version, escrow.version());
+        assert_eq!(decoded.maker.as_ref(), escrow.maker().as_ref());
+        assert_eq!(decoded.mint_a.as_ref(), escrow.mint_a().as_ref());
+        assert_eq!(decoded.mint_b.as_ref(), escrow.mint_b().as_ref());
+        assert_eq!(decoded.amount_to_receive, escrow.amount_to_receive());
+        assert_eq!(decoded.amount_to_give, escrow.amount_to_give());
+        assert_eq!(decoded.seed, escrow.seed());
+        assert_eq!(decoded.bump, escrow.bump);
+        assert_eq!((decoded.filled, decoded.remaining), (escrow.filled(), escrow.remaining()));
+        assert_eq!((decoded.filled, decoded.remaining), (40000000, 300000000));
+    }
+
+    #[test]
+    pub fn test_cancel_migrates_legacy_escrow() {
+        let mut test = TestEscrow::new();
+
+        test.make(100000000, 500000000).unwrap();
+        test.write_legacy_escrow(100000000, 500000000);
+
+        test.cancel().unwrap();
+
+        assert_eq!(test.balance(&test.maker_ata_a), INITIAL_BALANCE);
+        assert!(test.is_closed(&test.escrow.0));
+    }
+
+    #[test]
+    pub fn test_take_partial_migrates_legacy_escrow() {
+        let mut test = TestEscrow::new();
+
+        test.make(100000000, 500000000).unwrap();
+        test.write_legacy_escrow(100000000, 500000000);
+
+        test.take_partial(10000000).unwrap();
+
+        // Legacy escrows come from the unseeded Make, the bump is the one that was verified
+        let escrow = test.escrow_state();
+        assert_eq!(escrow.maker, test.maker.pubkey());
+        assert_eq!((escrow.mint_a, escrow.mint_b), (test.mint_a, test.mint_b));
+        assert_eq!((escrow.amount_to_receive, escrow.amount_to_give), (100000000, 500000000));
+        assert_eq!((escrow.seed, escrow.bump), (0, test.escrow.1));
+        assert_eq!((escrow.filled, escrow.remaining), (10000000, 450000000));
+        assert_eq!(test.balance(&test.taker_ata_a), 50000000);
+    }
+
+    #[test]
+    pub fn test_take_partial_migrates_v1_escrow() {
+        let mut test = TestEscrow::new();
+
+        test.make(30, 100).unwrap();
+
+        // Drop the fill progress to get the version 1 layout back
+        let mut escrow_account = test.svm.get_account(&test.escrow.0).unwrap();
+        escrow_account.data.truncate(Escrow::V1_LEN);
+        escrow_account.data[1] = 1;
+        test.svm.set_account(test.escrow.0, escrow_account).unwrap();
+
+        test.take_partial(10).unwrap();
+
+        let escrow_account = test.svm.get_account(&test.escrow.0).unwrap();
+        assert_eq!(escrow_account.data.len(), Escrow::LEN);
+        assert_eq!(escrow_account.data[1], Escrow::VERSION);
+        assert_eq!(&escrow_account.data[Escrow::V1_LEN..Escrow::V1_LEN + 8], &10u64.to_le_bytes());
+        assert_eq!(&escrow_account.data[Escrow::V1_LEN + 8..], &67u64.to_le_bytes());
+        assert_eq!((test.escrow_state().filled, test.escrow_state().remaining), (10, 67));
+    }
+
+    #[test]
+    pub fn test_unknown_escrow_layout_is_rejected() {
+        let mut test = TestEscrow::new();
+
+        test.make(100000000, 500000000).unwrap();
+
+        // Same size as the current layout but an unknown version
+        let mut escrow_account = test.svm.get_account(&test.escrow.0).unwrap();
+        escrow_account.data[1] = Escrow::VERSION + 1;
+        test.svm.set_account(test.escrow.0, escrow_account).unwrap();
+
+        assert_instruction_error(test.cancel(), InstructionError::InvalidAccountData);
+    }
+
+    #[test]
+    pub fn test_take_partial_fills_until_closed() {
+        let mut test = TestEscrow::new();
+
+        // 100 of mint A for 30 of mint B, so no fill divides evenly
+        test.make(30, 100).unwrap();
+
+        // 10 of 30 releases floor(10 * 100 / 30) = 33
+        test.take_partial(10).unwrap();
+        assert_eq!(test.balance(&test.taker_ata_a), 33);
+        assert_eq!(test.balance(&test.maker_ata_b), 10);
+        assert_eq!(test.balance(&test.vault), 67);
+        assert!(!test.is_closed(&test.escrow.0));
+
+        // Only 20 is outstanding
+        assert_escrow_error(test.take_partial(21), EscrowError::InvalidFillAmount);
+        assert_escrow_error(test.take_partial(0), EscrowError::InvalidFillAmount);
+
+        // A plain Take fills what is left and sweeps the rounding dust
+        test.take().unwrap();
+        assert_eq!(test.balance(&test.taker_ata_a), 100);
+        assert_eq!(test.balance(&test.maker_ata_b), 30);
+        assert!(test.is_closed(&test.vault));
+        assert!(test.is_closed(&test.escrow.0));
+    }
+
+    #[test]
+    pub fn test_take_partial_too_small_fails_with_fill_too_small() {
+        let mut test = TestEscrow::new();
+
+        test.make(1000, 10).unwrap();
+
+        // 99 * 10 / 1000 rounds down to nothing
+        assert_escrow_error(test.take_partial(99), EscrowError::FillTooSmall);
+
+        test.take_partial(100).unwrap();
+        assert_eq!(test.balance(&test.taker_ata_a), 1);
+        assert_eq!(test.balance(&test.vault), 9);
+    }
+
+    #[test]
+    pub fn test_events_are_logged() {
+        use crate::events::{EscrowCreated, EscrowRefunded, EscrowTaken};
+
+        let mut test = TestEscrow::new();
+        test.use_seed(Some(42));
+        let timestamp = test.svm.get_sysvar::<Clock>().unix_timestamp.to_le_bytes();
+
+        let logs = test.make(30, 100).unwrap().logs;
+        let data = program_data(&logs);
+        assert_eq!(data.len(), 1);
+        let created = EscrowCreated::from_log_data(&data[0]).expect("EscrowCreated not logged");
+        assert_eq!(&created.escrow, test.escrow.0.as_array());
+        assert_eq!(&created.maker, test.maker.pubkey().as_array());
+        assert_eq!(&created.mint_a, test.mint_a.as_array());
+        assert_eq!(&created.mint_b, test.mint_b.as_array());
+        assert_eq!(created.seed, 42u64.to_le_bytes());
+        assert_eq!(created.amount_to_give, 100u64.to_le_bytes());
+        assert_eq!(created.amount_to_receive, 30u64.to_le_bytes());
+        assert_eq!(created.timestamp, timestamp);
+
+        let logs = test.take_partial(10).unwrap().logs;
+        let data = program_data(&logs);
+        assert_eq!(data.len(), 1);
+        assert!(EscrowCreated::from_log_data(&data[0]).is_none());
+        let taken = EscrowTaken::from_log_data(&data[0]).expect("EscrowTaken not logged");
+        assert_eq!(&taken.escrow, test.escrow.0.as_array());
+        assert_eq!(&taken.maker, test.maker.pubkey().as_array());
+        assert_eq!(&taken.taker, test.taker.pubkey().as_array());
+        assert_eq!(&taken.mint_a, test.mint_a.as_array());
+        assert_eq!(&taken.mint_b, test.mint_b.as_array());
+        assert_eq!(taken.seed, 42u64.to_le_bytes());
+        assert_eq!(taken.amount_paid, 10u64.to_le_bytes());
+        assert_eq!(taken.amount_released, 33u64.to_le_bytes());
+        assert_eq!(taken.outstanding, 20u64.to_le_bytes());
+        assert_eq!(taken.timestamp, timestamp);
+
+        let logs = test.cancel().unwrap().logs;
+        let data = program_data(&logs);
+        assert_eq!(data.len(), 1);
+        let refunded = EscrowRefunded::from_log_data(&data[0]).expect("EscrowRefunded not logged");
+        assert_eq!(&refunded.escrow, test.escrow.0.as_array());
+        assert_eq!(&refunded.maker, test.maker.pubkey().as_array());
+        assert_eq!(&refunded.mint_a, test.mint_a.as_array());
+        assert_eq!(&refunded.mint_b, test.mint_b.as_array());
+        assert_eq!(refunded.seed, 42u64.to_le_bytes());
+        assert_eq!(refunded.amount, 67u64.to_le_bytes());
+        assert_eq!(refunded.timestamp, timestamp);
+    }
+
+    // Runs Make then Take with Token-2022 mints and returns the final (taker A, maker B, taker B) balances
+    fn make_and_take_token_2022(transfer_fee: Option<(u16, u64)>, amount_to_receive: u64, amount_to_give: u64) -> (u64, u64, u64) {
+        let mut test = TestEscrow::with_token_program(TOKEN_2022_PROGRAM_ID, transfer_fee);
+
+        test.make(amount_to_receive, amount_to_give).unwrap();
+
+        // The escrow records what actually landed in the vault
+        let escrow_account = test.svm.get_account(&test.escrow.0).unwrap();
+        let amount_in_vault = test.balance(&test.vault);
+        assert_eq!(&escrow_account.data[2 + 32 * 3 + 8..2 + 32 * 3 + 16], &amount_in_vault.to_le_bytes());
+
+        test.take().unwrap();
+
+        assert!(test.is_closed(&test.vault));
+        assert!(test.is_closed(&test.escrow.0));
+
+        (
+            test.balance(&test.taker_ata_a),
+            test.balance(&test.maker_ata_b),
+            test.balance(&test.taker_ata_b),
+        )
+    }
+
+    #[test]
+    pub fn test_make_and_take_token_2022() {
+        let (taker_a, maker_b, taker_b) = make_and_take_token_2022(None, 100000000, 500000000);
+
+        assert_eq!(taker_a, 500000000);
+        assert_eq!(maker_b, 100000000);
+        assert_eq!(taker_b, INITIAL_BALANCE - 100000000);
+    }
+
+    #[test]
+    pub fn test_make_and_take_token_2022_with_transfer_fee() {
+        // 1% fee on both mints with a cap high enough not to kick in
+        let (taker_a, maker_b, taker_b) = make_and_take_token_2022(Some((100, 1000000000)), 100000000, 500000000);
+
+        // 500 tokens lose 1% going into the vault and 1% again going out to the taker
+        assert_eq!(taker_a, 500000000 - 5000000 - 4950000);
+
+        // The maker is credited the full price, the taker pays it grossed up by the fee
+        assert_eq!(maker_b, 100000000);
+        assert_eq!(taker_b, INITIAL_BALANCE - 101010102);
+    }
+
+    #[test]
+    pub fn test_make_and_take_token_2022_with_capped_transfer_fee() {
+        // 1% fee capped at 0.5 tokens
+        let (taker_a, maker_b, taker_b) = make_and_take_token_2022(Some((100, 500000)), 100000000, 500000000);
+
+        assert_eq!(taker_a, 500000000 - 500000 - 500000);
+        assert_eq!(maker_b, 100000000);
+        assert_eq!(taker_b, INITIAL_BALANCE - 100000000 - 500000);
+    }
+
+    #[test]
+    pub fn test_cancel_token_2022_with_transfer_fee() {
+        let mut test = TestEscrow::with_token_program(TOKEN_2022_PROGRAM_ID, Some((100, 1000000000)));
+
+        test.make(100000000, 500000000).unwrap();
+        test.cancel().unwrap();
+
+        // The round trip through the vault costs the fee twice
+        assert_eq!(test.balance(&test.maker_ata_a), INITIAL_BALANCE - 500000000 + 495000000 - 4950000);
+        assert!(test.is_closed(&test.vault));
+        assert!(test.is_closed(&test.escrow.0));
+    }
+
+    #[test]
+    pub fn test_take_with_hook_mints() {
+        let mut test = TestEscrow::with_hook_mints(true, true);
+        let (maker, taker, escrow) = (test.maker.pubkey(), test.taker.pubkey(), test.escrow.0);
+
+        // The maker sends mint A into the vault
+        test.extra_accounts = (hook_accounts(&test.mint_a, &maker), Vec::new());
+        test.make(30, 100).unwrap();
+        assert_eq!(test.balance(&test.vault), 100);
+
+        // Mint A leaves the vault on the escrow's behalf, mint B comes from the taker
+        test.extra_accounts = (hook_accounts(&test.mint_a, &escrow), hook_accounts(&test.mint_b, &taker));
+
+        // Handing every account to mint B leaves mint A's transfer without its hook accounts
+        let mut take_ix = test.take_ix();
+        take_ix.data[2] = 0;
+        assert!(send(&mut test.svm, &[take_ix], &[&test.taker]).is_err());
+
+        test.take_partial(10).unwrap();
+        assert_eq!(test.balance(&test.taker_ata_a), 33);
+        assert_eq!(test.balance(&test.maker_ata_b), 10);
+
+        test.take().unwrap();
+        assert_eq!(test.balance(&test.taker_ata_a), 100);
+        assert_eq!(test.balance(&test.maker_ata_b), 30);
+        assert!(test.is_closed(&test.vault));
+        assert!(test.is_closed(&test.escrow.0));
+    }
+
+    #[test]
+    pub fn test_take_with_a_token_program_per_mint() {
+        // Mint A is a legacy SPL mint, mint B a Token-2022 hook mint
+        let mut test = TestEscrow::with_hook_mints(false, true);
+        let taker = test.taker.pubkey();
+
+        test.make(100000000, 500000000).unwrap();
+
+        test.extra_accounts = (Vec::new(), hook_accounts(&test.mint_b, &taker));
+        test.take().unwrap();
+
+        assert_eq!(test.balance(&test.taker_ata_a), 500000000);
+        assert_eq!(test.balance(&test.maker_ata_b), 100000000);
+        assert_eq!(test.balance(&test.taker_ata_b), INITIAL_BALANCE - 100000000);
+        assert!(test.is_closed(&test.vault));
+        assert!(test.is_closed(&test.escrow.0));
+    }
+
+    #[test]
+    pub fn test_missing_program_points_at_cargo_build_sbf() {
+        let target_dir = std::env::temp_dir().join("escrow-missing-target");
 
-        let transaction = Transaction::new(&[&payer], message, recent_blockhash);
+        let err = find_program_so(None, &target_dir).unwrap_err();
+        assert!(err.contains("cargo build-sbf"), "{}", err);
+        assert!(err.contains(&target_dir.join("deploy/escrow.so").display().to_string()), "{}", err);
 
-        // Send the transaction and capture the result
-        let tx = svm.send_transaction(transaction).unwrap();
+        let err = find_program_so(Some(target_dir.join("custom.so")), &target_dir).unwrap_err();
+        assert!(err.contains("custom.so"), "{}", err);
 
-        // Log transaction details
-        println!("\n\nMake transaction sucessfull");
-        println!("CUs Consumed: {}", tx.compute_units_consumed);
+        // An override that exists is used as-is
+        let so_path = program_so_path();
+        assert_eq!(find_program_so(Some(so_path.clone()), &target_dir).unwrap(), so_path);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,279 @@
+//! Minimal token interface that works against both SPL Token and Token-2022.
+//!
+//! `pinocchio_token` only targets the legacy program and rejects token accounts
+//! that carry extensions, so the escrow reads the base layouts itself and
+//! builds the `TransferChecked`/`CloseAccount` CPIs for whichever token program
+//! was passed in.
+
+use core::{mem::MaybeUninit, slice};
+
+use pinocchio::{
+    AccountView, Address, ProgramResult,
+    cpi::{invoke_signed_with_bounds, Signer},
+    error::ProgramError,
+    instruction::{InstructionAccount, InstructionView},
+    sysvars::{clock::Clock, Sysvar},
+};
+
+pub const TOKEN_PROGRAM_ID: Address = pinocchio_token::ID;
+
+pub const TOKEN_2022_PROGRAM_ID: Address =
+    Address::new_from_array(pinocchio_pubkey::pubkey!("TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb"));
+
+/// Upper bound on the transfer-hook accounts forwarded to a single transfer
+pub const MAX_EXTRA_ACCOUNTS: usize = 8;
+
+const MINT_BASE_LEN: usize = 82;
+const TOKEN_ACCOUNT_BASE_LEN: usize = 165;
+const ACCOUNT_TYPE_MINT: u8 = 1;
+const ACCOUNT_TYPE_TOKEN_ACCOUNT: u8 = 2;
+const EXTENSION_TRANSFER_FEE_CONFIG: u16 = 1;
+const MAX_FEE_BASIS_POINTS: u64 = 10_000;
+
+pub fn check_token_program(token_program: &AccountView) -> ProgramResult {
+    if token_program.address() != &TOKEN_PROGRAM_ID && token_program.address() != &TOKEN_2022_PROGRAM_ID {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+    Ok(())
+}
+
+pub struct TokenAccountState {
+    pub mint: Address,
+    pub owner: Address,
+    pub amount: u64,
+}
+
+/// Reads the base state of a token account owned by `token_program`
+pub fn token_account(account: &AccountView, token_program: &AccountView) -> Result<TokenAccountState, ProgramError> {
+    if !account.owned_by(token_program.address()) {
+        return Err(ProgramError::InvalidAccountOwner);
+    }
+
+    let data = account.try_borrow()?;
+    if data.len() < TOKEN_ACCOUNT_BASE_LEN
+        || (data.len() > TOKEN_ACCOUNT_BASE_LEN && data[TOKEN_ACCOUNT_BASE_LEN] != ACCOUNT_TYPE_TOKEN_ACCOUNT)
+    {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    Ok(TokenAccountState {
+        mint: Address::new_from_array(data[0..32].try_into().unwrap()),
+        owner: Address::new_from_array(data[32..64].try_into().unwrap()),
+        amount: u64::from_le_bytes(data[64..72].try_into().unwrap()),
+    })
+}
+
+/// Returns the decimals of a mint owned by `token_program`
+pub fn mint_decimals(mint: &AccountView, token_program: &AccountView) -> Result<u8, ProgramError> {
+    if !mint.owned_by(token_program.address()) {
+        return Err(ProgramError::InvalidAccountOwner);
+    }
+
+    let data = mint.try_borrow()?;
+    if data.len() < MINT_BASE_LEN {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    Ok(data[44])
+}
+
+struct TransferFee {
+    maximum_fee: u64,
+    basis_points: u64,
+}
+
+/// Fee schedule of a Token-2022 mint with the transfer-fee extension for the current epoch
+fn active_transfer_fee(mint: &AccountView) -> Result<Option<TransferFee>, ProgramError> {
+    let data = mint.try_borrow()?;
+    if data.len() <= TOKEN_ACCOUNT_BASE_LEN || data[TOKEN_ACCOUNT_BASE_LEN] != ACCOUNT_TYPE_MINT {
+        return Ok(None);
+    }
+
+    // TLV entries start right after the account type byte
+    let mut offset = TOKEN_ACCOUNT_BASE_LEN + 1;
+    while offset + 4 <= data.len() {
+        let extension_type = u16::from_le_bytes([data[offset], data[offset + 1]]);
+        let length = u16::from_le_bytes([data[offset + 2], data[offset + 3]]) as usize;
+        if extension_type == 0 {
+            break;
+        }
+
+        let value = data
+            .get(offset + 4..offset + 4 + length)
+            .ok_or(ProgramError::InvalidAccountData)?;
+
+        if extension_type == EXTENSION_TRANSFER_FEE_CONFIG {
+            if value.len() < 108 {
+                return Err(ProgramError::InvalidAccountData);
+            }
+
+            // Two authorities and the withheld amount come before the older and newer fees
+            let read_fee = |start: usize| -> (u64, TransferFee) {
+                (
+                    u64::from_le_bytes(value[start..start + 8].try_into().unwrap()),
+                    TransferFee {
+                        maximum_fee: u64::from_le_bytes(value[start + 8..start + 16].try_into().unwrap()),
+                        basis_points: u16::from_le_bytes(value[start + 16..start + 18].try_into().unwrap()) as u64,
+                    },
+                )
+            };
+            let (_, older) = read_fee(72);
+            let (newer_epoch, newer) = read_fee(90);
+
+            let epoch = Clock::get()?.epoch;
+            return Ok(Some(if epoch >= newer_epoch { newer } else { older }));
+        }
+
+        offset += 4 + length;
+    }
+
+    Ok(None)
+}
+
+pub fn has_transfer_fee(mint: &AccountView) -> Result<bool, ProgramError> {
+    Ok(active_transfer_fee(mint)?.is_some())
+}
+
+/// Fee withheld by the mint when `amount` is transferred
+pub fn transfer_fee(mint: &AccountView, amount: u64) -> Result<u64, ProgramError> {
+    let Some(fee) = active_transfer_fee(mint)? else {
+        return Ok(0);
+    };
+
+    if fee.basis_points == 0 || amount == 0 {
+        return Ok(0);
+    }
+
+    let raw_fee = (amount as u128 * fee.basis_points as u128).div_ceil(MAX_FEE_BASIS_POINTS as u128);
+    Ok(u64::try_from(raw_fee).map_err(|_| ProgramError::ArithmeticOverflow)?.min(fee.maximum_fee))
+}
+
+/// Amount to send so that the destination is credited with `net_amount` after fees
+pub fn pre_fee_amount(mint: &AccountView, net_amount: u64) -> Result<u64, ProgramError> {
+    let Some(fee) = active_transfer_fee(mint)? else {
+        return Ok(net_amount);
+    };
+
+    match fee.basis_points {
+        0 => Ok(net_amount),
+        MAX_FEE_BASIS_POINTS => net_amount.checked_add(fee.maximum_fee).ok_or(ProgramError::ArithmeticOverflow),
+        _ if net_amount == 0 => Ok(0),
+        basis_points => {
+            let raw = (net_amount as u128 * MAX_FEE_BASIS_POINTS as u128)
+                .div_ceil((MAX_FEE_BASIS_POINTS - basis_points) as u128);
+            let raw = u64::try_from(raw).map_err(|_| ProgramError::ArithmeticOverflow)?;
+
+            if raw - net_amount >= fee.maximum_fee {
+                net_amount.checked_add(fee.maximum_fee).ok_or(ProgramError::ArithmeticOverflow)
+            } else {
+                Ok(raw)
+            }
+        }
+    }
+}
+
+pub struct TransferChecked<'a> {
+    pub from: &'a AccountView,
+    pub mint: &'a AccountView,
+    pub to: &'a AccountView,
+    pub authority: &'a AccountView,
+    pub token_program: &'a AccountView,
+    pub amount: u64,
+    pub decimals: u8,
+    /// Accounts required by a transfer-hook mint, forwarded as-is
+    pub extra_accounts: &'a [AccountView],
+}
+
+impl TransferChecked<'_> {
+    pub fn invoke(&self) -> ProgramResult {
+        self.invoke_signed(&[])
+    }
+
+    pub fn invoke_signed(&self, signers: &[Signer]) -> ProgramResult {
+        if self.extra_accounts.len() > MAX_EXTRA_ACCOUNTS {
+            return Err(ProgramError::InvalidArgument);
+        }
+
+        let mut data = [0u8; 10];
+        data[0] = 12;
+        data[1..9].copy_from_slice(&self.amount.to_le_bytes());
+        data[9] = self.decimals;
+
+        let num_accounts = 4 + self.extra_accounts.len();
+
+        // Sized for the most accounts a transfer can take, only `num_accounts` are used
+        const UNINIT_INSTRUCTION_ACCOUNT: MaybeUninit<InstructionAccount> = MaybeUninit::<InstructionAccount>::uninit();
+        let mut instruction_accounts = [UNINIT_INSTRUCTION_ACCOUNT; 4 + MAX_EXTRA_ACCOUNTS];
+        instruction_accounts[0].write(InstructionAccount::writable(self.from.address()));
+        instruction_accounts[1].write(InstructionAccount::readonly(self.mint.address()));
+        instruction_accounts[2].write(InstructionAccount::writable(self.to.address()));
+        instruction_accounts[3].write(InstructionAccount::readonly_signer(self.authority.address()));
+        for (instruction_account, extra_account) in instruction_accounts[4..].iter_mut().zip(self.extra_accounts) {
+            instruction_account.write(InstructionAccount::from(extra_account));
+        }
+
+        let mut account_views = [self.from; 4 + MAX_EXTRA_ACCOUNTS];
+        account_views[1] = self.mint;
+        account_views[2] = self.to;
+        account_views[3] = self.authority;
+        for (account_view, extra_account) in account_views[4..].iter_mut().zip(self.extra_accounts) {
+            *account_view = extra_account;
+        }
+
+        let instruction = InstructionView {
+            program_id: self.token_program.address(),
+            // SAFETY: the first `num_accounts` entries were initialized above
+            accounts: unsafe { slice::from_raw_parts(instruction_accounts.as_ptr() as *const InstructionAccount, num_accounts) },
+            data: &data,
+        };
+
+        invoke_signed_with_bounds::<{ 4 + MAX_EXTRA_ACCOUNTS }>(&instruction, &account_views[..num_accounts], signers)
+    }
+}
+
+pub struct CloseAccount<'a> {
+    pub account: &'a AccountView,
+    pub destination: &'a AccountView,
+    pub authority: &'a AccountView,
+    pub token_program: &'a AccountView,
+}
+
+impl CloseAccount<'_> {
+    pub fn invoke_signed(&self, signers: &[Signer]) -> ProgramResult {
+        let instruction = InstructionView {
+            program_id: self.token_program.address(),
+            accounts: &[
+                InstructionAccount::writable(self.account.address()),
+                InstructionAccount::writable(self.destination.address()),
+                InstructionAccount::readonly_signer(self.authority.address()),
+            ],
+            data: &[9],
+        };
+
+        invoke_signed_with_bounds::<3>(&instruction, &[self.account, self.destination, self.authority], signers)
+    }
+}
+
+/// Moves the fees withheld in `account` to its mint, Token-2022 refuses to close
+/// an account that still holds withheld fees
+pub struct HarvestWithheldTokensToMint<'a> {
+    pub mint: &'a AccountView,
+    pub account: &'a AccountView,
+    pub token_program: &'a AccountView,
+}
+
+impl HarvestWithheldTokensToMint<'_> {
+    pub fn invoke(&self) -> ProgramResult {
+        let instruction = InstructionView {
+            program_id: self.token_program.address(),
+            accounts: &[
+                InstructionAccount::writable(self.mint.address()),
+                InstructionAccount::writable(self.account.address()),
+            ],
+            // TransferFeeExtension, HarvestWithheldTokensToMint
+            data: &[26, 4],
+        };
+
+        invoke_signed_with_bounds::<2>(&instruction, &[self.mint, self.account], &[])
+    }
+}
@@ -0,0 +1,790 @@
+//! Client for `escrow-litesvm/programs/anchor-escrow`.
+//!
+//! Instruction data is the Anchor discriminator, `sha256("global:<name>")[..8]`,
+//! followed by the Borsh encoding of the arguments. Accounts start with
+//! `sha256("account:<Name>")[..8]`. Optional accounts that are left out are passed
+//! as the program id, which is how Anchor encodes `None`.
+//!
+//! Takes are passed the current [`Config`], or `None` before `initialize_config`
+//! has run, in which case only escrows made without a fee can be taken. The fee is
+//! charged in mint B and comes out of what the maker receives: the taker pays the
+//! escrow's price and the treasury gets `fee_bps` of it.
+
+use anchor_lang::{
+    prelude::{borsh, AccountMeta, Pubkey},
+    solana_program::{
+        bpf_loader_upgradeable, hash::hash, instruction::Instruction, pubkey, system_program,
+    },
+    AnchorDeserialize, AnchorSerialize,
+};
+use anchor_spl::associated_token::get_associated_token_address_with_program_id;
+
+pub use anchor_spl::{
+    associated_token::ID as ASSOCIATED_TOKEN_PROGRAM_ID,
+    token::{spl_token::native_mint::ID as NATIVE_MINT, ID as TOKEN_PROGRAM_ID},
+};
+
+pub const PROGRAM_ID: Pubkey = pubkey!("FircrADQ2wgGuvpm8qneNCfKM7o5zoHTWnDQxngpTQ3J");
+
+/// Runs the refunds `schedule_refund` queues
+pub const TUKTUK_PROGRAM_ID: Pubkey = pubkey!("tuktukUrfhXT6ZT77QTU8RQtvgL967uRuVagWF57zVA");
+
+/// Who may take an escrow
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub enum TakerAllowlist {
+    Open,
+    Taker(Pubkey),
+    MerkleRoot([u8; 32]),
+}
+
+/// Price of the whole deposit, decaying linearly from `start_price` at
+/// `start_time` to `end_price` at `end_time`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct DutchAuction {
+    pub start_price: u64,
+    pub end_price: u64,
+    pub start_time: i64,
+    pub end_time: i64,
+}
+
+/// Price of the deposit relative to an oracle feed of mint A in mint B
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct OraclePricing {
+    pub feed: Pubkey,
+    /// Added to the oracle price in bps
+    pub spread_bps: i16,
+    /// In seconds
+    pub max_age: u32,
+    /// In bps of the price
+    pub max_conf_bps: u16,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct BasketLeg {
+    pub mint: Pubkey,
+    pub amount: u64,
+}
+
+/// Program accounts, read back from their raw data
+pub trait AccountState: AnchorDeserialize {
+    const NAME: &'static str;
+
+    /// `None` when the data is not an account of this type
+    fn decode(data: &[u8]) -> Option<Self> {
+        if data.len() < 8 || data[..8] != discriminator("account", Self::NAME) {
+            return None;
+        }
+
+        Self::deserialize(&mut &data[8..]).ok()
+    }
+}
+
+#[derive(AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Escrow {
+    pub seed: u64,
+    pub maker: Pubkey,
+    pub mint_a: Pubkey,
+    pub mint_b: Pubkey,
+    pub receive: u64,
+    pub filled: u64,
+    pub remaining: u64,
+    pub created_at: i64,
+    pub lock_until: i64,
+    pub expires_at: Option<i64>,
+    pub allowlist: TakerAllowlist,
+    pub crank_reward: u64,
+    pub refund_task: Option<Pubkey>,
+    /// Share of every fill's mint B that goes to the treasury instead of the maker
+    pub fee_bps: u16,
+    pub auction: Option<DutchAuction>,
+    pub oracle: Option<OraclePricing>,
+    pub listed: bool,
+    pub bump: u8,
+}
+
+impl AccountState for Escrow {
+    const NAME: &'static str = "Escrow";
+}
+
+#[derive(AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    pub admin: Pubkey,
+    pub fee_bps: u16,
+    pub treasury: Pubkey,
+    pub oracle_program: Option<Pubkey>,
+    /// Programs `take_with_route` may swap through
+    pub swap_programs: Vec<Pubkey>,
+    pub bump: u8,
+}
+
+impl AccountState for Config {
+    const NAME: &'static str = "Config";
+}
+
+#[derive(AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Basket {
+    pub seed: u64,
+    pub maker: Pubkey,
+    pub give: Vec<BasketLeg>,
+    pub receive: Vec<BasketLeg>,
+    pub created_at: i64,
+    pub bump: u8,
+}
+
+impl AccountState for Basket {
+    const NAME: &'static str = "Basket";
+}
+
+/// Prices of [`Offer`]s are mint B per unit of mint A, scaled by this
+pub const PRICE_SCALE: u128 = 1_000_000_000;
+
+#[derive(AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Offer {
+    pub escrow: Pubkey,
+    pub maker: Pubkey,
+    pub remaining: u64,
+    pub outstanding: u64,
+    pub price: u64,
+}
+
+#[derive(AnchorDeserialize, Clone, Debug, PartialEq, Eq)]
+pub struct OrderBook {
+    pub mint_a: Pubkey,
+    pub mint_b: Pubkey,
+    pub offers: Vec<Offer>,
+    pub bump: u8,
+}
+
+impl AccountState for OrderBook {
+    const NAME: &'static str = "OrderBook";
+}
+
+impl OrderBook {
+    /// Cheapest offer, the first in the book among equal prices
+    pub fn best_offer(&self) -> Option<&Offer> {
+        self.offers_by_price().into_iter().next()
+    }
+
+    /// Offers from cheapest to most expensive, in book order within a price
+    pub fn offers_by_price(&self) -> Vec<&Offer> {
+        let mut offers: Vec<_> = self.offers.iter().collect();
+        offers.sort_by_key(|offer| offer.price);
+        offers
+    }
+}
+
+/// Reads the order book of a pair out of LiteSVM, `None` if it was never opened
+#[cfg(feature = "litesvm")]
+pub fn order_book(svm: &litesvm::LiteSVM, mint_a: &Pubkey, mint_b: &Pubkey) -> Option<OrderBook> {
+    let account = svm.get_account(&order_book_address(mint_a, mint_b))?;
+    OrderBook::decode(&account.data)
+}
+
+fn discriminator(namespace: &str, name: &str) -> [u8; 8] {
+    hash(format!("{namespace}:{name}").as_bytes()).to_bytes()[..8]
+        .try_into()
+        .unwrap()
+}
+
+fn instruction_data(name: &str, args: impl AnchorSerialize) -> Vec<u8> {
+    let mut data = discriminator("global", name).to_vec();
+    args.serialize(&mut data).unwrap();
+    data
+}
+
+/// Anchor's encoding of an optional account
+fn optional(account: Option<Pubkey>) -> AccountMeta {
+    match account {
+        Some(account) => AccountMeta::new(account, false),
+        None => AccountMeta::new_readonly(PROGRAM_ID, false),
+    }
+}
+
+pub fn escrow_address(maker: &Pubkey, seed: u64) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"escrow", maker.as_ref(), &seed.to_le_bytes()],
+        &PROGRAM_ID,
+    )
+    .0
+}
+
+pub fn basket_address(maker: &Pubkey, seed: u64) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"basket", maker.as_ref(), &seed.to_le_bytes()],
+        &PROGRAM_ID,
+    )
+    .0
+}
+
+pub fn config_address() -> Pubkey {
+    Pubkey::find_program_address(&[b"config"], &PROGRAM_ID).0
+}
+
+/// Holds the program's upgrade authority, the only key that can initialize the config
+pub fn program_data_address() -> Pubkey {
+    bpf_loader_upgradeable::get_program_data_address(&PROGRAM_ID)
+}
+
+/// Signs for the program when it queues tasks, a task queue has to list it as a
+/// queue authority
+pub fn queue_authority_address() -> Pubkey {
+    Pubkey::find_program_address(&[b"queue_authority"], &PROGRAM_ID).0
+}
+
+/// Created by tuktuk when `task_queue` adds the program's queue authority
+pub fn task_queue_authority_address(task_queue: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[
+            b"task_queue_authority",
+            task_queue.as_ref(),
+            queue_authority_address().as_ref(),
+        ],
+        &TUKTUK_PROGRAM_ID,
+    )
+    .0
+}
+
+pub fn task_address(task_queue: &Pubkey, task_id: u16) -> Pubkey {
+    Pubkey::find_program_address(
+        &[b"task", task_queue.as_ref(), &task_id.to_le_bytes()],
+        &TUKTUK_PROGRAM_ID,
+    )
+    .0
+}
+
+pub fn order_book_address(mint_a: &Pubkey, mint_b: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[b"book", mint_a.as_ref(), mint_b.as_ref()], &PROGRAM_ID).0
+}
+
+pub fn ata(owner: &Pubkey, mint: &Pubkey, token_program: &Pubkey) -> Pubkey {
+    get_associated_token_address_with_program_id(owner, mint, token_program)
+}
+
+/// Arguments of `make` after the seed
+#[derive(AnchorSerialize, Clone, Debug)]
+pub struct MakeArgs {
+    pub deposit: u64,
+    pub receive: u64,
+    pub unlock_after: i64,
+    pub expires_at: Option<i64>,
+    pub allowlist: TakerAllowlist,
+    /// Lamports paid to whoever cranks `refund_expired`
+    pub crank_reward: u64,
+    /// Replaces the fixed `receive`, which the program then ignores
+    pub auction: Option<DutchAuction>,
+    /// Replaces the fixed `receive`, which the program then ignores
+    pub oracle: Option<OraclePricing>,
+}
+
+impl MakeArgs {
+    /// Unlocked right away, never expires, open to any taker, fixed price and
+    /// without a crank reward
+    pub fn new(deposit: u64, receive: u64) -> Self {
+        Self {
+            deposit,
+            receive,
+            unlock_after: 0,
+            expires_at: None,
+            allowlist: TakerAllowlist::Open,
+            crank_reward: 0,
+            auction: None,
+            oracle: None,
+        }
+    }
+}
+
+/// Arguments of `update`, the default leaves the escrow unchanged
+#[derive(AnchorSerialize, Clone, Debug, Default)]
+pub struct UpdateArgs {
+    pub receive: Option<u64>,
+    pub top_up: u64,
+    pub withdraw: u64,
+    /// `Some(None)` removes the expiry
+    pub expires_at: Option<Option<i64>>,
+}
+
+/// The keys that identify an escrow, every other account is derived from them.
+///
+/// Token accounts are left out for a native SOL side.
+#[derive(Clone, Debug)]
+pub struct EscrowKeys {
+    pub maker: Pubkey,
+    pub mint_a: Pubkey,
+    pub mint_b: Pubkey,
+    pub seed: u64,
+    pub token_program: Pubkey,
+    /// Listed in the pair's order book, which every instruction then passes
+    pub listed: bool,
+    /// Price feed of an oracle priced escrow, passed to take
+    pub oracle_feed: Option<Pubkey>,
+    /// Task queue and task id of the escrow's scheduled refund, dequeued by
+    /// whatever closes the escrow
+    pub refund_task: Option<(Pubkey, u16)>,
+}
+
+impl EscrowKeys {
+    /// Escrow between two SPL Token (or native SOL) mints
+    pub fn new(maker: Pubkey, mint_a: Pubkey, mint_b: Pubkey, seed: u64) -> Self {
+        Self {
+            maker,
+            mint_a,
+            mint_b,
+            seed,
+            token_program: TOKEN_PROGRAM_ID,
+            listed: false,
+            oracle_feed: None,
+            refund_task: None,
+        }
+    }
+
+    /// `make` lists the escrow in the pair's order book
+    pub fn with_order_book(self) -> Self {
+        Self {
+            listed: true,
+            ..self
+        }
+    }
+
+    pub fn with_token_program(self, token_program: Pubkey) -> Self {
+        Self {
+            token_program,
+            ..self
+        }
+    }
+
+    /// Take passes `oracle_feed`, which must match the escrow's oracle pricing
+    pub fn with_oracle_feed(self, oracle_feed: Pubkey) -> Self {
+        Self {
+            oracle_feed: Some(oracle_feed),
+            ..self
+        }
+    }
+
+    /// The escrow's refund was scheduled as task `task_id` of `task_queue`
+    pub fn with_refund_task(self, task_queue: Pubkey, task_id: u16) -> Self {
+        Self {
+            refund_task: Some((task_queue, task_id)),
+            ..self
+        }
+    }
+
+    pub fn escrow(&self) -> Pubkey {
+        escrow_address(&self.maker, self.seed)
+    }
+
+    /// `None` when mint A is native SOL, which the escrow account holds itself
+    pub fn vault(&self) -> Option<Pubkey> {
+        self.token_account(&self.escrow(), &self.mint_a)
+    }
+
+    /// ATA of `owner` for `mint`, `None` for native SOL
+    pub fn token_account(&self, owner: &Pubkey, mint: &Pubkey) -> Option<Pubkey> {
+        (*mint != NATIVE_MINT).then(|| ata(owner, mint, &self.token_program))
+    }
+
+    pub fn order_book(&self) -> Option<Pubkey> {
+        self.listed
+            .then(|| order_book_address(&self.mint_a, &self.mint_b))
+    }
+
+    pub fn make_ix(&self, args: MakeArgs) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: vec![
+                AccountMeta::new(self.maker, true),
+                AccountMeta::new_readonly(self.mint_a, false),
+                AccountMeta::new_readonly(self.mint_b, false),
+                optional(self.token_account(&self.maker, &self.mint_a)),
+                AccountMeta::new(self.escrow(), false),
+                optional(self.vault()),
+                AccountMeta::new_readonly(ASSOCIATED_TOKEN_PROGRAM_ID, false),
+                AccountMeta::new_readonly(self.token_program, false),
+                AccountMeta::new_readonly(system_program::ID, false),
+                optional(self.order_book()),
+                AccountMeta::new_readonly(config_address(), false),
+            ],
+            data: instruction_data("make", (self.seed, args)),
+        }
+    }
+
+    /// Fills everything still outstanding, whatever the escrow asks
+    pub fn take_ix(
+        &self,
+        taker: &Pubkey,
+        config: Option<&Config>,
+        proof: Vec<[u8; 32]>,
+    ) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: self.take_accounts(taker, config),
+            data: instruction_data("take", (proof, None::<u64>)),
+        }
+    }
+
+    /// Fills everything still outstanding, failing if the escrow asks more than
+    /// `max_pay` of mint B
+    pub fn take_with_max_pay_ix(
+        &self,
+        taker: &Pubkey,
+        config: Option<&Config>,
+        proof: Vec<[u8; 32]>,
+        max_pay: u64,
+    ) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: self.take_accounts(taker, config),
+            data: instruction_data("take", (proof, Some(max_pay))),
+        }
+    }
+
+    /// Fills everything still outstanding with the mint B `route` pays the taker,
+    /// the swap's program and accounts follow the take's. The program has to be one
+    /// of the config's `swap_programs`
+    pub fn take_with_route_ix(
+        &self,
+        taker: &Pubkey,
+        config: Option<&Config>,
+        proof: Vec<[u8; 32]>,
+        max_pay: Option<u64>,
+        route: Instruction,
+    ) -> Instruction {
+        let mut accounts = self.take_accounts(taker, config);
+        accounts.push(AccountMeta::new_readonly(route.program_id, false));
+        accounts.extend(route.accounts);
+
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts,
+            data: instruction_data("take_with_route", (proof, max_pay, route.data)),
+        }
+    }
+
+    /// Pays `amount` of mint B for the matching share of the deposit
+    pub fn take_partial_ix(
+        &self,
+        taker: &Pubkey,
+        config: Option<&Config>,
+        proof: Vec<[u8; 32]>,
+        amount: u64,
+    ) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: self.take_accounts(taker, config),
+            data: instruction_data("take_partial", (proof, amount)),
+        }
+    }
+
+    /// The escrow's fee goes to the `config` treasury, whose ATA for an SPL mint B is
+    /// created by the take when it is missing
+    fn take_accounts(&self, taker: &Pubkey, config: Option<&Config>) -> Vec<AccountMeta> {
+        let treasury = config.map(|config| config.treasury);
+
+        vec![
+            AccountMeta::new(*taker, true),
+            AccountMeta::new(self.maker, false),
+            AccountMeta::new_readonly(self.mint_a, false),
+            AccountMeta::new_readonly(self.mint_b, false),
+            optional(self.token_account(taker, &self.mint_a)),
+            optional(self.token_account(taker, &self.mint_b)),
+            optional(self.token_account(&self.maker, &self.mint_b)),
+            AccountMeta::new(self.escrow(), false),
+            optional(self.vault()),
+            match config {
+                Some(_) => AccountMeta::new_readonly(config_address(), false),
+                None => optional(None),
+            },
+            optional(treasury),
+            optional(treasury.and_then(|treasury| self.token_account(&treasury, &self.mint_b))),
+            AccountMeta::new_readonly(ASSOCIATED_TOKEN_PROGRAM_ID, false),
+            AccountMeta::new_readonly(self.token_program, false),
+            AccountMeta::new_readonly(system_program::ID, false),
+            optional(self.order_book()),
+            match self.oracle_feed {
+                Some(oracle_feed) => AccountMeta::new_readonly(oracle_feed, false),
+                None => optional(None),
+            },
+        ]
+        .into_iter()
+        .chain(self.scheduled_refund_accounts())
+        .collect()
+    }
+
+    /// Left out for an escrow without a scheduled refund
+    fn scheduled_refund_accounts(&self) -> [AccountMeta; 5] {
+        match self.refund_task {
+            Some((task_queue, task_id)) => [
+                AccountMeta::new(task_address(&task_queue, task_id), false),
+                AccountMeta::new(task_queue, false),
+                AccountMeta::new_readonly(task_queue_authority_address(&task_queue), false),
+                AccountMeta::new_readonly(queue_authority_address(), false),
+                AccountMeta::new_readonly(TUKTUK_PROGRAM_ID, false),
+            ],
+            None => [
+                optional(None),
+                optional(None),
+                optional(None),
+                optional(None),
+                optional(None),
+            ],
+        }
+    }
+
+    pub fn update_ix(&self, args: UpdateArgs) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: vec![
+                AccountMeta::new(self.maker, true),
+                AccountMeta::new_readonly(self.mint_a, false),
+                optional(self.token_account(&self.maker, &self.mint_a)),
+                AccountMeta::new(self.escrow(), false),
+                optional(self.vault()),
+                AccountMeta::new_readonly(self.token_program, false),
+                AccountMeta::new_readonly(system_program::ID, false),
+                optional(self.order_book()),
+            ],
+            data: instruction_data("update", args),
+        }
+    }
+
+    pub fn refund_ix(&self) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: [
+                AccountMeta::new(self.maker, true),
+                AccountMeta::new_readonly(self.mint_a, false),
+                optional(self.token_account(&self.maker, &self.mint_a)),
+                AccountMeta::new(self.escrow(), false),
+                optional(self.vault()),
+                AccountMeta::new_readonly(self.token_program, false),
+                AccountMeta::new_readonly(system_program::ID, false),
+                optional(self.order_book()),
+            ]
+            .into_iter()
+            .chain(self.scheduled_refund_accounts())
+            .collect(),
+            data: instruction_data("refund", ()),
+        }
+    }
+
+    /// Refund of an expired escrow, the crank reward goes to `cranker` if it signs.
+    /// A cranker also dequeues the escrow's scheduled refund
+    pub fn refund_expired_ix(&self, cranker: Option<Pubkey>) -> Instruction {
+        let cranker = match cranker {
+            Some(cranker) => AccountMeta::new(cranker, true),
+            None => optional(None),
+        };
+
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: [
+                AccountMeta::new(self.maker, false),
+                AccountMeta::new_readonly(self.mint_a, false),
+                optional(self.token_account(&self.maker, &self.mint_a)),
+                AccountMeta::new(self.escrow(), false),
+                optional(self.vault()),
+                AccountMeta::new_readonly(self.token_program, false),
+                AccountMeta::new_readonly(system_program::ID, false),
+                optional(self.order_book()),
+                cranker,
+            ]
+            .into_iter()
+            .chain(self.scheduled_refund_accounts())
+            .collect(),
+            data: instruction_data("refund_expired", ()),
+        }
+    }
+
+    /// Queues `refund_expired` for the escrow's expiry as task `task_id` of
+    /// `task_queue`, a free id in the queue
+    pub fn schedule_refund_ix(&self, task_queue: &Pubkey, task_id: u16) -> Instruction {
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: vec![
+                AccountMeta::new(self.maker, true),
+                AccountMeta::new_readonly(self.mint_a, false),
+                optional(self.token_account(&self.maker, &self.mint_a)),
+                AccountMeta::new(self.escrow(), false),
+                optional(self.vault()),
+                optional(self.order_book()),
+                AccountMeta::new_readonly(self.token_program, false),
+                AccountMeta::new(*task_queue, false),
+                AccountMeta::new_readonly(task_queue_authority_address(task_queue), false),
+                AccountMeta::new(task_address(task_queue, task_id), false),
+                AccountMeta::new(queue_authority_address(), false),
+                AccountMeta::new_readonly(system_program::ID, false),
+                AccountMeta::new_readonly(TUKTUK_PROGRAM_ID, false),
+            ],
+            data: instruction_data("schedule_refund", task_id),
+        }
+    }
+}
+
+pub fn initialize_order_book_ix(payer: &Pubkey, mint_a: &Pubkey, mint_b: &Pubkey) -> Instruction {
+    Instruction {
+        program_id: PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new(*payer, true),
+            AccountMeta::new_readonly(*mint_a, false),
+            AccountMeta::new_readonly(*mint_b, false),
+            AccountMeta::new(order_book_address(mint_a, mint_b), false),
+            AccountMeta::new_readonly(system_program::ID, false),
+        ],
+        data: instruction_data("initialize_order_book", ()),
+    }
+}
+
+/// Only the program's upgrade authority can be the first `admin`
+pub fn initialize_config_ix(admin: &Pubkey, fee_bps: u16, treasury: &Pubkey) -> Instruction {
+    Instruction {
+        program_id: PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new(*admin, true),
+            AccountMeta::new(config_address(), false),
+            AccountMeta::new_readonly(PROGRAM_ID, false),
+            AccountMeta::new_readonly(program_data_address(), false),
+            AccountMeta::new_readonly(system_program::ID, false),
+        ],
+        data: instruction_data("initialize_config", (fee_bps, *treasury)),
+    }
+}
+
+/// Fields left as `None` are kept, `Some(None)` unsets the oracle program
+pub fn update_config_ix(
+    admin: &Pubkey,
+    new_admin: Option<Pubkey>,
+    fee_bps: Option<u16>,
+    treasury: Option<Pubkey>,
+    oracle_program: Option<Option<Pubkey>>,
+) -> Instruction {
+    Instruction {
+        program_id: PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new_readonly(*admin, true),
+            AccountMeta::new(config_address(), false),
+        ],
+        data: instruction_data(
+            "update_config",
+            (new_admin, fee_bps, treasury, oracle_program),
+        ),
+    }
+}
+
+/// Replaces the programs `take_with_route` may swap through
+pub fn set_swap_programs_ix(admin: &Pubkey, swap_programs: Vec<Pubkey>) -> Instruction {
+    Instruction {
+        program_id: PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new_readonly(*admin, true),
+            AccountMeta::new(config_address(), false),
+        ],
+        data: instruction_data("set_swap_programs", swap_programs),
+    }
+}
+
+/// The keys that identify a basket escrow. The legs of a basket travel as
+/// remaining accounts, three per leg.
+#[derive(Clone, Debug)]
+pub struct BasketKeys {
+    pub maker: Pubkey,
+    pub seed: u64,
+    pub token_program: Pubkey,
+}
+
+impl BasketKeys {
+    pub fn new(maker: Pubkey, seed: u64) -> Self {
+        Self {
+            maker,
+            seed,
+            token_program: TOKEN_PROGRAM_ID,
+        }
+    }
+
+    pub fn basket(&self) -> Pubkey {
+        basket_address(&self.maker, self.seed)
+    }
+
+    /// Vault of the basket for one of the mints it gives
+    pub fn vault(&self, mint: &Pubkey) -> Pubkey {
+        ata(&self.basket(), mint, &self.token_program)
+    }
+
+    /// Remaining accounts: `[mint, maker_ata, vault]` per leg of `give`
+    pub fn make_ix(&self, give: Vec<BasketLeg>, receive: Vec<BasketLeg>) -> Instruction {
+        let mut accounts = vec![
+            AccountMeta::new(self.maker, true),
+            AccountMeta::new(self.basket(), false),
+            AccountMeta::new_readonly(ASSOCIATED_TOKEN_PROGRAM_ID, false),
+            AccountMeta::new_readonly(self.token_program, false),
+            AccountMeta::new_readonly(system_program::ID, false),
+        ];
+        accounts.extend(give.iter().flat_map(|leg| {
+            [
+                AccountMeta::new_readonly(leg.mint, false),
+                AccountMeta::new(ata(&self.maker, &leg.mint, &self.token_program), false),
+                AccountMeta::new(self.vault(&leg.mint), false),
+            ]
+        }));
+
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts,
+            data: instruction_data("make_basket", (self.seed, give, receive)),
+        }
+    }
+
+    /// Remaining accounts: `[mint, vault, taker_ata]` per leg of `give`, then
+    /// `[mint, taker_ata, maker_ata]` per leg of `receive`
+    pub fn take_ix(&self, taker: &Pubkey, basket: &Basket) -> Instruction {
+        let mut accounts = vec![
+            AccountMeta::new(*taker, true),
+            AccountMeta::new(self.maker, false),
+            AccountMeta::new(self.basket(), false),
+            AccountMeta::new_readonly(ASSOCIATED_TOKEN_PROGRAM_ID, false),
+            AccountMeta::new_readonly(self.token_program, false),
+            AccountMeta::new_readonly(system_program::ID, false),
+        ];
+        accounts.extend(basket.give.iter().flat_map(|leg| {
+            [
+                AccountMeta::new_readonly(leg.mint, false),
+                AccountMeta::new(self.vault(&leg.mint), false),
+                AccountMeta::new(ata(taker, &leg.mint, &self.token_program), false),
+            ]
+        }));
+        accounts.extend(basket.receive.iter().flat_map(|leg| {
+            [
+                AccountMeta::new_readonly(leg.mint, false),
+                AccountMeta::new(ata(taker, &leg.mint, &self.token_program), false),
+                AccountMeta::new(ata(&self.maker, &leg.mint, &self.token_program), false),
+            ]
+        }));
+
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts,
+            data: instruction_data("take_basket", ()),
+        }
+    }
+
+    /// Remaining accounts: `[mint, vault, maker_ata]` per leg of `give`
+    pub fn refund_ix(&self, basket: &Basket) -> Instruction {
+        let mut accounts = vec![
+            AccountMeta::new(self.maker, true),
+            AccountMeta::new(self.basket(), false),
+            AccountMeta::new_readonly(self.token_program, false),
+            AccountMeta::new_readonly(system_program::ID, false),
+        ];
+        accounts.extend(basket.give.iter().flat_map(|leg| {
+            [
+                AccountMeta::new_readonly(leg.mint, false),
+                AccountMeta::new(self.vault(&leg.mint), false),
+                AccountMeta::new(ata(&self.maker, &leg.mint, &self.token_program), false),
+            ]
+        }));
+
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts,
+            data: instruction_data("refund_basket", ()),
+        }
+    }
+}
@@ -0,0 +1,17 @@
+//! Client for the two escrow programs in this repository.
+//!
+//! - `anchor`: `escrow-litesvm/programs/anchor-escrow`
+//! - `pinocchio`: `accel-pinocchio-escrow`
+//!
+//! Each module builds the program's instructions, derives its PDAs and token
+//! accounts, and decodes its accounts. Neither links the program it talks to, so
+//! the programs' own tests can depend on it.
+//!
+//! The `litesvm` feature adds queries of the anchor program's state in a LiteSVM
+//! instance.
+
+#[cfg(feature = "anchor")]
+pub mod anchor;
+
+#[cfg(feature = "pinocchio")]
+pub mod pinocchio;
@@ -0,0 +1,267 @@
+//! Client for `accel-pinocchio-escrow`.
+//!
+//! Instruction data is a one byte discriminator followed by the little-endian
+//! fields of the instruction's args struct, accounts use the `#[repr(C)]` layout
+//! of `state::Escrow`.
+
+use solana_instruction::{AccountMeta, Instruction};
+use solana_pubkey::Pubkey;
+
+pub use solana_sdk_ids::system_program::ID as SYSTEM_PROGRAM_ID;
+
+pub const PROGRAM_ID: Pubkey =
+    Pubkey::from_str_const("4ibrEMW5F6hKnkW4jVedswYv6H6VtwPN6ar6dvXDN1nT");
+pub const TOKEN_PROGRAM_ID: Pubkey =
+    Pubkey::from_str_const("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA");
+pub const TOKEN_2022_PROGRAM_ID: Pubkey =
+    Pubkey::from_str_const("TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb");
+pub const ASSOCIATED_TOKEN_PROGRAM_ID: Pubkey =
+    Pubkey::from_str_const("ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL");
+
+/// Instruction discriminators, in the order of `EscrowInstrctions`
+pub mod discriminator {
+    pub const MAKE: u8 = 0;
+    pub const TAKE: u8 = 1;
+    pub const CANCEL: u8 = 2;
+    pub const MAKE_V2: u8 = 3;
+    pub const TAKE_PARTIAL: u8 = 4;
+}
+
+/// Escrow PDA and bump, `Make` escrows have no seed and `MakeV2` ones do
+pub fn escrow_address(maker: &Pubkey, seed: Option<u64>) -> (Pubkey, u8) {
+    match seed {
+        Some(seed) => Pubkey::find_program_address(
+            &[b"escrow", maker.as_ref(), &seed.to_le_bytes()],
+            &PROGRAM_ID,
+        ),
+        None => Pubkey::find_program_address(&[b"escrow", maker.as_ref()], &PROGRAM_ID),
+    }
+}
+
+pub fn ata(owner: &Pubkey, mint: &Pubkey, token_program: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(
+        &[owner.as_ref(), token_program.as_ref(), mint.as_ref()],
+        &ASSOCIATED_TOKEN_PROGRAM_ID,
+    )
+    .0
+}
+
+/// Decoded escrow account, current layout only
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Escrow {
+    pub version: u8,
+    pub maker: Pubkey,
+    pub mint_a: Pubkey,
+    pub mint_b: Pubkey,
+    pub amount_to_receive: u64,
+    pub amount_to_give: u64,
+    pub seed: u64,
+    pub bump: u8,
+    pub filled: u64,
+    pub remaining: u64,
+}
+
+impl Escrow {
+    /// `size_of::<state::Escrow>()` in the program, whose tests check the two agree
+    pub const LEN: usize = 139;
+
+    pub const DISCRIMINATOR: u8 = 1;
+
+    pub const VERSION: u8 = 2;
+
+    /// `None` unless `data` is an escrow at the current version, older ones are
+    /// migrated by the program on their next take or cancel
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        if data.len() != Self::LEN || data[0] != Self::DISCRIMINATOR || data[1] != Self::VERSION {
+            return None;
+        }
+
+        let pubkey =
+            |offset: usize| Pubkey::new_from_array(data[offset..offset + 32].try_into().unwrap());
+        let u64_at =
+            |offset: usize| u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap());
+
+        Some(Self {
+            version: data[1],
+            maker: pubkey(2),
+            mint_a: pubkey(34),
+            mint_b: pubkey(66),
+            amount_to_receive: u64_at(98),
+            amount_to_give: u64_at(106),
+            seed: u64_at(114),
+            bump: data[122],
+            filled: u64_at(123),
+            remaining: u64_at(131),
+        })
+    }
+}
+
+/// The keys that identify an escrow, every other account is derived from them
+#[derive(Clone, Debug)]
+pub struct EscrowKeys {
+    pub maker: Pubkey,
+    pub mint_a: Pubkey,
+    pub mint_b: Pubkey,
+    /// Token program of mint A
+    pub token_program: Pubkey,
+    /// Token program of mint B, the same as mint A's unless set
+    pub token_program_b: Pubkey,
+    /// `None` for the unseeded escrow `Make` opens
+    pub seed: Option<u64>,
+    /// Transfer-hook accounts of mint A, passed to every instruction that moves it
+    pub extra_accounts_a: Vec<AccountMeta>,
+    /// Transfer-hook accounts of mint B, passed to `Take` and `TakePartial`
+    pub extra_accounts_b: Vec<AccountMeta>,
+}
+
+impl EscrowKeys {
+    pub fn new(
+        maker: Pubkey,
+        mint_a: Pubkey,
+        mint_b: Pubkey,
+        token_program: Pubkey,
+        seed: Option<u64>,
+    ) -> Self {
+        Self {
+            maker,
+            mint_a,
+            mint_b,
+            token_program,
+            token_program_b: token_program,
+            seed,
+            extra_accounts_a: Vec::new(),
+            extra_accounts_b: Vec::new(),
+        }
+    }
+
+    pub fn with_token_program_b(mut self, token_program_b: Pubkey) -> Self {
+        self.token_program_b = token_program_b;
+        self
+    }
+
+    pub fn with_extra_accounts(
+        mut self,
+        extra_accounts_a: Vec<AccountMeta>,
+        extra_accounts_b: Vec<AccountMeta>,
+    ) -> Self {
+        self.extra_accounts_a = extra_accounts_a;
+        self.extra_accounts_b = extra_accounts_b;
+        self
+    }
+
+    pub fn escrow(&self) -> (Pubkey, u8) {
+        escrow_address(&self.maker, self.seed)
+    }
+
+    pub fn vault(&self) -> Pubkey {
+        ata(&self.escrow().0, &self.mint_a, &self.token_program)
+    }
+
+    /// `Make` or `MakeV2` depending on the seed
+    pub fn make_ix(&self, amount_to_receive: u64, amount_to_give: u64) -> Instruction {
+        let (escrow, bump) = self.escrow();
+
+        let data = match self.seed {
+            Some(seed) => [
+                vec![discriminator::MAKE_V2, bump],
+                seed.to_le_bytes().to_vec(),
+                amount_to_receive.to_le_bytes().to_vec(),
+                amount_to_give.to_le_bytes().to_vec(),
+            ]
+            .concat(),
+            None => [
+                vec![discriminator::MAKE, bump],
+                amount_to_receive.to_le_bytes().to_vec(),
+                amount_to_give.to_le_bytes().to_vec(),
+            ]
+            .concat(),
+        };
+
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: vec![
+                AccountMeta::new(self.maker, true),
+                AccountMeta::new_readonly(self.mint_a, false),
+                AccountMeta::new_readonly(self.mint_b, false),
+                AccountMeta::new(escrow, false),
+                AccountMeta::new(ata(&self.maker, &self.mint_a, &self.token_program), false),
+                AccountMeta::new(self.vault(), false),
+                AccountMeta::new_readonly(SYSTEM_PROGRAM_ID, false),
+                AccountMeta::new_readonly(self.token_program, false),
+                AccountMeta::new_readonly(ASSOCIATED_TOKEN_PROGRAM_ID, false),
+            ]
+            .into_iter()
+            .chain(self.extra_accounts_a.iter().cloned())
+            .collect(),
+            data,
+        }
+    }
+
+    /// The remaining accounts are mint A's hook accounts followed by mint B's, the
+    /// data says where one list ends
+    pub fn take_ix(&self, taker: &Pubkey) -> Instruction {
+        let (escrow, bump) = self.escrow();
+
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: vec![
+                AccountMeta::new(*taker, true),
+                AccountMeta::new(self.maker, false),
+                // Written by HarvestWithheldTokensToMint before the vault is closed
+                AccountMeta::new(self.mint_a, false),
+                AccountMeta::new_readonly(self.mint_b, false),
+                AccountMeta::new(escrow, false),
+                AccountMeta::new(ata(taker, &self.mint_a, &self.token_program), false),
+                AccountMeta::new(ata(taker, &self.mint_b, &self.token_program_b), false),
+                AccountMeta::new(ata(&self.maker, &self.mint_b, &self.token_program_b), false),
+                AccountMeta::new(self.vault(), false),
+                AccountMeta::new_readonly(SYSTEM_PROGRAM_ID, false),
+                AccountMeta::new_readonly(self.token_program, false),
+                AccountMeta::new_readonly(ASSOCIATED_TOKEN_PROGRAM_ID, false),
+                AccountMeta::new_readonly(self.token_program_b, false),
+            ]
+            .into_iter()
+            .chain(self.extra_accounts_a.iter().cloned())
+            .chain(self.extra_accounts_b.iter().cloned())
+            .collect(),
+            data: vec![discriminator::TAKE, bump, self.extra_accounts_a.len() as u8],
+        }
+    }
+
+    /// Take that only pays `amount` of mint B and releases the matching share of the vault
+    pub fn take_partial_ix(&self, taker: &Pubkey, amount: u64) -> Instruction {
+        let take_ix = self.take_ix(taker);
+
+        Instruction {
+            data: [
+                vec![discriminator::TAKE_PARTIAL, take_ix.data[1], take_ix.data[2]],
+                amount.to_le_bytes().to_vec(),
+            ]
+            .concat(),
+            ..take_ix
+        }
+    }
+
+    /// Cancel signed by `signer`, the program only accepts the maker
+    pub fn cancel_ix(&self, signer: &Pubkey) -> Instruction {
+        let (escrow, bump) = self.escrow();
+
+        Instruction {
+            program_id: PROGRAM_ID,
+            accounts: vec![
+                AccountMeta::new(*signer, true),
+                // Written by HarvestWithheldTokensToMint before the vault is closed
+                AccountMeta::new(self.mint_a, false),
+                AccountMeta::new(escrow, false),
+                AccountMeta::new(ata(&self.maker, &self.mint_a, &self.token_program), false),
+                AccountMeta::new(self.vault(), false),
+                AccountMeta::new_readonly(SYSTEM_PROGRAM_ID, false),
+                AccountMeta::new_readonly(self.token_program, false),
+            ]
+            .into_iter()
+            .chain(self.extra_accounts_a.iter().cloned())
+            .collect(),
+            data: vec![discriminator::CANCEL, bump],
+        }
+    }
+}
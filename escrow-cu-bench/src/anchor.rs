@@ -0,0 +1,103 @@
+//! Flows against `escrow-litesvm/programs/anchor-escrow`, with the instructions
+//! `escrow_client::anchor` builds.
+
+use std::{collections::BTreeMap, path::PathBuf};
+
+use anchor_lang::solana_program::instruction::Instruction as AnchorInstruction;
+use escrow_client::anchor::{EscrowKeys, MakeArgs, PROGRAM_ID};
+use solana_instruction::{AccountMeta, Instruction};
+use solana_pubkey::Pubkey;
+use solana_signer::Signer;
+
+use crate::fixture::{read_program, Fixture, DEPOSIT, RECEIVE, SEED};
+
+/// Points the benchmark at a specific build instead of `escrow-litesvm/target`
+pub const PROGRAM_SO_ENV: &str = "ANCHOR_ESCROW_SO";
+
+pub fn run() -> BTreeMap<String, u64> {
+    let target_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../escrow-litesvm/target");
+    let program = read_program(PROGRAM_SO_ENV, target_dir, "anchor_escrow.so");
+
+    let mut results = BTreeMap::new();
+
+    let mut escrow = AnchorEscrow::new(&program);
+    results.insert("make".to_string(), escrow.measure_make());
+    let ix = escrow.keys.take_ix(&escrow.taker(), None, vec![]);
+    results.insert("take".to_string(), escrow.measure_taker(ix));
+
+    let mut escrow = AnchorEscrow::new(&program);
+    escrow.measure_make();
+    let ix = escrow
+        .keys
+        .take_partial_ix(&escrow.taker(), None, vec![], RECEIVE / 2);
+    results.insert("take_partial".to_string(), escrow.measure_taker(ix));
+
+    let mut escrow = AnchorEscrow::new(&program);
+    escrow.measure_make();
+    let ix = escrow.keys.refund_ix();
+    results.insert("refund".to_string(), escrow.measure_maker(ix));
+
+    results
+}
+
+/// The client speaks the Solana 2 types the Anchor program is built with
+fn to_anchor(key: &Pubkey) -> anchor_lang::prelude::Pubkey {
+    anchor_lang::prelude::Pubkey::new_from_array(key.to_bytes())
+}
+
+fn from_anchor(ix: AnchorInstruction) -> Instruction {
+    Instruction {
+        program_id: Pubkey::new_from_array(ix.program_id.to_bytes()),
+        accounts: ix
+            .accounts
+            .into_iter()
+            .map(|meta| AccountMeta {
+                pubkey: Pubkey::new_from_array(meta.pubkey.to_bytes()),
+                is_signer: meta.is_signer,
+                is_writable: meta.is_writable,
+            })
+            .collect(),
+        data: ix.data,
+    }
+}
+
+struct AnchorEscrow {
+    fixture: Fixture,
+    keys: EscrowKeys,
+}
+
+impl AnchorEscrow {
+    fn new(program: &[u8]) -> Self {
+        let program_id = Pubkey::new_from_array(PROGRAM_ID.to_bytes());
+        let fixture = Fixture::new(program_id, program);
+
+        // The config is left uninitialized, so escrows are made without a fee
+        let keys = EscrowKeys::new(
+            to_anchor(&fixture.maker.pubkey()),
+            to_anchor(&fixture.mint_a),
+            to_anchor(&fixture.mint_b),
+            SEED,
+        );
+
+        Self { fixture, keys }
+    }
+
+    fn taker(&self) -> anchor_lang::prelude::Pubkey {
+        to_anchor(&self.fixture.taker.pubkey())
+    }
+
+    fn measure_make(&mut self) -> u64 {
+        let ix = self.keys.make_ix(MakeArgs::new(DEPOSIT, RECEIVE));
+        self.measure_maker(ix)
+    }
+
+    fn measure_maker(&mut self, ix: AnchorInstruction) -> u64 {
+        let maker = self.fixture.maker.insecure_clone();
+        self.fixture.measure(from_anchor(ix), &maker)
+    }
+
+    fn measure_taker(&mut self, ix: AnchorInstruction) -> u64 {
+        let taker = self.fixture.taker.insecure_clone();
+        self.fixture.measure(from_anchor(ix), &taker)
+    }
+}
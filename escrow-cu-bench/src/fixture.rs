@@ -0,0 +1,155 @@
+use std::path::PathBuf;
+
+use litesvm::LiteSVM;
+use litesvm_token::{spl_token, CreateAssociatedTokenAccount, MintTo};
+use solana_instruction::{AccountMeta, Instruction};
+use solana_keypair::Keypair;
+use solana_message::Message;
+use solana_native_token::LAMPORTS_PER_SOL;
+use solana_pubkey::Pubkey;
+use solana_signer::Signer;
+use solana_transaction::Transaction;
+
+pub const TOKEN_PROGRAM_ID: Pubkey = spl_token::ID;
+
+pub const SEED: u64 = 42;
+/// Mint A the maker deposits
+pub const DEPOSIT: u64 = 1_000_000;
+/// Mint B the maker asks for
+pub const RECEIVE: u64 = 500_000;
+
+const MINT_LEN: u64 = 82;
+
+/// Reads a built program, `env` wins over the `cargo build-sbf` outputs in `target_dir`
+pub fn read_program(env: &str, target_dir: PathBuf, name: &str) -> Vec<u8> {
+    let candidates = match std::env::var(env) {
+        Ok(path) => vec![PathBuf::from(path)],
+        Err(_) => vec![
+            target_dir.join("deploy").join(name),
+            target_dir.join("sbf-solana-solana/release").join(name),
+        ],
+    };
+
+    let path = candidates.iter().find(|path| path.exists()).unwrap_or_else(|| {
+        panic!(
+            "{name} not found (looked in {}). Build the program with `cargo build-sbf` first, or set {env} to its path",
+            candidates
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", "),
+        )
+    });
+
+    std::fs::read(path).unwrap_or_else(|err| panic!("Failed to read {}: {err}", path.display()))
+}
+
+/// A maker holding mint A and a taker holding mint B.
+///
+/// Every key is derived from a fixed secret so PDA bump searches, and with them the
+/// compute units, are the same from one run to the next.
+pub struct Fixture {
+    pub svm: LiteSVM,
+    pub maker: Keypair,
+    pub taker: Keypair,
+    pub mint_a: Pubkey,
+    pub mint_b: Pubkey,
+}
+
+impl Fixture {
+    pub fn new(program_id: Pubkey, program: &[u8]) -> Self {
+        let mut svm = LiteSVM::new();
+        svm.add_program(program_id, program)
+            .expect("Failed to add program");
+
+        let maker = Keypair::new_from_array([1; 32]);
+        let taker = Keypair::new_from_array([2; 32]);
+        for owner in [&maker, &taker] {
+            svm.airdrop(&owner.pubkey(), 10 * LAMPORTS_PER_SOL)
+                .expect("Airdrop failed");
+        }
+
+        let mint_a = create_mint(&mut svm, &maker, Keypair::new_from_array([3; 32]));
+        let mint_b = create_mint(&mut svm, &maker, Keypair::new_from_array([4; 32]));
+
+        fund(&mut svm, &maker, &mint_a, &maker.pubkey(), DEPOSIT);
+        fund(&mut svm, &maker, &mint_b, &taker.pubkey(), RECEIVE);
+
+        Self {
+            svm,
+            maker,
+            taker,
+            mint_a,
+            mint_b,
+        }
+    }
+
+    /// Compute units of a single instruction, which has to succeed
+    pub fn measure(&mut self, ix: Instruction, signer: &Keypair) -> u64 {
+        self.svm.expire_blockhash();
+        let message = Message::new(&[ix], Some(&signer.pubkey()));
+        let transaction = Transaction::new(&[signer], message, self.svm.latest_blockhash());
+
+        match self.svm.send_transaction(transaction) {
+            Ok(meta) => meta.compute_units_consumed,
+            Err(failed) => panic!(
+                "Benchmarked instruction failed: {:?}\n{}",
+                failed.err,
+                failed.meta.logs.join("\n")
+            ),
+        }
+    }
+}
+
+/// A 6 decimals mint at a fixed address, `CreateMint` would pick a random one
+fn create_mint(svm: &mut LiteSVM, authority: &Keypair, mint: Keypair) -> Pubkey {
+    let lamports = svm.minimum_balance_for_rent_exemption(MINT_LEN as usize);
+
+    let create_account_ix = Instruction {
+        program_id: solana_sdk_ids::system_program::ID,
+        accounts: vec![
+            AccountMeta::new(authority.pubkey(), true),
+            AccountMeta::new(mint.pubkey(), true),
+        ],
+        data: [
+            0u32.to_le_bytes().to_vec(),
+            lamports.to_le_bytes().to_vec(),
+            MINT_LEN.to_le_bytes().to_vec(),
+            TOKEN_PROGRAM_ID.to_bytes().to_vec(),
+        ]
+        .concat(),
+    };
+
+    // InitializeMint2: decimals, mint authority and no freeze authority
+    let initialize_mint_ix = Instruction {
+        program_id: TOKEN_PROGRAM_ID,
+        accounts: vec![AccountMeta::new(mint.pubkey(), false)],
+        data: [
+            vec![20u8, 6],
+            authority.pubkey().to_bytes().to_vec(),
+            vec![0],
+        ]
+        .concat(),
+    };
+
+    let message = Message::new(
+        &[create_account_ix, initialize_mint_ix],
+        Some(&authority.pubkey()),
+    );
+    let transaction = Transaction::new(&[authority, &mint], message, svm.latest_blockhash());
+    svm.send_transaction(transaction)
+        .expect("Failed to create mint");
+
+    mint.pubkey()
+}
+
+fn fund(svm: &mut LiteSVM, authority: &Keypair, mint: &Pubkey, owner: &Pubkey, amount: u64) {
+    let ata = CreateAssociatedTokenAccount::new(svm, authority, mint)
+        .owner(owner)
+        .send()
+        .expect("Failed to create token account");
+
+    MintTo::new(svm, authority, mint, &ata, amount)
+        .send()
+        .expect("Failed to mint");
+}
@@ -0,0 +1,159 @@
+//! Compute units of the same make, take, partial take and refund flows in the Anchor
+//! escrow and the Pinocchio escrow, run in LiteSVM against the built programs.
+//!
+//! ```text
+//! cargo run --release -- [--update-baseline] [--tolerance <percent>]
+//! ```
+//!
+//! Writes `target/cu-report.md` and `target/cu-report.json`, then exits with an
+//! error when any flow uses more compute units than `baseline.json` allows, or has
+//! no number in it. `--update-baseline` records the current numbers as the new
+//! baseline instead.
+//!
+//! Build both programs with `cargo build-sbf` first, or point `ANCHOR_ESCROW_SO`
+//! and `ESCROW_PROGRAM_SO` at the `.so` files.
+
+mod anchor;
+mod fixture;
+mod pinocchio;
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+/// Compute units per flow, per program
+type Results = BTreeMap<String, BTreeMap<String, u64>>;
+
+const PROGRAMS: [&str; 2] = ["anchor", "pinocchio"];
+
+/// What the Anchor flows do on top of the Pinocchio ones, printed under the table
+const NOTES: &str = "\
+The Anchor make passes the fee config PDA, left uninitialized so no fee is \
+snapshotted, and reads its owner. The Anchor take passes no config, treasury or \
+oracle feed, so it pays only for skipping those optional accounts. The Pinocchio \
+escrow has no fee, config or oracle at all.
+";
+
+fn main() -> ExitCode {
+    let mut update_baseline = false;
+    let mut tolerance = 0.0;
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--update-baseline" => update_baseline = true,
+            "--tolerance" => {
+                tolerance = args
+                    .next()
+                    .and_then(|value| value.parse::<f64>().ok())
+                    .expect("--tolerance needs a percentage")
+            }
+            _ => {
+                eprintln!(
+                    "Usage: cargo run --release -- [--update-baseline] [--tolerance <percent>]"
+                );
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+
+    let manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let baseline_path = manifest_dir.join("baseline.json");
+
+    let results: Results = BTreeMap::from([
+        ("anchor".to_string(), anchor::run()),
+        ("pinocchio".to_string(), pinocchio::run()),
+    ]);
+
+    if update_baseline {
+        write_json(&baseline_path, &results);
+        println!("Wrote {}", baseline_path.display());
+        return ExitCode::SUCCESS;
+    }
+
+    let baseline: Results = std::fs::read_to_string(&baseline_path)
+        .map(|json| serde_json::from_str(&json).expect("baseline.json is not valid"))
+        .unwrap_or_default();
+
+    let (report, regressions) = report(&results, &baseline, tolerance);
+
+    let report_dir = manifest_dir.join("target");
+    std::fs::create_dir_all(&report_dir).expect("Failed to create the report directory");
+    std::fs::write(report_dir.join("cu-report.md"), &report).expect("Failed to write report");
+    write_json(&report_dir.join("cu-report.json"), &results);
+
+    print!("{report}");
+
+    if regressions.is_empty() {
+        return ExitCode::SUCCESS;
+    }
+
+    eprintln!(
+        "\nCompute units missing from or regressed past the baseline (tolerance {tolerance}%):"
+    );
+    for regression in &regressions {
+        eprintln!("  {regression}");
+    }
+    eprintln!("Run with --update-baseline if the change is expected.");
+
+    ExitCode::FAILURE
+}
+
+/// Markdown table of the results next to the baseline, and the flows that regressed
+/// or are missing from it
+fn report(results: &Results, baseline: &Results, tolerance: f64) -> (String, Vec<String>) {
+    let mut table = String::new();
+    let mut regressions = Vec::new();
+
+    writeln!(
+        table,
+        "| Flow | Anchor | vs baseline | Pinocchio | vs baseline | Pinocchio / Anchor |"
+    )
+    .unwrap();
+    writeln!(
+        table,
+        "|------|-------:|------------:|----------:|------------:|-------------------:|"
+    )
+    .unwrap();
+
+    for flow in results["anchor"].keys() {
+        let mut row = format!("| {flow} |");
+
+        for program in PROGRAMS {
+            let units = results[program][flow];
+            let previous = baseline.get(program).and_then(|flows| flows.get(flow));
+
+            let delta = match previous {
+                Some(&previous) => {
+                    let allowed = previous as f64 * (1.0 + tolerance / 100.0);
+                    if units as f64 > allowed {
+                        regressions.push(format!("{program} {flow}: {previous} -> {units}"));
+                    }
+                    format!("{:+}", units as i64 - previous as i64)
+                }
+                None => {
+                    regressions.push(format!("{program} {flow}: not in the baseline"));
+                    "missing".to_string()
+                }
+            };
+
+            write!(row, " {units} | {delta} |").unwrap();
+        }
+
+        let ratio = results["pinocchio"][flow] as f64 / results["anchor"][flow] as f64;
+        writeln!(table, "{row} {:.0}% |", ratio * 100.0).unwrap();
+    }
+
+    write!(table, "\n{NOTES}").unwrap();
+
+    (table, regressions)
+}
+
+fn write_json(path: &Path, results: &Results) {
+    let json = serde_json::to_string_pretty(results).unwrap() + "\n";
+    std::fs::write(path, json)
+        .unwrap_or_else(|err| panic!("Failed to write {}: {err}", path.display()));
+}
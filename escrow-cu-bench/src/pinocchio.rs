@@ -0,0 +1,77 @@
+//! Flows against `accel-pinocchio-escrow`, with the instructions
+//! `escrow_client::pinocchio` builds.
+
+use std::{collections::BTreeMap, path::PathBuf};
+
+use escrow_client::pinocchio::{EscrowKeys, PROGRAM_ID, TOKEN_PROGRAM_ID};
+use solana_instruction::Instruction;
+use solana_signer::Signer;
+
+use crate::fixture::{read_program, Fixture, DEPOSIT, RECEIVE, SEED};
+
+/// Same variable the escrow's own tests read
+pub const PROGRAM_SO_ENV: &str = "ESCROW_PROGRAM_SO";
+
+pub fn run() -> BTreeMap<String, u64> {
+    let target_dir =
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../accel-pinocchio-escrow/target");
+    let program = read_program(PROGRAM_SO_ENV, target_dir, "escrow.so");
+
+    let mut results = BTreeMap::new();
+
+    let mut escrow = PinocchioEscrow::new(&program);
+    results.insert("make".to_string(), escrow.measure_make());
+    let ix = escrow.keys.take_ix(&escrow.fixture.taker.pubkey());
+    results.insert("take".to_string(), escrow.measure_taker(ix));
+
+    let mut escrow = PinocchioEscrow::new(&program);
+    escrow.measure_make();
+    let ix = escrow
+        .keys
+        .take_partial_ix(&escrow.fixture.taker.pubkey(), RECEIVE / 2);
+    results.insert("take_partial".to_string(), escrow.measure_taker(ix));
+
+    let mut escrow = PinocchioEscrow::new(&program);
+    escrow.measure_make();
+    let ix = escrow.keys.cancel_ix(&escrow.fixture.maker.pubkey());
+    results.insert("refund".to_string(), escrow.measure_maker(ix));
+
+    results
+}
+
+struct PinocchioEscrow {
+    fixture: Fixture,
+    keys: EscrowKeys,
+}
+
+impl PinocchioEscrow {
+    fn new(program: &[u8]) -> Self {
+        let fixture = Fixture::new(PROGRAM_ID, program);
+
+        // Seeded, so `MakeV2` like the Anchor escrow's make
+        let keys = EscrowKeys::new(
+            fixture.maker.pubkey(),
+            fixture.mint_a,
+            fixture.mint_b,
+            TOKEN_PROGRAM_ID,
+            Some(SEED),
+        );
+
+        Self { fixture, keys }
+    }
+
+    fn measure_make(&mut self) -> u64 {
+        let ix = self.keys.make_ix(RECEIVE, DEPOSIT);
+        self.measure_maker(ix)
+    }
+
+    fn measure_maker(&mut self, ix: Instruction) -> u64 {
+        let maker = self.fixture.maker.insecure_clone();
+        self.fixture.measure(ix, &maker)
+    }
+
+    fn measure_taker(&mut self, ix: Instruction) -> u64 {
+        let taker = self.fixture.taker.insecure_clone();
+        self.fixture.measure(ix, &taker)
+    }
+}
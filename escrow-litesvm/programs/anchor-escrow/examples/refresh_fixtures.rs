@@ -0,0 +1,101 @@
+//! Refreshes the account fixtures the LiteSVM tests load from `fixtures/`.
+//!
+//! The tests never touch the network, run this when a fixture needs to match
+//! what is currently on chain:
+//!
+//! ```text
+//! cargo run --example refresh_fixtures -- <address>... [--program <address>]... [--url <rpc url>]
+//! ```
+//!
+//! Each account is written to `fixtures/<address>.json` in the same format as
+//! `solana account <address> --output json`. Each upgradeable `--program` is
+//! written to `fixtures/<address>.so`, like `solana program dump`.
+
+use {
+    base64::{prelude::BASE64_STANDARD, Engine},
+    serde_json::json,
+    solana_address::Address,
+    solana_rpc_client::rpc_client::RpcClient,
+    std::{path::PathBuf, str::FromStr},
+};
+
+const DEFAULT_URL: &str = "https://api.devnet.solana.com";
+
+/// `UpgradeableLoaderState::ProgramData` header ahead of the ELF
+const PROGRAM_DATA_METADATA_LEN: usize = 45;
+
+fn main() {
+    let mut url = DEFAULT_URL.to_string();
+    let mut addresses = Vec::new();
+    let mut programs = Vec::new();
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--url" => url = args.next().expect("--url needs a value"),
+            "--program" => programs.push(args.next().expect("--program needs a value")),
+            _ => addresses.push(arg),
+        }
+    }
+
+    if addresses.is_empty() && programs.is_empty() {
+        eprintln!(
+            "Usage: cargo run --example refresh_fixtures -- <address>... [--program <address>]... [--url <rpc url>]"
+        );
+        std::process::exit(1);
+    }
+
+    let rpc_client = RpcClient::new(url);
+    let fixtures_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("fixtures");
+    std::fs::create_dir_all(&fixtures_dir).expect("Failed to create the fixtures directory");
+
+    for address in addresses {
+        let account_address = Address::from_str(&address).expect("Invalid address");
+        let account = rpc_client
+            .get_account(&account_address)
+            .unwrap_or_else(|err| panic!("Failed to fetch {address}: {err}"));
+
+        let fixture = json!({
+            "pubkey": address,
+            "account": {
+                "lamports": account.lamports,
+                "data": [BASE64_STANDARD.encode(&account.data), "base64"],
+                "owner": account.owner.to_string(),
+                "executable": account.executable,
+                "rentEpoch": account.rent_epoch,
+                "space": account.data.len(),
+            },
+        });
+
+        let path = fixtures_dir.join(format!("{address}.json"));
+        std::fs::write(&path, serde_json::to_string_pretty(&fixture).unwrap() + "\n")
+            .expect("Failed to write fixture");
+
+        println!("Wrote {}", path.display());
+    }
+
+    for address in programs {
+        let program_address = Address::from_str(&address).expect("Invalid address");
+        let program = rpc_client
+            .get_account(&program_address)
+            .unwrap_or_else(|err| panic!("Failed to fetch {address}: {err}"));
+
+        // `UpgradeableLoaderState::Program` points at the account holding the ELF
+        let program_data_address = Address::new_from_array(
+            program
+                .data
+                .get(4..36)
+                .and_then(|address| address.try_into().ok())
+                .unwrap_or_else(|| panic!("{address} is not an upgradeable program")),
+        );
+        let program_data = rpc_client
+            .get_account(&program_data_address)
+            .unwrap_or_else(|err| panic!("Failed to fetch the program data of {address}: {err}"));
+
+        let path = fixtures_dir.join(format!("{address}.so"));
+        std::fs::write(&path, &program_data.data[PROGRAM_DATA_METADATA_LEN..])
+            .expect("Failed to write program");
+
+        println!("Wrote {}", path.display());
+    }
+}
@@ -0,0 +1,67 @@
+use anchor_lang::prelude::*;
+
+#[error_code]
+pub enum EscrowError {
+    #[msg("Take instruction can only be called once the escrow is unlocked")]
+    TakeTooEarly,
+    #[msg("Fill amount must be greater than zero and at most the amount still outstanding")]
+    InvalidFillAmount,
+    #[msg("Fill amount is too small to release any of the deposit")]
+    FillTooSmall,
+    #[msg("Arithmetic overflow")]
+    MathOverflow,
+    #[msg("The escrow has expired and can only be refunded")]
+    EscrowExpired,
+    #[msg("Lock period must not be negative and must end before the escrow expires")]
+    InvalidLockPeriod,
+    #[msg("Taker is not on the escrow allowlist")]
+    TakerNotAllowed,
+    #[msg("Update must leave a deposit and an amount still to receive, and cannot reprice an auction or oracle price")]
+    InvalidUpdate,
+    #[msg("Token accounts must be passed for SPL mints and left out for native SOL")]
+    InvalidTokenAccounts,
+    #[msg("A basket needs one to four legs on each side, with distinct mints and non-zero amounts")]
+    InvalidBasket,
+    #[msg("Remaining accounts do not match the basket legs")]
+    BasketAccountMismatch,
+    #[msg("Only the config admin can change the config")]
+    Unauthorized,
+    #[msg("Fee must be at most 1000 bps")]
+    InvalidFee,
+    #[msg("Only an escrow past its expiry can be refunded without the maker")]
+    EscrowNotExpired,
+    #[msg("Crank reward must be at most the escrow's rent")]
+    InvalidCrankReward,
+    #[msg("Only an escrow with an expiry can schedule its refund")]
+    MissingExpiry,
+    #[msg("An auction must decay to a non-zero end price over a non-empty period and cannot be listed")]
+    InvalidAuction,
+    #[msg("Auctions and oracle priced escrows can only be taken whole")]
+    WholeFillOnly,
+    #[msg("Oracle pricing must keep the price positive, bound the confidence to 100% and not be combined with an auction or listing")]
+    InvalidOraclePricing,
+    #[msg("The oracle feed is not the escrow's, is not owned by the configured oracle program or holds no valid price")]
+    InvalidOracleFeed,
+    #[msg("The oracle price is older than the escrow accepts")]
+    StaleOraclePrice,
+    #[msg("The oracle confidence interval is wider than the escrow accepts")]
+    OracleConfidenceTooWide,
+    #[msg("The escrow asks more than the taker's max_pay")]
+    MaxPayExceeded,
+    #[msg("A route needs an SPL mint B and a swap program allowed by the config")]
+    InvalidRoute,
+    #[msg("The route left the taker with less mint B than the escrow asks")]
+    RouteShortfall,
+    #[msg("A fee can only be paid to the config's treasury")]
+    InvalidTreasury,
+    #[msg("The refund of this escrow is already scheduled for its expiry, which cannot change anymore")]
+    RefundScheduled,
+    #[msg("At most 4 swap programs can be allowed")]
+    TooManySwapPrograms,
+    #[msg("The escrow's scheduled refund task has to be passed to close the escrow")]
+    MissingRefundTask,
+    #[msg("The order book is full and the offer is not cheaper than its most expensive one")]
+    OrderBookFull,
+    #[msg("Listed escrows must be passed their order book and unlisted ones none")]
+    OrderBookMismatch,
+}
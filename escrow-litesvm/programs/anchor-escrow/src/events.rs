@@ -0,0 +1,59 @@
+use anchor_lang::prelude::*;
+
+#[event]
+pub struct EscrowCreated {
+    pub escrow: Pubkey,
+    pub maker: Pubkey,
+    pub mint_a: Pubkey,
+    pub mint_b: Pubkey,
+    pub seed: u64,
+    /// Mint A deposited into the vault
+    pub deposit: u64,
+    /// Mint B the maker asks for
+    pub receive: u64,
+    pub timestamp: i64,
+}
+
+/// Emitted for every fill, `outstanding` is zero once the escrow is closed
+#[event]
+pub struct EscrowTaken {
+    pub escrow: Pubkey,
+    pub maker: Pubkey,
+    pub taker: Pubkey,
+    pub mint_a: Pubkey,
+    pub mint_b: Pubkey,
+    pub seed: u64,
+    /// Mint B paid by the taker, including the fee
+    pub amount_paid: u64,
+    /// Part of `amount_paid` sent to the treasury
+    pub fee: u64,
+    /// Mint A released to the taker
+    pub amount_released: u64,
+    pub outstanding: u64,
+    pub timestamp: i64,
+}
+
+/// Emitted when the maker changes an open escrow, carries the values after the update
+#[event]
+pub struct EscrowUpdated {
+    pub escrow: Pubkey,
+    pub maker: Pubkey,
+    pub seed: u64,
+    pub receive: u64,
+    /// Mint A left in the vault
+    pub remaining: u64,
+    pub expires_at: Option<i64>,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct EscrowRefunded {
+    pub escrow: Pubkey,
+    pub maker: Pubkey,
+    pub mint_a: Pubkey,
+    pub mint_b: Pubkey,
+    pub seed: u64,
+    /// Mint A returned to the maker
+    pub amount: u64,
+    pub timestamp: i64,
+}
@@ -0,0 +1,106 @@
+use anchor_lang::prelude::*;
+
+use crate::{
+    error::EscrowError,
+    program::AnchorEscrow,
+    state::{Config, MAX_SWAP_PROGRAMS},
+};
+
+#[derive(Accounts)]
+pub struct InitializeConfig<'info> {
+    /// Becomes the config admin, has to be the program's upgrade authority so the
+    /// role cannot be front-run after a deploy
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    #[account(
+        init,
+        payer = admin,
+        seeds = [b"config"],
+        bump,
+        space = 8 + Config::INIT_SPACE,
+    )]
+    pub config: Account<'info, Config>,
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()))]
+    pub program: Program<'info, AnchorEscrow>,
+    #[account(
+        constraint = program_data.upgrade_authority_address == Some(admin.key())
+            @ EscrowError::Unauthorized,
+    )]
+    pub program_data: Account<'info, ProgramData>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> InitializeConfig<'info> {
+    pub fn init_config(
+        &mut self,
+        fee_bps: u16,
+        treasury: Pubkey,
+        bumps: &InitializeConfigBumps,
+    ) -> Result<()> {
+        Config::check_fee_bps(fee_bps)?;
+
+        self.config.set_inner(Config {
+            admin: self.admin.key(),
+            fee_bps,
+            treasury,
+            oracle_program: None,
+            swap_programs: vec![],
+            bump: bumps.config,
+        });
+
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct UpdateConfig<'info> {
+    pub admin: Signer<'info>,
+    #[account(
+        mut,
+        has_one = admin @ EscrowError::Unauthorized,
+        seeds = [b"config"],
+        bump = config.bump,
+    )]
+    pub config: Account<'info, Config>,
+}
+
+impl<'info> UpdateConfig<'info> {
+    /// Fields left as `None` are kept, handing `admin` over gives up control of the config
+    pub fn update_config(
+        &mut self,
+        admin: Option<Pubkey>,
+        fee_bps: Option<u16>,
+        treasury: Option<Pubkey>,
+        oracle_program: Option<Option<Pubkey>>,
+    ) -> Result<()> {
+        if let Some(fee_bps) = fee_bps {
+            Config::check_fee_bps(fee_bps)?;
+            self.config.fee_bps = fee_bps;
+        }
+
+        if let Some(treasury) = treasury {
+            self.config.treasury = treasury;
+        }
+
+        if let Some(oracle_program) = oracle_program {
+            self.config.oracle_program = oracle_program;
+        }
+
+        if let Some(admin) = admin {
+            self.config.admin = admin;
+        }
+
+        Ok(())
+    }
+
+    /// Replaces the whole list, an empty one stops every routed take
+    pub fn set_swap_programs(&mut self, swap_programs: Vec<Pubkey>) -> Result<()> {
+        require!(
+            swap_programs.len() <= MAX_SWAP_PROGRAMS,
+            EscrowError::TooManySwapPrograms
+        );
+        self.config.swap_programs = swap_programs;
+
+        Ok(())
+    }
+}
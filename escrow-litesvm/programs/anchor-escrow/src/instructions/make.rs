@@ -4,7 +4,28 @@ use anchor_spl::{
     token_interface::{transfer_checked, Mint, TokenAccount, TokenInterface, TransferChecked},
 };
 
-use crate::state::Escrow;
+use crate::{
+    error::EscrowError,
+    events::EscrowCreated,
+    native::{check_token_accounts, is_native, required, send_lamports},
+    state::{Config, DutchAuction, Escrow, OraclePricing, OrderBook, TakerAllowlist},
+};
+
+/// Arguments of `make` after the seed, grouped so new settings don't reorder them
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub struct MakeArgs {
+    pub deposit: u64,
+    pub receive: u64,
+    pub unlock_after: i64,
+    pub expires_at: Option<i64>,
+    pub allowlist: TakerAllowlist,
+    /// Lamports paid to whoever cranks `refund_expired`
+    pub crank_reward: u64,
+    /// Replaces the fixed `receive`, which is then ignored
+    pub auction: Option<DutchAuction>,
+    /// Replaces the fixed `receive`, which is then ignored
+    pub oracle: Option<OraclePricing>,
+}
 
 #[derive(Accounts)]
 #[instruction(seed: u64)]
@@ -18,7 +39,7 @@ pub struct Make<'info> {
         associated_token::mint = mint_a,
         associated_token::authority = maker,
     )]
-    pub maker_ata_a: InterfaceAccount<'info, TokenAccount>,
+    pub maker_ata_a: Option<InterfaceAccount<'info, TokenAccount>>,
     #[account(
         init,
         payer = maker,
@@ -33,22 +54,86 @@ pub struct Make<'info> {
         associated_token::mint = mint_a,
         associated_token::authority = escrow,
     )]
-    pub vault: InterfaceAccount<'info, TokenAccount>,
+    pub vault: Option<InterfaceAccount<'info, TokenAccount>>,
     pub associated_token_program: Program<'info, AssociatedToken>,
     pub token_program: Interface<'info, TokenInterface>,
     pub system_program: Program<'info, System>,
+    #[account(
+        mut,
+        seeds = [b"book", mint_a.key().as_ref(), mint_b.key().as_ref()],
+        bump = order_book.bump,
+    )]
+    pub order_book: Option<Account<'info, OrderBook>>,
+    /// CHECK: The config PDA, the escrow keeps its fee. May not be initialized yet,
+    /// which makes the escrow free of fees
+    #[account(seeds = [b"config"], bump)]
+    pub config: UncheckedAccount<'info>,
 }
 
 impl<'info> Make<'info> {
-    pub fn init_escrow(&mut self, seed: u64, receive: u64, bumps: &MakeBumps) -> Result<()> {
+    pub fn init_escrow(&mut self, seed: u64, args: MakeArgs, bumps: &MakeBumps) -> Result<()> {
+        let MakeArgs {
+            receive,
+            unlock_after,
+            expires_at,
+            allowlist,
+            crank_reward,
+            auction,
+            oracle,
+            ..
+        } = args;
         let clock = Clock::get()?;
+
+        require!(unlock_after >= 0, EscrowError::InvalidLockPeriod);
+        let lock_until = clock
+            .unix_timestamp
+            .checked_add(unlock_after)
+            .ok_or(EscrowError::MathOverflow)?;
+        if let Some(expires_at) = expires_at {
+            require!(expires_at >= lock_until, EscrowError::InvalidLockPeriod);
+        }
+        require!(
+            crank_reward <= Rent::get()?.minimum_balance(8 + Escrow::INIT_SPACE),
+            EscrowError::InvalidCrankReward
+        );
+
+        // An auction asks its start price until the first take reprices it
+        let receive = match &auction {
+            Some(auction) => {
+                require!(
+                    auction.is_valid() && self.order_book.is_none(),
+                    EscrowError::InvalidAuction
+                );
+                auction.start_price
+            }
+            None => receive,
+        };
+        if let Some(oracle) = &oracle {
+            require!(
+                oracle.is_valid() && auction.is_none() && self.order_book.is_none(),
+                EscrowError::InvalidOraclePricing
+            );
+        }
+
         self.escrow.set_inner(Escrow {
             seed,
             maker: self.maker.key(),
             mint_a: self.mint_a.key(),
             mint_b: self.mint_b.key(),
             receive,
+            filled: 0,
+            // Set from the vault once the deposit has landed
+            remaining: 0,
             created_at: clock.unix_timestamp,
+            lock_until,
+            expires_at,
+            allowlist,
+            crank_reward,
+            refund_task: None,
+            fee_bps: Config::current_fee_bps(&self.config)?,
+            auction,
+            oracle,
+            listed: self.order_book.is_some(),
             bump: bumps.escrow,
         });
 
@@ -56,18 +141,55 @@ impl<'info> Make<'info> {
     }
 
     pub fn deposit(&mut self, deposit: u64) -> Result<()> {
-        let cpi_program = self.token_program.to_account_info();
+        let mint_a = self.mint_a.key();
+        check_token_accounts(&mint_a, &[self.maker_ata_a.is_some(), self.vault.is_some()])?;
+
+        if is_native(&mint_a) {
+            // Native SOL is held by the escrow account itself
+            send_lamports(
+                self.maker.to_account_info(),
+                self.escrow.to_account_info(),
+                self.system_program.to_account_info(),
+                deposit,
+            )?;
+        } else {
+            let cpi_program = self.token_program.to_account_info();
+
+            let cpi_accounts = TransferChecked {
+                from: required(&self.maker_ata_a)?.to_account_info(),
+                to: required(&self.vault)?.to_account_info(),
+                authority: self.maker.to_account_info(),
+                mint: self.mint_a.to_account_info(),
+            };
+
+            let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+
+            transfer_checked(cpi_ctx, deposit, self.mint_a.decimals)?;
+        }
 
-        let cpi_accounts = TransferChecked {
-            from: self.maker_ata_a.to_account_info(),
-            to: self.vault.to_account_info(),
-            authority: self.maker.to_account_info(),
-            mint: self.mint_a.to_account_info(),
+        // A transfer-fee mint credits the vault with less than the maker sent
+        self.escrow.remaining = match &mut self.vault {
+            Some(vault) => {
+                vault.reload()?;
+                vault.amount
+            }
+            None => deposit,
         };
 
-        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        if let Some(order_book) = &mut self.order_book {
+            order_book.list(self.escrow.key(), &self.escrow)?;
+        }
 
-        transfer_checked(cpi_ctx, deposit, self.mint_a.decimals)?;
+        emit!(EscrowCreated {
+            escrow: self.escrow.key(),
+            maker: self.maker.key(),
+            mint_a: self.mint_a.key(),
+            mint_b: self.mint_b.key(),
+            seed: self.escrow.seed,
+            deposit: self.escrow.remaining,
+            receive: self.escrow.receive,
+            timestamp: self.escrow.created_at,
+        });
 
         Ok(())
     }
@@ -0,0 +1,103 @@
+use anchor_lang::prelude::*;
+use anchor_spl::{
+    associated_token::{create_idempotent, AssociatedToken, Create},
+    token_interface::{transfer_checked, TokenInterface, TransferChecked},
+};
+
+use crate::{
+    error::EscrowError,
+    state::{basket_mint, basket_token_account, Basket, BasketLeg},
+};
+
+#[derive(Accounts)]
+#[instruction(seed: u64)]
+pub struct MakeBasket<'info> {
+    #[account(mut)]
+    pub maker: Signer<'info>,
+    #[account(
+        init,
+        payer = maker,
+        seeds = [b"basket", maker.key().as_ref(), seed.to_le_bytes().as_ref()],
+        bump,
+        space = 8 + Basket::INIT_SPACE,
+    )]
+    pub basket: Account<'info, Basket>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> MakeBasket<'info> {
+    pub fn init_basket(
+        &mut self,
+        seed: u64,
+        give: Vec<BasketLeg>,
+        receive: Vec<BasketLeg>,
+        bumps: &MakeBasketBumps,
+    ) -> Result<()> {
+        Basket::check_legs(&give)?;
+        Basket::check_legs(&receive)?;
+
+        self.basket.set_inner(Basket {
+            seed,
+            maker: self.maker.key(),
+            give,
+            receive,
+            created_at: Clock::get()?.unix_timestamp,
+            bump: bumps.basket,
+        });
+
+        Ok(())
+    }
+
+    /// Moves every leg the maker gives into its own vault.
+    ///
+    /// `remaining_accounts` holds `[mint, maker_ata, vault]` for each leg of `give`, in order.
+    pub fn deposit(&mut self, remaining_accounts: &'info [AccountInfo<'info>]) -> Result<()> {
+        let give = self.basket.give.clone();
+        require!(
+            remaining_accounts.len() == give.len() * 3,
+            EscrowError::BasketAccountMismatch
+        );
+
+        let token_program = self.token_program.key();
+        let basket = self.basket.key();
+
+        for (leg, accounts) in give.iter().zip(remaining_accounts.chunks(3)) {
+            let [mint, maker_ata, vault] = accounts else {
+                return err!(EscrowError::BasketAccountMismatch);
+            };
+
+            let decimals = basket_mint(mint, leg, &token_program)?;
+            basket_token_account(maker_ata, &self.maker.key(), leg, &token_program)?;
+            basket_token_account(vault, &basket, leg, &token_program)?;
+
+            let cpi_accounts = Create {
+                payer: self.maker.to_account_info(),
+                associated_token: vault.clone(),
+                authority: self.basket.to_account_info(),
+                mint: mint.clone(),
+                system_program: self.system_program.to_account_info(),
+                token_program: self.token_program.to_account_info(),
+            };
+
+            create_idempotent(CpiContext::new(
+                self.associated_token_program.to_account_info(),
+                cpi_accounts,
+            ))?;
+
+            let cpi_accounts = TransferChecked {
+                from: maker_ata.clone(),
+                to: vault.clone(),
+                authority: self.maker.to_account_info(),
+                mint: mint.clone(),
+            };
+
+            let cpi_ctx = CpiContext::new(self.token_program.to_account_info(), cpi_accounts);
+
+            transfer_checked(cpi_ctx, leg.amount, decimals)?;
+        }
+
+        Ok(())
+    }
+}
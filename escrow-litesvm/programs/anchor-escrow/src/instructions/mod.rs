@@ -1,7 +1,23 @@
+pub mod config;
 pub mod make;
+pub mod make_basket;
+pub mod order_book;
 pub mod refund;
+pub mod refund_basket;
+pub mod refund_expired;
+pub mod schedule_refund;
 pub mod take;
+pub mod take_basket;
+pub mod update;
 
+pub use config::*;
 pub use make::*;
+pub use make_basket::*;
+pub use order_book::*;
 pub use refund::*;
+pub use refund_basket::*;
+pub use refund_expired::*;
+pub use schedule_refund::*;
 pub use take::*;
+pub use take_basket::*;
+pub use update::*;
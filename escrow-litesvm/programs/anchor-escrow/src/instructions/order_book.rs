@@ -0,0 +1,35 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::Mint;
+
+use crate::state::OrderBook;
+
+/// Anyone can open the order book of a pair, makers then choose to list in it
+#[derive(Accounts)]
+pub struct InitializeOrderBook<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub mint_a: InterfaceAccount<'info, Mint>,
+    pub mint_b: InterfaceAccount<'info, Mint>,
+    #[account(
+        init,
+        payer = payer,
+        seeds = [b"book", mint_a.key().as_ref(), mint_b.key().as_ref()],
+        bump,
+        space = 8 + OrderBook::INIT_SPACE,
+    )]
+    pub order_book: Account<'info, OrderBook>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> InitializeOrderBook<'info> {
+    pub fn init_order_book(&mut self, bumps: &InitializeOrderBookBumps) -> Result<()> {
+        self.order_book.set_inner(OrderBook {
+            mint_a: self.mint_a.key(),
+            mint_b: self.mint_b.key(),
+            offers: Vec::new(),
+            bump: bumps.order_book,
+        });
+
+        Ok(())
+    }
+}
@@ -4,7 +4,12 @@ use anchor_spl::token_interface::{
     TransferChecked,
 };
 
-use crate::state::Escrow;
+use crate::{
+    events::EscrowRefunded,
+    instructions::schedule_refund::*,
+    native::{check_token_accounts, is_native, required},
+    state::{check_order_book, Escrow, OrderBook},
+};
 
 #[derive(Accounts)]
 pub struct Refund<'info> {
@@ -16,7 +21,7 @@ pub struct Refund<'info> {
         associated_token::mint = mint_a,
         associated_token::authority = maker,
     )]
-    maker_ata_a: InterfaceAccount<'info, TokenAccount>,
+    maker_ata_a: Option<InterfaceAccount<'info, TokenAccount>>,
     #[account(
         mut,
         close = maker,
@@ -31,13 +36,54 @@ pub struct Refund<'info> {
         associated_token::mint = mint_a,
         associated_token::authority = escrow,
     )]
-    vault: InterfaceAccount<'info, TokenAccount>,
+    vault: Option<InterfaceAccount<'info, TokenAccount>>,
     token_program: Interface<'info, TokenInterface>,
     system_program: Program<'info, System>,
+    #[account(
+        mut,
+        seeds = [b"book", mint_a.key().as_ref(), escrow.mint_b.as_ref()],
+        bump = order_book.bump,
+    )]
+    order_book: Option<Account<'info, OrderBook>>,
+    scheduled_refund: ScheduledRefund<'info>,
 }
 
 impl<'info> Refund<'info> {
     pub fn refund_and_close_vault(&mut self) -> Result<()> {
+        let mint_a = self.mint_a.key();
+        check_token_accounts(&mint_a, &[self.maker_ata_a.is_some(), self.vault.is_some()])?;
+        check_order_book(&self.escrow, self.order_book.is_some())?;
+
+        if let Some(order_book) = &mut self.order_book {
+            order_book.delist(&self.escrow.key());
+        }
+
+        // Native SOL is held by the escrow account, closing it hands everything back
+        let amount = if is_native(&mint_a) {
+            self.escrow.remaining
+        } else {
+            self.refund_tokens()?
+        };
+
+        if let Some(refund_task) = self.escrow.refund_task {
+            self.scheduled_refund
+                .dequeue(refund_task, self.maker.to_account_info())?;
+        }
+
+        emit!(EscrowRefunded {
+            escrow: self.escrow.key(),
+            maker: self.maker.key(),
+            mint_a: self.mint_a.key(),
+            mint_b: self.escrow.mint_b,
+            seed: self.escrow.seed,
+            amount,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    fn refund_tokens(&mut self) -> Result<u64> {
         let signer_seeds: [&[&[u8]]; 1] = [&[
             b"escrow",
             self.maker.key.as_ref(),
@@ -45,23 +91,26 @@ impl<'info> Refund<'info> {
             &[self.escrow.bump],
         ]];
 
+        let vault = required(&self.vault)?;
+        let amount = vault.amount;
+
         let cpi_program = self.token_program.to_account_info();
 
         let cpi_accounts = TransferChecked {
-            from: self.vault.to_account_info(),
-            to: self.maker_ata_a.to_account_info(),
+            from: vault.to_account_info(),
+            to: required(&self.maker_ata_a)?.to_account_info(),
             mint: self.mint_a.to_account_info(),
             authority: self.escrow.to_account_info(),
         };
 
         let cpi_context = CpiContext::new_with_signer(cpi_program, cpi_accounts, &signer_seeds);
 
-        transfer_checked(cpi_context, self.vault.amount, self.mint_a.decimals)?;
+        transfer_checked(cpi_context, amount, self.mint_a.decimals)?;
 
         let cpi_program = self.token_program.to_account_info();
 
         let cpi_accounts = CloseAccount {
-            account: self.vault.to_account_info(),
+            account: vault.to_account_info(),
             destination: self.maker.to_account_info(),
             authority: self.escrow.to_account_info(),
         };
@@ -70,6 +119,6 @@ impl<'info> Refund<'info> {
 
         close_account(cpi_context)?;
 
-        Ok(())
+        Ok(amount)
     }
 }
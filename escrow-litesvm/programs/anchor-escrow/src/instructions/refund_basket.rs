@@ -0,0 +1,93 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{
+    close_account, transfer_checked, CloseAccount, TokenInterface, TransferChecked,
+};
+
+use crate::{
+    error::EscrowError,
+    state::{basket_mint, basket_token_account, token_amount, Basket},
+};
+
+#[derive(Accounts)]
+pub struct RefundBasket<'info> {
+    #[account(mut)]
+    pub maker: Signer<'info>,
+    #[account(
+        mut,
+        close = maker,
+        has_one = maker,
+        seeds = [b"basket", maker.key().as_ref(), basket.seed.to_le_bytes().as_ref()],
+        bump = basket.bump,
+    )]
+    pub basket: Account<'info, Basket>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> RefundBasket<'info> {
+    /// Returns every vault to the maker and closes it.
+    ///
+    /// `remaining_accounts` holds `[mint, vault, maker_ata]` for each leg of `give`, in order.
+    pub fn refund_and_close_vaults(
+        &mut self,
+        remaining_accounts: &'info [AccountInfo<'info>],
+    ) -> Result<()> {
+        let give = self.basket.give.clone();
+        require!(
+            remaining_accounts.len() == give.len() * 3,
+            EscrowError::BasketAccountMismatch
+        );
+
+        let token_program = self.token_program.key();
+        let basket = self.basket.key();
+
+        let seed = self.basket.seed.to_le_bytes();
+        let signer_seeds: [&[&[u8]]; 1] = [&[
+            b"basket",
+            self.maker.key.as_ref(),
+            &seed,
+            &[self.basket.bump],
+        ]];
+
+        for (leg, accounts) in give.iter().zip(remaining_accounts.chunks(3)) {
+            let [mint, vault, maker_ata] = accounts else {
+                return err!(EscrowError::BasketAccountMismatch);
+            };
+
+            let decimals = basket_mint(mint, leg, &token_program)?;
+            basket_token_account(vault, &basket, leg, &token_program)?;
+            basket_token_account(maker_ata, &self.maker.key(), leg, &token_program)?;
+
+            let cpi_accounts = TransferChecked {
+                from: vault.clone(),
+                to: maker_ata.clone(),
+                mint: mint.clone(),
+                authority: self.basket.to_account_info(),
+            };
+
+            let cpi_context = CpiContext::new_with_signer(
+                self.token_program.to_account_info(),
+                cpi_accounts,
+                &signer_seeds,
+            );
+
+            transfer_checked(cpi_context, token_amount(vault)?, decimals)?;
+
+            let cpi_accounts = CloseAccount {
+                account: vault.clone(),
+                destination: self.maker.to_account_info(),
+                authority: self.basket.to_account_info(),
+            };
+
+            let cpi_context = CpiContext::new_with_signer(
+                self.token_program.to_account_info(),
+                cpi_accounts,
+                &signer_seeds,
+            );
+
+            close_account(cpi_context)?;
+        }
+
+        Ok(())
+    }
+}
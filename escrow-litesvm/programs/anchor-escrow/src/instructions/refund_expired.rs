@@ -0,0 +1,148 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{
+    close_account, transfer_checked, CloseAccount, Mint, TokenAccount, TokenInterface,
+    TransferChecked,
+};
+
+use crate::{
+    error::EscrowError,
+    events::EscrowRefunded,
+    instructions::schedule_refund::*,
+    native::{check_token_accounts, is_native, release_lamports, required},
+    state::{check_order_book, Escrow, OrderBook},
+};
+
+/// `Refund` without the maker's signature, open to anyone once the escrow has expired
+#[derive(Accounts)]
+pub struct RefundExpired<'info> {
+    #[account(mut)]
+    pub maker: SystemAccount<'info>,
+    pub mint_a: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = mint_a,
+        associated_token::authority = maker,
+    )]
+    pub maker_ata_a: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        close = maker,
+        has_one = mint_a,
+        has_one = maker,
+        seeds = [b"escrow", maker.key().as_ref(), escrow.seed.to_le_bytes().as_ref()],
+        bump = escrow.bump,
+    )]
+    pub escrow: Account<'info, Escrow>,
+    #[account(
+        mut,
+        associated_token::mint = mint_a,
+        associated_token::authority = escrow,
+    )]
+    pub vault: Option<InterfaceAccount<'info, TokenAccount>>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+    #[account(
+        mut,
+        seeds = [b"book", mint_a.key().as_ref(), escrow.mint_b.as_ref()],
+        bump = order_book.bump,
+    )]
+    pub order_book: Option<Account<'info, OrderBook>>,
+    /// Receives the escrow's crank reward, left out by tuktuk which pays its own
+    #[account(mut)]
+    pub cranker: Option<Signer<'info>>,
+    /// Dequeued when a cranker beats the task to the refund
+    pub scheduled_refund: ScheduledRefund<'info>,
+}
+
+impl<'info> RefundExpired<'info> {
+    pub fn refund_expired(&mut self) -> Result<()> {
+        let mint_a = self.mint_a.key();
+        check_token_accounts(&mint_a, &[self.maker_ata_a.is_some(), self.vault.is_some()])?;
+        check_order_book(&self.escrow, self.order_book.is_some())?;
+
+        let current_time = Clock::get()?.unix_timestamp;
+        require!(
+            self.escrow
+                .expires_at
+                .is_some_and(|expires_at| current_time > expires_at),
+            EscrowError::EscrowNotExpired
+        );
+
+        if let Some(order_book) = &mut self.order_book {
+            order_book.delist(&self.escrow.key());
+        }
+
+        // Native SOL is held by the escrow account, closing it hands everything back
+        let amount = if is_native(&mint_a) {
+            self.escrow.remaining
+        } else {
+            self.refund_tokens()?
+        };
+
+        // Paid out of the escrow's rent, the rest goes back to the maker on close.
+        // Tuktuk runs the task without a cranker and closes the task itself
+        if let Some(cranker) = &self.cranker {
+            release_lamports(
+                &self.escrow.to_account_info(),
+                &cranker.to_account_info(),
+                self.escrow.crank_reward,
+            )?;
+
+            if let Some(refund_task) = self.escrow.refund_task {
+                self.scheduled_refund
+                    .dequeue(refund_task, self.maker.to_account_info())?;
+            }
+        }
+
+        emit!(EscrowRefunded {
+            escrow: self.escrow.key(),
+            maker: self.maker.key(),
+            mint_a: self.mint_a.key(),
+            mint_b: self.escrow.mint_b,
+            seed: self.escrow.seed,
+            amount,
+            timestamp: current_time,
+        });
+
+        Ok(())
+    }
+
+    fn refund_tokens(&mut self) -> Result<u64> {
+        let signer_seeds: [&[&[u8]]; 1] = [&[
+            b"escrow",
+            self.maker.key.as_ref(),
+            &self.escrow.seed.to_le_bytes()[..],
+            &[self.escrow.bump],
+        ]];
+
+        let vault = required(&self.vault)?;
+        let amount = vault.amount;
+
+        let cpi_program = self.token_program.to_account_info();
+
+        let cpi_accounts = TransferChecked {
+            from: vault.to_account_info(),
+            to: required(&self.maker_ata_a)?.to_account_info(),
+            mint: self.mint_a.to_account_info(),
+            authority: self.escrow.to_account_info(),
+        };
+
+        let cpi_context = CpiContext::new_with_signer(cpi_program, cpi_accounts, &signer_seeds);
+
+        transfer_checked(cpi_context, amount, self.mint_a.decimals)?;
+
+        let cpi_program = self.token_program.to_account_info();
+
+        let cpi_accounts = CloseAccount {
+            account: vault.to_account_info(),
+            destination: self.maker.to_account_info(),
+            authority: self.escrow.to_account_info(),
+        };
+
+        let cpi_context = CpiContext::new_with_signer(cpi_program, cpi_accounts, &signer_seeds);
+
+        close_account(cpi_context)?;
+
+        Ok(amount)
+    }
+}
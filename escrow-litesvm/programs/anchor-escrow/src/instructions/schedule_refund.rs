@@ -0,0 +1,207 @@
+use anchor_lang::{prelude::*, solana_program::instruction::Instruction, InstructionData};
+use anchor_spl::token_interface::{Mint, TokenAccount, TokenInterface};
+use tuktuk_program::{
+    compile_transaction,
+    tuktuk::{
+        cpi::{
+            accounts::{DequeueTaskV0, QueueTaskV0},
+            dequeue_task_v0, queue_task_v0,
+        },
+        program::Tuktuk,
+        types::TriggerV0,
+    },
+    types::QueueTaskArgsV0,
+    TransactionSourceV0,
+};
+
+use crate::{
+    error::EscrowError,
+    state::{Escrow, OrderBook},
+};
+
+/// Queues `refund_expired` on a tuktuk task queue for the moment the escrow expires.
+///
+/// The task queue must list this program's `queue_authority` PDA as a queue
+/// authority. The maker pays for the task, the queue's crankers are paid by tuktuk.
+/// A refund is scheduled once, `update` then keeps the expiry it runs at.
+///
+/// This is not part of `make`: most escrows never expire or get refunded by their
+/// maker, and they should not have to pass tuktuk's accounts or pick a free task id
+/// in someone's queue. Once scheduled, closing the escrow dequeues the task, see
+/// [`ScheduledRefund`].
+#[derive(Accounts)]
+pub struct ScheduleRefund<'info> {
+    #[account(mut)]
+    pub maker: Signer<'info>,
+    pub mint_a: InterfaceAccount<'info, Mint>,
+    #[account(
+        associated_token::mint = mint_a,
+        associated_token::authority = maker,
+    )]
+    pub maker_ata_a: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        has_one = mint_a,
+        has_one = maker,
+        seeds = [b"escrow", maker.key().as_ref(), escrow.seed.to_le_bytes().as_ref()],
+        bump = escrow.bump,
+    )]
+    pub escrow: Account<'info, Escrow>,
+    #[account(
+        associated_token::mint = mint_a,
+        associated_token::authority = escrow,
+    )]
+    pub vault: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        seeds = [b"book", mint_a.key().as_ref(), escrow.mint_b.as_ref()],
+        bump = order_book.bump,
+    )]
+    pub order_book: Option<Account<'info, OrderBook>>,
+    pub token_program: Interface<'info, TokenInterface>,
+    /// CHECK: Checked by tuktuk in the CPI
+    #[account(mut)]
+    pub task_queue: UncheckedAccount<'info>,
+    /// CHECK: Checked by tuktuk in the CPI
+    pub task_queue_authority: UncheckedAccount<'info>,
+    /// CHECK: Initialized by tuktuk in the CPI
+    #[account(mut)]
+    pub task: UncheckedAccount<'info>,
+    /// CHECK: Via seeds
+    #[account(
+        mut,
+        seeds = [b"queue_authority"],
+        bump,
+    )]
+    pub queue_authority: UncheckedAccount<'info>,
+    pub system_program: Program<'info, System>,
+    pub tuktuk_program: Program<'info, Tuktuk>,
+}
+
+/// The escrow's scheduled refund, left out for an escrow without one. Closing an
+/// escrow with a `refund_task` dequeues it, or the task would keep the maker's rent
+/// and fire against a closed escrow.
+#[derive(Accounts)]
+pub struct ScheduledRefund<'info> {
+    /// CHECK: The escrow's `refund_task`, the rest is checked by tuktuk in the CPI
+    #[account(mut)]
+    pub task: Option<UncheckedAccount<'info>>,
+    /// CHECK: Checked by tuktuk in the CPI
+    #[account(mut)]
+    pub task_queue: Option<UncheckedAccount<'info>>,
+    /// CHECK: Checked by tuktuk in the CPI
+    pub task_queue_authority: Option<UncheckedAccount<'info>>,
+    /// CHECK: Checked against the PDA before it signs
+    pub queue_authority: Option<UncheckedAccount<'info>>,
+    pub tuktuk_program: Option<Program<'info, Tuktuk>>,
+}
+
+impl<'info> ScheduledRefund<'info> {
+    /// Dequeues `refund_task`, its rent goes back to the maker who paid for it
+    pub fn dequeue(&self, refund_task: Pubkey, maker: AccountInfo<'info>) -> Result<()> {
+        let (
+            Some(task),
+            Some(task_queue),
+            Some(task_queue_authority),
+            Some(queue_authority),
+            Some(tuktuk_program),
+        ) = (
+            &self.task,
+            &self.task_queue,
+            &self.task_queue_authority,
+            &self.queue_authority,
+            &self.tuktuk_program,
+        )
+        else {
+            return err!(EscrowError::MissingRefundTask);
+        };
+        require_keys_eq!(task.key(), refund_task, EscrowError::MissingRefundTask);
+
+        let (address, bump) = Pubkey::find_program_address(&[b"queue_authority"], &crate::ID);
+        require_keys_eq!(
+            queue_authority.key(),
+            address,
+            EscrowError::MissingRefundTask
+        );
+
+        dequeue_task_v0(CpiContext::new_with_signer(
+            tuktuk_program.to_account_info(),
+            DequeueTaskV0 {
+                queue_authority: queue_authority.to_account_info(),
+                rent_refund: maker,
+                task_queue_authority: task_queue_authority.to_account_info(),
+                task_queue: task_queue.to_account_info(),
+                task: task.to_account_info(),
+            },
+            &[&[b"queue_authority", &[bump]]],
+        ))
+    }
+}
+
+impl<'info> ScheduleRefund<'info> {
+    pub fn schedule_refund(&mut self, task_id: u16, bumps: &ScheduleRefundBumps) -> Result<()> {
+        let expires_at = self.escrow.expires_at.ok_or(EscrowError::MissingExpiry)?;
+        require!(
+            self.escrow.refund_task.is_none(),
+            EscrowError::RefundScheduled
+        );
+
+        let (compiled_tx, _) = compile_transaction(
+            vec![Instruction {
+                program_id: crate::ID,
+                accounts: crate::__cpi_client_accounts_refund_expired::RefundExpired {
+                    maker: self.maker.to_account_info(),
+                    mint_a: self.mint_a.to_account_info(),
+                    maker_ata_a: self.maker_ata_a.as_ref().map(|ata| ata.to_account_info()),
+                    escrow: self.escrow.to_account_info(),
+                    vault: self.vault.as_ref().map(|vault| vault.to_account_info()),
+                    token_program: self.token_program.to_account_info(),
+                    system_program: self.system_program.to_account_info(),
+                    order_book: self.order_book.as_ref().map(|book| book.to_account_info()),
+                    cranker: None,
+                    // The task is running, tuktuk closes it afterwards
+                    scheduled_refund:
+                        crate::__cpi_client_accounts_scheduled_refund::ScheduledRefund {
+                            task: None,
+                            task_queue: None,
+                            task_queue_authority: None,
+                            queue_authority: None,
+                            tuktuk_program: None,
+                        },
+                }
+                .to_account_metas(None),
+                data: crate::instruction::RefundExpired {}.data(),
+            }],
+            vec![],
+        )?;
+
+        queue_task_v0(
+            CpiContext::new_with_signer(
+                self.tuktuk_program.to_account_info(),
+                QueueTaskV0 {
+                    payer: self.maker.to_account_info(),
+                    queue_authority: self.queue_authority.to_account_info(),
+                    task_queue: self.task_queue.to_account_info(),
+                    task_queue_authority: self.task_queue_authority.to_account_info(),
+                    task: self.task.to_account_info(),
+                    system_program: self.system_program.to_account_info(),
+                },
+                &[&[b"queue_authority", &[bumps.queue_authority]]],
+            ),
+            QueueTaskArgsV0 {
+                // Refunds only open once the expiry has passed
+                trigger: TriggerV0::Timestamp(
+                    expires_at.checked_add(1).ok_or(EscrowError::MathOverflow)?,
+                ),
+                transaction: TransactionSourceV0::CompiledV0(compiled_tx),
+                crank_reward: None,
+                free_tasks: 0,
+                id: task_id,
+                description: "refund expired escrow".to_string(),
+            },
+        )?;
+
+        self.escrow.refund_task = Some(self.task.key());
+
+        Ok(())
+    }
+}
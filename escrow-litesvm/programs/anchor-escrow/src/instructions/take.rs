@@ -1,19 +1,23 @@
-use anchor_lang::prelude::*;
+use anchor_lang::{
+    prelude::*,
+    solana_program::{instruction::Instruction, program::invoke},
+};
 use anchor_spl::{
-    associated_token::AssociatedToken,
+    associated_token::{create_idempotent, AssociatedToken, Create},
     token_interface::{
         close_account, transfer_checked, CloseAccount, Mint, TokenAccount, TokenInterface,
         TransferChecked,
     },
 };
 
-use crate::state::Escrow;
-
-#[error_code]
-pub enum EscrowError {
-    #[msg("Take instruction can only be called 5 days after the escrow was created")]
-    TakeTooEarly,
-}
+use crate::{
+    error::EscrowError,
+    events::EscrowTaken,
+    instructions::schedule_refund::*,
+    native::{check_token_accounts, is_native, release_lamports, required, send_lamports},
+    oracle::read_price,
+    state::{check_order_book, Config, Escrow, OrderBook},
+};
 
 //Create context
 #[derive(Accounts)]
@@ -30,23 +34,22 @@ pub struct Take<'info> {
         associated_token::mint = mint_a,
         associated_token::authority = taker,
     )]
-    pub taker_ata_a: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub taker_ata_a: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
     #[account(
         mut,
         associated_token::mint = mint_b,
         associated_token::authority = taker,
     )]
-    pub taker_ata_b: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub taker_ata_b: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
     #[account(
         init_if_needed,
         payer = taker,
         associated_token::mint = mint_b,
         associated_token::authority = maker,
     )]
-    pub maker_ata_b: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub maker_ata_b: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
     #[account(
         mut,
-        close = maker,
         has_one = maker,
         has_one = mint_a,
         has_one = mint_b,
@@ -59,49 +62,336 @@ pub struct Take<'info> {
         associated_token::mint = mint_a,
         associated_token::authority = escrow,
     )]
-    pub vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    /// Only needed for an escrow that charges a fee or is priced by an oracle
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Option<Box<Account<'info, Config>>>,
+    /// CHECK: Checked against the config when a fee is charged
+    #[account(mut)]
+    pub treasury: Option<UncheckedAccount<'info>>,
+    /// CHECK: ATA of the treasury for an SPL mint B, created by the take if the
+    /// escrow charges a fee, which also checks its address
+    #[account(mut)]
+    pub treasury_ata_b: Option<UncheckedAccount<'info>>,
     pub associated_token_program: Program<'info, AssociatedToken>,
     pub token_program: Interface<'info, TokenInterface>,
     pub system_program: Program<'info, System>,
+    #[account(
+        mut,
+        seeds = [b"book", mint_a.key().as_ref(), mint_b.key().as_ref()],
+        bump = order_book.bump,
+    )]
+    pub order_book: Option<Box<Account<'info, OrderBook>>>,
+    /// CHECK: The escrow's price feed, checked against the escrow and the config
+    pub oracle_feed: Option<UncheckedAccount<'info>>,
+    /// Dequeued by the final fill
+    pub scheduled_refund: ScheduledRefund<'info>,
 }
 
 //Deposit tokens from taker to maker
 //Transfer tokens from vault to taker
-//Close vault account
-
-// 5 days in seconds
-const FIVE_DAYS_IN_SECONDS: i64 = 5 * 24 * 60 * 60;
+//Close vault and escrow once fully filled
 
 impl<'info> Take<'info> {
-    pub fn check_time_passed(&self) -> Result<()> {
+    pub fn check_take_window(&self) -> Result<()> {
         let clock = Clock::get()?;
         let current_time = clock.unix_timestamp;
-        let escrow_created = self.escrow.created_at;
 
         require!(
-            current_time >= escrow_created + FIVE_DAYS_IN_SECONDS,
+            current_time >= self.escrow.lock_until,
             EscrowError::TakeTooEarly
         );
 
+        if let Some(expires_at) = self.escrow.expires_at {
+            require!(current_time <= expires_at, EscrowError::EscrowExpired);
+        }
+
+        Ok(())
+    }
+
+    /// Asks the auction's or the oracle's current price. Both are only taken whole
+    /// so nothing has been filled yet
+    pub fn reprice(&mut self) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+
+        let receive = match (&self.escrow.auction, &self.escrow.oracle) {
+            (Some(auction), _) => auction.price_at(now),
+            (None, Some(oracle)) => {
+                let feed = self
+                    .oracle_feed
+                    .as_ref()
+                    .filter(|feed| feed.key() == oracle.feed)
+                    .ok_or(EscrowError::InvalidOracleFeed)?;
+                let oracle_program = self
+                    .config
+                    .as_ref()
+                    .and_then(|config| config.oracle_program);
+                let price = read_price(feed, oracle_program)?;
+
+                oracle.quote(
+                    &price,
+                    now,
+                    self.escrow.remaining,
+                    self.mint_a.decimals,
+                    self.mint_b.decimals,
+                )?
+            }
+            (None, None) => return Ok(()),
+        };
+        self.escrow.receive = receive;
+
+        Ok(())
+    }
+
+    pub fn check_partial_fill(&self) -> Result<()> {
+        require!(
+            self.escrow.auction.is_none() && self.escrow.oracle.is_none(),
+            EscrowError::WholeFillOnly
+        );
+
+        Ok(())
+    }
+
+    /// Swaps into the taker's mint B account through `remaining_accounts`: the swap
+    /// program, which the config has to allow, then the accounts of its instruction.
+    /// The taker's signature carries over to the swap, the escrow's never does.
+    /// Only vetted programs get to run with the taker's accounts, so a front end
+    /// cannot slip in one that drains them.
+    pub fn route(
+        &mut self,
+        remaining_accounts: &[AccountInfo<'info>],
+        route_data: Vec<u8>,
+        amount: u64,
+    ) -> Result<()> {
+        require!(!is_native(&self.mint_b.key()), EscrowError::InvalidRoute);
+        let (swap_program, accounts) = remaining_accounts
+            .split_first()
+            .ok_or(EscrowError::InvalidRoute)?;
+        require!(swap_program.key() != crate::ID, EscrowError::InvalidRoute);
+        require!(
+            self.config
+                .as_ref()
+                .is_some_and(|config| config.swap_programs.contains(&swap_program.key())),
+            EscrowError::InvalidRoute
+        );
+
+        let before = required(&self.taker_ata_b)?.amount;
+
+        let instruction = Instruction {
+            program_id: swap_program.key(),
+            accounts: accounts
+                .iter()
+                .map(|account| AccountMeta {
+                    pubkey: account.key(),
+                    is_signer: account.is_signer,
+                    is_writable: account.is_writable,
+                })
+                .collect(),
+            data: route_data,
+        };
+        invoke(&instruction, remaining_accounts)?;
+
+        let taker_ata_b = self
+            .taker_ata_b
+            .as_mut()
+            .ok_or(EscrowError::InvalidTokenAccounts)?;
+        taker_ata_b.reload()?;
+
+        // Whatever the route produced beyond `amount` stays with the taker
+        let produced = taker_ata_b.amount.saturating_sub(before);
+        require!(produced >= amount, EscrowError::RouteShortfall);
+
+        Ok(())
+    }
+
+    pub fn check_max_pay(&self, amount: u64, max_pay: Option<u64>) -> Result<()> {
+        if let Some(max_pay) = max_pay {
+            require!(amount <= max_pay, EscrowError::MaxPayExceeded);
+        }
+
+        Ok(())
+    }
+
+    /// `proof` is only read when the escrow is restricted to a Merkle root
+    pub fn check_taker(&self, proof: &[[u8; 32]]) -> Result<()> {
+        require!(
+            self.escrow.allowlist.allows(self.taker.key, proof),
+            EscrowError::TakerNotAllowed
+        );
+
         Ok(())
     }
 
-    pub fn deposit(&mut self) -> Result<()> {
+    pub fn fill(&mut self, amount: u64) -> Result<()> {
+        check_token_accounts(
+            &self.mint_a.key(),
+            &[self.taker_ata_a.is_some(), self.vault.is_some()],
+        )?;
+        check_token_accounts(
+            &self.mint_b.key(),
+            &[self.taker_ata_b.is_some(), self.maker_ata_b.is_some()],
+        )?;
+        check_order_book(&self.escrow, self.order_book.is_some())?;
+
+        let released = self.escrow.fill_amount(amount)?;
+        let outstanding = self.escrow.outstanding()?;
+        let is_final_fill = amount == outstanding;
+
+        let fee = self.deposit(amount)?;
+
+        // The last fill takes whatever is left in the vault
+        let released = match (is_final_fill, &self.vault) {
+            (true, Some(vault)) => vault.amount,
+            (true, None) => self.escrow.remaining,
+            (false, _) => released,
+        };
+        self.withdraw(released)?;
+
+        emit!(EscrowTaken {
+            escrow: self.escrow.key(),
+            maker: self.maker.key(),
+            taker: self.taker.key(),
+            mint_a: self.mint_a.key(),
+            mint_b: self.mint_b.key(),
+            seed: self.escrow.seed,
+            amount_paid: amount,
+            fee,
+            amount_released: released,
+            outstanding: outstanding - amount,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        if is_final_fill {
+            if let Some(order_book) = &mut self.order_book {
+                order_book.delist(&self.escrow.key());
+            }
+            if self.vault.is_some() {
+                self.close_vault()?;
+            }
+            if let Some(refund_task) = self.escrow.refund_task {
+                self.scheduled_refund
+                    .dequeue(refund_task, self.maker.to_account_info())?;
+            }
+            return self.escrow.close(self.maker.to_account_info());
+        }
+
+        self.escrow.filled = self
+            .escrow
+            .filled
+            .checked_add(amount)
+            .ok_or(EscrowError::MathOverflow)?;
+        self.escrow.remaining = self
+            .escrow
+            .remaining
+            .checked_sub(released)
+            .ok_or(EscrowError::MathOverflow)?;
+
+        if let Some(order_book) = &mut self.order_book {
+            order_book.relist(self.escrow.key(), &self.escrow)?;
+        }
+
+        Ok(())
+    }
+
+    /// Pays the maker, minus the escrow's protocol fee which goes to the treasury.
+    ///
+    /// Returns the fee.
+    pub fn deposit(&mut self, amount: u64) -> Result<u64> {
+        let fee = self.escrow.fee(amount)?;
+        let to_maker = amount.checked_sub(fee).ok_or(EscrowError::MathOverflow)?;
+
+        if is_native(&self.mint_b.key()) {
+            check_token_accounts(&self.mint_b.key(), &[self.treasury_ata_b.is_some()])?;
+
+            send_lamports(
+                self.taker.to_account_info(),
+                self.maker.to_account_info(),
+                self.system_program.to_account_info(),
+                to_maker,
+            )?;
+
+            if fee > 0 {
+                send_lamports(
+                    self.taker.to_account_info(),
+                    self.treasury()?,
+                    self.system_program.to_account_info(),
+                    fee,
+                )?;
+            }
+
+            return Ok(fee);
+        }
+
+        self.transfer_b(required(&self.maker_ata_b)?.to_account_info(), to_maker)?;
+
+        if fee > 0 {
+            let treasury = self.treasury()?;
+            let treasury_ata_b = required(&self.treasury_ata_b)?.to_account_info();
+            self.create_treasury_ata_b(&treasury_ata_b, treasury)?;
+            self.transfer_b(treasury_ata_b, fee)?;
+        }
+
+        Ok(fee)
+    }
+
+    /// The config's treasury, the only account a fee can be paid to
+    fn treasury(&self) -> Result<AccountInfo<'info>> {
+        let config = self.config.as_ref().ok_or(EscrowError::InvalidTreasury)?;
+        let treasury = self.treasury.as_ref().ok_or(EscrowError::InvalidTreasury)?;
+        require_keys_eq!(
+            treasury.key(),
+            config.treasury,
+            EscrowError::InvalidTreasury
+        );
+
+        Ok(treasury.to_account_info())
+    }
+
+    /// The taker pays for the treasury's ATA when it does not exist yet
+    fn create_treasury_ata_b(
+        &self,
+        treasury_ata_b: &AccountInfo<'info>,
+        treasury: AccountInfo<'info>,
+    ) -> Result<()> {
+        let cpi_accounts = Create {
+            payer: self.taker.to_account_info(),
+            associated_token: treasury_ata_b.clone(),
+            authority: treasury,
+            mint: self.mint_b.to_account_info(),
+            system_program: self.system_program.to_account_info(),
+            token_program: self.token_program.to_account_info(),
+        };
+
+        create_idempotent(CpiContext::new(
+            self.associated_token_program.to_account_info(),
+            cpi_accounts,
+        ))
+    }
+
+    fn transfer_b(&self, to: AccountInfo<'info>, amount: u64) -> Result<()> {
         let cpi_program = self.token_program.to_account_info();
 
         let cpi_accounts = TransferChecked {
-            from: self.taker_ata_b.to_account_info(),
-            to: self.maker_ata_b.to_account_info(),
+            from: required(&self.taker_ata_b)?.to_account_info(),
+            to,
             authority: self.taker.to_account_info(),
             mint: self.mint_b.to_account_info(),
         };
 
         let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
 
-        transfer_checked(cpi_ctx, self.escrow.receive, self.mint_b.decimals)
+        transfer_checked(cpi_ctx, amount, self.mint_b.decimals)
     }
 
-    pub fn withdraw_and_close_vault(&mut self) -> Result<()> {
+    pub fn withdraw(&mut self, amount: u64) -> Result<()> {
+        if is_native(&self.mint_a.key()) {
+            return release_lamports(
+                &self.escrow.to_account_info(),
+                &self.taker.to_account_info(),
+                amount,
+            );
+        }
+
         let signer_seeds: [&[&[u8]]; 1] = [&[
             b"escrow",
             self.maker.key.as_ref(),
@@ -112,20 +402,29 @@ impl<'info> Take<'info> {
         let cpi_program = self.token_program.to_account_info();
 
         let cpi_accounts = TransferChecked {
-            from: self.vault.to_account_info(),
-            to: self.taker_ata_a.to_account_info(),
+            from: required(&self.vault)?.to_account_info(),
+            to: required(&self.taker_ata_a)?.to_account_info(),
             authority: self.escrow.to_account_info(),
             mint: self.mint_a.to_account_info(),
         };
 
         let cpi_context = CpiContext::new_with_signer(cpi_program, cpi_accounts, &signer_seeds);
 
-        transfer_checked(cpi_context, self.vault.amount, self.mint_a.decimals)?;
+        transfer_checked(cpi_context, amount, self.mint_a.decimals)
+    }
+
+    pub fn close_vault(&mut self) -> Result<()> {
+        let signer_seeds: [&[&[u8]]; 1] = [&[
+            b"escrow",
+            self.maker.key.as_ref(),
+            &self.escrow.seed.to_le_bytes()[..],
+            &[self.escrow.bump],
+        ]];
 
         let cpi_program = self.token_program.to_account_info();
 
         let cpi_accounts = CloseAccount {
-            account: self.vault.to_account_info(),
+            account: required(&self.vault)?.to_account_info(),
             destination: self.maker.to_account_info(),
             authority: self.escrow.to_account_info(),
         };
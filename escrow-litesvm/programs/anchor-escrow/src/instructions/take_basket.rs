@@ -0,0 +1,145 @@
+use anchor_lang::prelude::*;
+use anchor_spl::{
+    associated_token::{create_idempotent, AssociatedToken, Create},
+    token_interface::{close_account, transfer_checked, CloseAccount, TokenInterface, TransferChecked},
+};
+
+use crate::{
+    error::EscrowError,
+    state::{basket_mint, basket_token_account, token_amount, Basket},
+};
+
+#[derive(Accounts)]
+pub struct TakeBasket<'info> {
+    #[account(mut)]
+    pub taker: Signer<'info>,
+    #[account(mut)]
+    pub maker: SystemAccount<'info>,
+    #[account(
+        mut,
+        close = maker,
+        has_one = maker,
+        seeds = [b"basket", maker.key().as_ref(), basket.seed.to_le_bytes().as_ref()],
+        bump = basket.bump,
+    )]
+    pub basket: Account<'info, Basket>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> TakeBasket<'info> {
+    /// Pays every leg the maker asks for and empties every vault into the taker's
+    /// accounts, a single failing leg reverts the whole take.
+    ///
+    /// `remaining_accounts` holds `[mint, vault, taker_ata]` for each leg of `give`,
+    /// followed by `[mint, taker_ata, maker_ata]` for each leg of `receive`.
+    pub fn swap(&mut self, remaining_accounts: &'info [AccountInfo<'info>]) -> Result<()> {
+        let give = self.basket.give.clone();
+        let receive = self.basket.receive.clone();
+        require!(
+            remaining_accounts.len() == (give.len() + receive.len()) * 3,
+            EscrowError::BasketAccountMismatch
+        );
+        let (give_accounts, receive_accounts) = remaining_accounts.split_at(give.len() * 3);
+
+        let token_program = self.token_program.key();
+
+        for (leg, accounts) in receive.iter().zip(receive_accounts.chunks(3)) {
+            let [mint, taker_ata, maker_ata] = accounts else {
+                return err!(EscrowError::BasketAccountMismatch);
+            };
+
+            let decimals = basket_mint(mint, leg, &token_program)?;
+            basket_token_account(taker_ata, &self.taker.key(), leg, &token_program)?;
+            basket_token_account(maker_ata, &self.maker.key(), leg, &token_program)?;
+
+            self.create_ata(maker_ata, &self.maker.to_account_info(), mint)?;
+
+            let cpi_accounts = TransferChecked {
+                from: taker_ata.clone(),
+                to: maker_ata.clone(),
+                authority: self.taker.to_account_info(),
+                mint: mint.clone(),
+            };
+
+            let cpi_ctx = CpiContext::new(self.token_program.to_account_info(), cpi_accounts);
+
+            transfer_checked(cpi_ctx, leg.amount, decimals)?;
+        }
+
+        let seed = self.basket.seed.to_le_bytes();
+        let signer_seeds: [&[&[u8]]; 1] = [&[
+            b"basket",
+            self.maker.key.as_ref(),
+            &seed,
+            &[self.basket.bump],
+        ]];
+        let basket = self.basket.key();
+
+        for (leg, accounts) in give.iter().zip(give_accounts.chunks(3)) {
+            let [mint, vault, taker_ata] = accounts else {
+                return err!(EscrowError::BasketAccountMismatch);
+            };
+
+            let decimals = basket_mint(mint, leg, &token_program)?;
+            basket_token_account(vault, &basket, leg, &token_program)?;
+            basket_token_account(taker_ata, &self.taker.key(), leg, &token_program)?;
+
+            self.create_ata(taker_ata, &self.taker.to_account_info(), mint)?;
+
+            let cpi_accounts = TransferChecked {
+                from: vault.clone(),
+                to: taker_ata.clone(),
+                authority: self.basket.to_account_info(),
+                mint: mint.clone(),
+            };
+
+            let cpi_context = CpiContext::new_with_signer(
+                self.token_program.to_account_info(),
+                cpi_accounts,
+                &signer_seeds,
+            );
+
+            transfer_checked(cpi_context, token_amount(vault)?, decimals)?;
+
+            let cpi_accounts = CloseAccount {
+                account: vault.clone(),
+                destination: self.maker.to_account_info(),
+                authority: self.basket.to_account_info(),
+            };
+
+            let cpi_context = CpiContext::new_with_signer(
+                self.token_program.to_account_info(),
+                cpi_accounts,
+                &signer_seeds,
+            );
+
+            close_account(cpi_context)?;
+        }
+
+        Ok(())
+    }
+
+    /// The taker pays for any token account that does not exist yet
+    fn create_ata(
+        &self,
+        account: &AccountInfo<'info>,
+        authority: &AccountInfo<'info>,
+        mint: &AccountInfo<'info>,
+    ) -> Result<()> {
+        let cpi_accounts = Create {
+            payer: self.taker.to_account_info(),
+            associated_token: account.clone(),
+            authority: authority.clone(),
+            mint: mint.clone(),
+            system_program: self.system_program.to_account_info(),
+            token_program: self.token_program.to_account_info(),
+        };
+
+        create_idempotent(CpiContext::new(
+            self.associated_token_program.to_account_info(),
+            cpi_accounts,
+        ))
+    }
+}
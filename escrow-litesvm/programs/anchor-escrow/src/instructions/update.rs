@@ -0,0 +1,196 @@
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{
+    transfer_checked, Mint, TokenAccount, TokenInterface, TransferChecked,
+};
+
+use crate::{
+    error::EscrowError,
+    events::EscrowUpdated,
+    native::{check_token_accounts, is_native, release_lamports, required, send_lamports},
+    state::{check_order_book, Escrow, OrderBook},
+};
+
+#[derive(Accounts)]
+pub struct Update<'info> {
+    #[account(mut)]
+    pub maker: Signer<'info>,
+    pub mint_a: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        associated_token::mint = mint_a,
+        associated_token::authority = maker,
+    )]
+    pub maker_ata_a: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        has_one = mint_a,
+        has_one = maker,
+        seeds = [b"escrow", maker.key().as_ref(), escrow.seed.to_le_bytes().as_ref()],
+        bump = escrow.bump,
+    )]
+    pub escrow: Account<'info, Escrow>,
+    #[account(
+        mut,
+        associated_token::mint = mint_a,
+        associated_token::authority = escrow,
+    )]
+    pub vault: Option<InterfaceAccount<'info, TokenAccount>>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+    #[account(
+        mut,
+        seeds = [b"book", mint_a.key().as_ref(), escrow.mint_b.as_ref()],
+        bump = order_book.bump,
+    )]
+    pub order_book: Option<Account<'info, OrderBook>>,
+}
+
+impl<'info> Update<'info> {
+    /// Changes the price, the deposit and the expiry of an open escrow.
+    ///
+    /// `receive` is the new total amount of mint B, including what earlier fills
+    /// already paid. `expires_at` is left alone when `None` and replaced otherwise,
+    /// `Some(None)` removes the expiry.
+    pub fn update(
+        &mut self,
+        receive: Option<u64>,
+        top_up: u64,
+        withdraw: u64,
+        expires_at: Option<Option<i64>>,
+    ) -> Result<()> {
+        check_token_accounts(
+            &self.mint_a.key(),
+            &[self.maker_ata_a.is_some(), self.vault.is_some()],
+        )?;
+        check_order_book(&self.escrow, self.order_book.is_some())?;
+
+        if let Some(receive) = receive {
+            require!(
+                receive > self.escrow.filled
+                    && self.escrow.auction.is_none()
+                    && self.escrow.oracle.is_none(),
+                EscrowError::InvalidUpdate
+            );
+            self.escrow.receive = receive;
+        }
+
+        if let Some(expires_at) = expires_at {
+            // A queued refund runs at the expiry it was scheduled for
+            require!(
+                self.escrow.refund_task.is_none() || expires_at == self.escrow.expires_at,
+                EscrowError::RefundScheduled
+            );
+            if let Some(expires_at) = expires_at {
+                require!(expires_at >= self.escrow.lock_until, EscrowError::InvalidLockPeriod);
+            }
+            self.escrow.expires_at = expires_at;
+        }
+
+        if top_up > 0 {
+            self.top_up(top_up)?;
+        }
+
+        if withdraw > 0 {
+            self.withdraw(withdraw)?;
+        }
+
+        require!(self.escrow.remaining > 0, EscrowError::InvalidUpdate);
+
+        if let Some(order_book) = &mut self.order_book {
+            order_book.relist(self.escrow.key(), &self.escrow)?;
+        }
+
+        emit!(EscrowUpdated {
+            escrow: self.escrow.key(),
+            maker: self.maker.key(),
+            seed: self.escrow.seed,
+            receive: self.escrow.receive,
+            remaining: self.escrow.remaining,
+            expires_at: self.escrow.expires_at,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    fn top_up(&mut self, amount: u64) -> Result<()> {
+        if is_native(&self.mint_a.key()) {
+            send_lamports(
+                self.maker.to_account_info(),
+                self.escrow.to_account_info(),
+                self.system_program.to_account_info(),
+                amount,
+            )?;
+        } else {
+            let cpi_program = self.token_program.to_account_info();
+
+            let cpi_accounts = TransferChecked {
+                from: required(&self.maker_ata_a)?.to_account_info(),
+                to: required(&self.vault)?.to_account_info(),
+                authority: self.maker.to_account_info(),
+                mint: self.mint_a.to_account_info(),
+            };
+
+            let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+
+            transfer_checked(cpi_ctx, amount, self.mint_a.decimals)?;
+        }
+
+        // A transfer-fee mint credits the vault with less than the maker sent
+        let credited = match &mut self.vault {
+            Some(vault) => {
+                let before = vault.amount;
+                vault.reload()?;
+                vault
+                    .amount
+                    .checked_sub(before)
+                    .ok_or(EscrowError::MathOverflow)?
+            }
+            None => amount,
+        };
+
+        self.escrow.remaining = self
+            .escrow
+            .remaining
+            .checked_add(credited)
+            .ok_or(EscrowError::MathOverflow)?;
+
+        Ok(())
+    }
+
+    fn withdraw(&mut self, amount: u64) -> Result<()> {
+        self.escrow.remaining = self
+            .escrow
+            .remaining
+            .checked_sub(amount)
+            .ok_or(EscrowError::InvalidUpdate)?;
+
+        if is_native(&self.mint_a.key()) {
+            return release_lamports(
+                &self.escrow.to_account_info(),
+                &self.maker.to_account_info(),
+                amount,
+            );
+        }
+
+        let signer_seeds: [&[&[u8]]; 1] = [&[
+            b"escrow",
+            self.maker.key.as_ref(),
+            &self.escrow.seed.to_le_bytes()[..],
+            &[self.escrow.bump],
+        ]];
+
+        let cpi_program = self.token_program.to_account_info();
+
+        let cpi_accounts = TransferChecked {
+            from: required(&self.vault)?.to_account_info(),
+            to: required(&self.maker_ata_a)?.to_account_info(),
+            mint: self.mint_a.to_account_info(),
+            authority: self.escrow.to_account_info(),
+        };
+
+        let cpi_context = CpiContext::new_with_signer(cpi_program, cpi_accounts, &signer_seeds);
+
+        transfer_checked(cpi_context, amount, self.mint_a.decimals)
+    }
+}
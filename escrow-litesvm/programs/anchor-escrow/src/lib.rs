@@ -3,11 +3,16 @@
 
 use anchor_lang::prelude::*;
 
+mod error;
+pub mod events;
 mod instructions;
+pub mod native;
+pub mod oracle;
 mod state;
 mod tests;
 
 use instructions::*;
+use state::BasketLeg;
 
 declare_id!("FircrADQ2wgGuvpm8qneNCfKM7o5zoHTWnDQxngpTQ3J");
 
@@ -15,18 +20,124 @@ declare_id!("FircrADQ2wgGuvpm8qneNCfKM7o5zoHTWnDQxngpTQ3J");
 pub mod anchor_escrow {
     use super::*;
 
-    pub fn make(ctx: Context<Make>, seed: u64, deposit: u64, receive: u64) -> Result<()> {
-        ctx.accounts.init_escrow(seed, receive, &ctx.bumps)?;
+    pub fn initialize_config(
+        ctx: Context<InitializeConfig>,
+        fee_bps: u16,
+        treasury: Pubkey,
+    ) -> Result<()> {
+        ctx.accounts.init_config(fee_bps, treasury, &ctx.bumps)
+    }
+
+    pub fn update_config(
+        ctx: Context<UpdateConfig>,
+        admin: Option<Pubkey>,
+        fee_bps: Option<u16>,
+        treasury: Option<Pubkey>,
+        oracle_program: Option<Option<Pubkey>>,
+    ) -> Result<()> {
+        ctx.accounts.update_config(admin, fee_bps, treasury, oracle_program)
+    }
+
+    pub fn set_swap_programs(ctx: Context<UpdateConfig>, swap_programs: Vec<Pubkey>) -> Result<()> {
+        ctx.accounts.set_swap_programs(swap_programs)
+    }
+
+    pub fn initialize_order_book(ctx: Context<InitializeOrderBook>) -> Result<()> {
+        ctx.accounts.init_order_book(&ctx.bumps)
+    }
+
+    /// Passing the pair's `order_book` lists the escrow in it. `crank_reward` is paid
+    /// to whoever cranks `refund_expired`. With an `auction` or `oracle` price,
+    /// `receive` is ignored and takers pay the price at the time of the take
+    pub fn make(ctx: Context<Make>, seed: u64, args: MakeArgs) -> Result<()> {
+        let deposit = args.deposit;
+        ctx.accounts.init_escrow(seed, args, &ctx.bumps)?;
         ctx.accounts.deposit(deposit)
     }
 
+    pub fn update(
+        ctx: Context<Update>,
+        receive: Option<u64>,
+        top_up: u64,
+        withdraw: u64,
+        expires_at: Option<Option<i64>>,
+    ) -> Result<()> {
+        ctx.accounts.update(receive, top_up, withdraw, expires_at)
+    }
+
     pub fn refund(ctx: Context<Refund>) -> Result<()> {
         ctx.accounts.refund_and_close_vault()
     }
 
-    pub fn take(ctx: Context<Take>) -> Result<()> {
-        ctx.accounts.check_time_passed()?;
-        ctx.accounts.deposit()?;
-        ctx.accounts.withdraw_and_close_vault()
+    /// Permissionless refund once the escrow has expired
+    pub fn refund_expired(ctx: Context<RefundExpired>) -> Result<()> {
+        ctx.accounts.refund_expired()
+    }
+
+    /// Queues `refund_expired` as a tuktuk task, opted into by the maker of an expiring
+    /// escrow. Take and refund dequeue it when they close the escrow
+    pub fn schedule_refund(ctx: Context<ScheduleRefund>, task_id: u16) -> Result<()> {
+        ctx.accounts.schedule_refund(task_id, &ctx.bumps)
+    }
+
+    /// Oracle priced escrows need the `oracle_feed`. The take fails if the escrow asks
+    /// more than `max_pay`
+    pub fn take(ctx: Context<Take>, proof: Vec<[u8; 32]>, max_pay: Option<u64>) -> Result<()> {
+        ctx.accounts.check_taker(&proof)?;
+        ctx.accounts.check_take_window()?;
+        ctx.accounts.reprice()?;
+        let amount = ctx.accounts.escrow.outstanding()?;
+        ctx.accounts.check_max_pay(amount, max_pay)?;
+        ctx.accounts.fill(amount)
+    }
+
+    /// `remaining_accounts`: the swap program, one of the config's `swap_programs`, then
+    /// the accounts of its instruction. The swap runs with `route_data` before the take and has to pay the taker at
+    /// least the outstanding mint B, the rest of its output stays with the taker
+    pub fn take_with_route<'info>(
+        ctx: Context<'_, '_, 'info, 'info, Take<'info>>,
+        proof: Vec<[u8; 32]>,
+        max_pay: Option<u64>,
+        route_data: Vec<u8>,
+    ) -> Result<()> {
+        ctx.accounts.check_taker(&proof)?;
+        ctx.accounts.check_take_window()?;
+        ctx.accounts.reprice()?;
+        let amount = ctx.accounts.escrow.outstanding()?;
+        ctx.accounts.check_max_pay(amount, max_pay)?;
+        ctx.accounts.route(ctx.remaining_accounts, route_data, amount)?;
+        ctx.accounts.fill(amount)
+    }
+
+    pub fn take_partial(ctx: Context<Take>, proof: Vec<[u8; 32]>, amount: u64) -> Result<()> {
+        ctx.accounts.check_taker(&proof)?;
+        ctx.accounts.check_take_window()?;
+        ctx.accounts.check_partial_fill()?;
+        ctx.accounts.fill(amount)
+    }
+
+    /// `remaining_accounts`: `[mint, maker_ata, vault]` for each leg of `give`
+    pub fn make_basket<'info>(
+        ctx: Context<'_, '_, 'info, 'info, MakeBasket<'info>>,
+        seed: u64,
+        give: Vec<BasketLeg>,
+        receive: Vec<BasketLeg>,
+    ) -> Result<()> {
+        ctx.accounts.init_basket(seed, give, receive, &ctx.bumps)?;
+        ctx.accounts.deposit(ctx.remaining_accounts)
+    }
+
+    /// `remaining_accounts`: `[mint, vault, taker_ata]` for each leg of `give`, then
+    /// `[mint, taker_ata, maker_ata]` for each leg of `receive`. Baskets are exempt
+    /// from the protocol fee, the maker receives every leg in full
+    pub fn take_basket<'info>(ctx: Context<'_, '_, 'info, 'info, TakeBasket<'info>>) -> Result<()> {
+        ctx.accounts.swap(ctx.remaining_accounts)
+    }
+
+    /// `remaining_accounts`: `[mint, vault, maker_ata]` for each leg of `give`
+    pub fn refund_basket<'info>(
+        ctx: Context<'_, '_, 'info, 'info, RefundBasket<'info>>,
+    ) -> Result<()> {
+        ctx.accounts.refund_and_close_vaults(ctx.remaining_accounts)
     }
 }
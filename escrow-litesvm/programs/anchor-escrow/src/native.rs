@@ -0,0 +1,58 @@
+//! Native SOL on either side of an escrow.
+//!
+//! Passing the native mint as `mint_a` or `mint_b` switches that side from token
+//! transfers to lamport transfers. The token accounts of a native side are left
+//! out of the instruction: native `mint_a` is held in the escrow account itself,
+//! on top of its rent, and native `mint_b` goes straight from taker to maker.
+
+use anchor_lang::{
+    prelude::*,
+    system_program::{transfer, Transfer},
+};
+
+use crate::error::EscrowError;
+
+pub use anchor_spl::token::spl_token::native_mint::ID as NATIVE_MINT;
+
+pub fn is_native(mint: &Pubkey) -> bool {
+    *mint == NATIVE_MINT
+}
+
+/// Checks that the token accounts of one side are passed for an SPL mint and left out for native SOL
+pub fn check_token_accounts(mint: &Pubkey, present: &[bool]) -> Result<()> {
+    let expected = !is_native(mint);
+    require!(
+        present.iter().all(|&is_present| is_present == expected),
+        EscrowError::InvalidTokenAccounts
+    );
+
+    Ok(())
+}
+
+/// Token account of an SPL side, only call after `check_token_accounts`
+pub fn required<T>(account: &Option<T>) -> Result<&T> {
+    account
+        .as_ref()
+        .ok_or(error!(EscrowError::InvalidTokenAccounts))
+}
+
+/// Sends lamports from a system account that signed the transaction
+pub fn send_lamports<'info>(
+    from: AccountInfo<'info>,
+    to: AccountInfo<'info>,
+    system_program: AccountInfo<'info>,
+    amount: u64,
+) -> Result<()> {
+    let cpi_ctx = CpiContext::new(system_program, Transfer { from, to });
+
+    transfer(cpi_ctx, amount)
+}
+
+/// Moves lamports out of an account owned by this program, the system program
+/// cannot debit it so the balances are changed directly
+pub fn release_lamports(from: &AccountInfo, to: &AccountInfo, amount: u64) -> Result<()> {
+    from.sub_lamports(amount)?;
+    to.add_lamports(amount)?;
+
+    Ok(())
+}
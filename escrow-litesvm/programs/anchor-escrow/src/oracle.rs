@@ -0,0 +1,60 @@
+//! Prices read from an oracle feed.
+//!
+//! A feed is an account owned by the config's `oracle_program` that holds a Pyth
+//! `PriceUpdateV2`. It is decoded here rather than through the Pyth SDK so any
+//! program writing that layout can be configured, and only fully verified updates
+//! are accepted.
+
+use anchor_lang::{prelude::*, solana_program::hash::hash};
+
+use crate::error::EscrowError;
+
+#[derive(AnchorDeserialize)]
+enum VerificationLevel {
+    Partial { _num_signatures: u8 },
+    Full,
+}
+
+/// Mint B per unit of mint A is `price * 10^exponent`, give or take `conf`
+#[derive(AnchorDeserialize, Clone, Debug)]
+pub struct PriceFeedMessage {
+    pub feed_id: [u8; 32],
+    pub price: i64,
+    pub conf: u64,
+    pub exponent: i32,
+    pub publish_time: i64,
+    pub prev_publish_time: i64,
+    pub ema_price: i64,
+    pub ema_conf: u64,
+}
+
+#[derive(AnchorDeserialize)]
+struct PriceUpdateV2 {
+    _write_authority: Pubkey,
+    verification_level: VerificationLevel,
+    price_message: PriceFeedMessage,
+    _posted_slot: u64,
+}
+
+/// Latest price of `feed`, which must be owned by `oracle_program`
+pub fn read_price(feed: &AccountInfo, oracle_program: Option<Pubkey>) -> Result<PriceFeedMessage> {
+    require!(
+        oracle_program.is_some_and(|oracle_program| feed.owner == &oracle_program),
+        EscrowError::InvalidOracleFeed
+    );
+
+    let data = feed.try_borrow_data()?;
+    require!(
+        data.len() >= 8 && data[..8] == hash(b"account:PriceUpdateV2").to_bytes()[..8],
+        EscrowError::InvalidOracleFeed
+    );
+
+    let update = PriceUpdateV2::deserialize(&mut &data[8..])
+        .map_err(|_| error!(EscrowError::InvalidOracleFeed))?;
+    require!(
+        matches!(update.verification_level, VerificationLevel::Full),
+        EscrowError::InvalidOracleFeed
+    );
+
+    Ok(update.price_message)
+}
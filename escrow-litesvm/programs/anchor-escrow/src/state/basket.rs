@@ -0,0 +1,86 @@
+use anchor_lang::prelude::*;
+use anchor_spl::{
+    associated_token::get_associated_token_address_with_program_id,
+    token_interface::{Mint, TokenAccount},
+};
+
+use crate::error::EscrowError;
+
+/// Most mints a basket can hold on either side
+pub const MAX_BASKET_LEGS: usize = 4;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, InitSpace, PartialEq, Eq)]
+pub struct BasketLeg {
+    pub mint: Pubkey,
+    pub amount: u64,
+}
+
+/// Escrow of several mints for several mints, taken all at once.
+///
+/// Each mint the maker gives sits in its own vault, the ATA of the basket for
+/// that mint.
+#[account]
+#[derive(InitSpace, Debug)]
+pub struct Basket {
+    pub seed: u64,
+    pub maker: Pubkey,
+    #[max_len(MAX_BASKET_LEGS)]
+    pub give: Vec<BasketLeg>,
+    #[max_len(MAX_BASKET_LEGS)]
+    pub receive: Vec<BasketLeg>,
+    pub created_at: i64,
+    pub bump: u8,
+}
+
+impl Basket {
+    /// Both sides need between one and `MAX_BASKET_LEGS` legs with distinct mints and non-zero amounts
+    pub fn check_legs(legs: &[BasketLeg]) -> Result<()> {
+        require!(
+            !legs.is_empty() && legs.len() <= MAX_BASKET_LEGS,
+            EscrowError::InvalidBasket
+        );
+
+        for (i, leg) in legs.iter().enumerate() {
+            require!(leg.amount > 0, EscrowError::InvalidBasket);
+            require!(
+                legs[..i].iter().all(|other| other.mint != leg.mint),
+                EscrowError::InvalidBasket
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Checks that `account` is the mint of a leg and returns its decimals
+pub fn basket_mint<'info>(
+    account: &'info AccountInfo<'info>,
+    leg: &BasketLeg,
+    token_program: &Pubkey,
+) -> Result<u8> {
+    require_keys_eq!(account.key(), leg.mint, EscrowError::BasketAccountMismatch);
+    require_keys_eq!(*account.owner, *token_program, EscrowError::BasketAccountMismatch);
+
+    Ok(InterfaceAccount::<Mint>::try_from(account)?.decimals)
+}
+
+/// Checks that `account` is the associated token account of `authority` for the leg's mint
+pub fn basket_token_account(
+    account: &AccountInfo,
+    authority: &Pubkey,
+    leg: &BasketLeg,
+    token_program: &Pubkey,
+) -> Result<()> {
+    require_keys_eq!(
+        account.key(),
+        get_associated_token_address_with_program_id(authority, &leg.mint, token_program),
+        EscrowError::BasketAccountMismatch
+    );
+
+    Ok(())
+}
+
+/// Balance of an existing token account
+pub fn token_amount<'info>(account: &'info AccountInfo<'info>) -> Result<u64> {
+    Ok(InterfaceAccount::<TokenAccount>::try_from(account)?.amount)
+}
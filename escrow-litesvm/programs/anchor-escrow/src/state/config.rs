@@ -0,0 +1,48 @@
+use anchor_lang::prelude::*;
+
+use crate::error::EscrowError;
+
+/// Fees are expressed in basis points of the mint B paid by the taker, at most 10%
+pub const MAX_FEE_BPS: u16 = 1_000;
+
+/// Most swap programs `take_with_route` can be allowed to go through
+pub const MAX_SWAP_PROGRAMS: usize = 4;
+
+/// Program-wide settings, a single PDA at `[b"config"]`
+#[account]
+#[derive(InitSpace, Debug)]
+pub struct Config {
+    /// The only key allowed to change the config
+    pub admin: Pubkey,
+    /// Fee of escrows made from now on, each escrow keeps the fee it was made with.
+    /// Baskets are not charged
+    pub fee_bps: u16,
+    /// Wallet the fee is paid to, in its mint B ATA or in lamports for native SOL
+    pub treasury: Pubkey,
+    /// Owner of the price feeds oracle priced escrows read, none can be taken while unset
+    pub oracle_program: Option<Pubkey>,
+    /// Programs `take_with_route` may swap through, no take can be routed while empty
+    #[max_len(MAX_SWAP_PROGRAMS)]
+    pub swap_programs: Vec<Pubkey>,
+    pub bump: u8,
+}
+
+impl Config {
+    pub fn check_fee_bps(fee_bps: u16) -> Result<()> {
+        require!(fee_bps <= MAX_FEE_BPS, EscrowError::InvalidFee);
+
+        Ok(())
+    }
+
+    /// Fee of an escrow made now, none while the config at `[b"config"]` is not
+    /// initialized
+    pub fn current_fee_bps(config: &AccountInfo) -> Result<u16> {
+        if config.owner != &crate::ID {
+            return Ok(0);
+        }
+
+        let config = Config::try_deserialize(&mut &config.try_borrow_data()?[..])?;
+
+        Ok(config.fee_bps)
+    }
+}
@@ -1,4 +1,9 @@
-use anchor_lang::prelude::*;
+use anchor_lang::{prelude::*, solana_program::hash::hashv};
+
+use crate::{error::EscrowError, oracle::PriceFeedMessage};
+
+/// Fees, oracle spreads and confidence bounds are in basis points
+const BPS: u128 = 10_000;
 
 #[account]
 #[derive(InitSpace, Debug)]
@@ -8,6 +13,213 @@ pub struct Escrow {
     pub mint_a: Pubkey,
     pub mint_b: Pubkey,
     pub receive: u64,
+    pub filled: u64,
+    pub remaining: u64,
     pub created_at: i64,
+    /// Take is rejected before this timestamp
+    pub lock_until: i64,
+    /// Take is rejected after this timestamp, leaving refund as the only way out
+    pub expires_at: Option<i64>,
+    pub allowlist: TakerAllowlist,
+    /// Lamports out of the escrow's rent paid to whoever cranks `refund_expired`
+    pub crank_reward: u64,
+    /// Tuktuk task queued by `schedule_refund`, which fixes the expiry from then on
+    pub refund_task: Option<Pubkey>,
+    /// Protocol fee in bps of every take, fixed from the config when the escrow is made.
+    /// The maker pays it: the taker pays the escrow's price and the treasury's cut
+    /// comes out of the mint B the maker receives
+    pub fee_bps: u16,
+    /// Dutch auction that reprices `receive` at every take, fixed price when `None`
+    pub auction: Option<DutchAuction>,
+    /// Oracle feed that reprices `receive` at every take, fixed price when `None`
+    pub oracle: Option<OraclePricing>,
+    /// Listed in the order book of its pair, which take, update and refund then
+    /// have to be given
+    pub listed: bool,
     pub bump: u8,
 }
+
+impl Escrow {
+    /// Mint B still needed to fill the escrow completely
+    pub fn outstanding(&self) -> Result<u64> {
+        self.receive
+            .checked_sub(self.filled)
+            .ok_or(error!(EscrowError::MathOverflow))
+    }
+
+    /// Mint A released for `amount` of mint B.
+    ///
+    /// The price is taken from what is still outstanding and rounded down, so the
+    /// maker never sells below their price. The fill that completes the escrow
+    /// releases everything left, which sweeps up the rounding dust.
+    pub fn fill_amount(&self, amount: u64) -> Result<u64> {
+        let outstanding = self.outstanding()?;
+        require!(amount > 0 && amount <= outstanding, EscrowError::InvalidFillAmount);
+
+        if amount == outstanding {
+            return Ok(self.remaining);
+        }
+
+        let released = (amount as u128)
+            .checked_mul(self.remaining as u128)
+            .and_then(|product| product.checked_div(outstanding as u128))
+            .ok_or(error!(EscrowError::MathOverflow))?;
+        let released = u64::try_from(released).map_err(|_| error!(EscrowError::MathOverflow))?;
+
+        require!(released > 0, EscrowError::FillTooSmall);
+
+        Ok(released)
+    }
+
+    /// Part of `amount` that goes to the treasury, rounded down in favour of the maker
+    pub fn fee(&self, amount: u64) -> Result<u64> {
+        let fee = (amount as u128)
+            .checked_mul(self.fee_bps as u128)
+            .and_then(|product| product.checked_div(BPS))
+            .ok_or(error!(EscrowError::MathOverflow))?;
+
+        u64::try_from(fee).map_err(|_| error!(EscrowError::MathOverflow))
+    }
+}
+
+/// Price of the whole deposit that decays linearly from `start_price` to
+/// `end_price` of mint B between `start_time` and `end_time`, and holds at either
+/// end outside of them
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, InitSpace, PartialEq, Eq)]
+pub struct DutchAuction {
+    pub start_price: u64,
+    pub end_price: u64,
+    pub start_time: i64,
+    pub end_time: i64,
+}
+
+impl DutchAuction {
+    /// The price only goes down, never to zero, over a non-empty period
+    pub fn is_valid(&self) -> bool {
+        self.start_price >= self.end_price && self.end_price > 0 && self.start_time < self.end_time
+    }
+
+    /// Mint B asked at `now`. The decay is rounded down so the maker never sells
+    /// below the line
+    pub fn price_at(&self, now: i64) -> u64 {
+        if now <= self.start_time {
+            return self.start_price;
+        }
+        if now >= self.end_time {
+            return self.end_price;
+        }
+
+        let elapsed = (now as i128 - self.start_time as i128) as u128;
+        let duration = (self.end_time as i128 - self.start_time as i128) as u128;
+        let decay = (self.start_price - self.end_price) as u128 * elapsed / duration;
+
+        // `elapsed < duration`, so the decay is less than the price range
+        self.start_price - decay as u64
+    }
+}
+
+/// Price of the deposit relative to an oracle feed of mint A in mint B
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, InitSpace, PartialEq, Eq)]
+pub struct OraclePricing {
+    /// Price account owned by the config's oracle program
+    pub feed: Pubkey,
+    /// Added to the oracle price in bps, -100 sells at the oracle price minus 1%
+    pub spread_bps: i16,
+    /// Oldest price accepted, in seconds before the take
+    pub max_age: u32,
+    /// Widest confidence interval accepted, in bps of the price
+    pub max_conf_bps: u16,
+}
+
+impl OraclePricing {
+    /// The spread leaves a positive price and the confidence bound is at most 100%
+    pub fn is_valid(&self) -> bool {
+        i128::from(self.spread_bps) > -(BPS as i128) && u128::from(self.max_conf_bps) <= BPS
+    }
+
+    /// Mint B asked for `remaining` of mint A at `now`, rounded up so the maker never
+    /// sells below the spread
+    pub fn quote(
+        &self,
+        price: &PriceFeedMessage,
+        now: i64,
+        remaining: u64,
+        decimals_a: u8,
+        decimals_b: u8,
+    ) -> Result<u64> {
+        require!(price.price > 0, EscrowError::InvalidOracleFeed);
+        require!(
+            now.saturating_sub(price.publish_time) <= i64::from(self.max_age),
+            EscrowError::StaleOraclePrice
+        );
+        require!(
+            price.conf as u128 * BPS <= self.max_conf_bps as u128 * price.price as u128,
+            EscrowError::OracleConfidenceTooWide
+        );
+
+        // Base units of mint B per base unit of mint A carry this power of ten
+        let exponent = price.exponent + i32::from(decimals_b) - i32::from(decimals_a);
+        let scale = 10u128
+            .checked_pow(exponent.unsigned_abs())
+            .ok_or(error!(EscrowError::MathOverflow))?;
+        let spread = (BPS as i128 + i128::from(self.spread_bps)) as u128;
+
+        let mut numerator = (remaining as u128)
+            .checked_mul(price.price as u128)
+            .and_then(|product| product.checked_mul(spread));
+        let mut denominator = Some(BPS);
+        if exponent >= 0 {
+            numerator = numerator.and_then(|numerator| numerator.checked_mul(scale));
+        } else {
+            denominator = denominator.and_then(|denominator| denominator.checked_mul(scale));
+        }
+
+        let quote = numerator
+            .zip(denominator)
+            .map(|(numerator, denominator)| numerator.div_ceil(denominator))
+            .ok_or(error!(EscrowError::MathOverflow))?;
+
+        u64::try_from(quote).map_err(|_| error!(EscrowError::MathOverflow))
+    }
+}
+
+/// Who may take an escrow
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, InitSpace, PartialEq, Eq)]
+pub enum TakerAllowlist {
+    /// Anyone can take
+    Open,
+    /// Only this taker can take
+    Taker(Pubkey),
+    /// Takers whose leaf is under this Merkle root, see [`allowlist_leaf`]
+    MerkleRoot([u8; 32]),
+}
+
+impl TakerAllowlist {
+    /// Checks `taker` against the allowlist, `proof` is only used for a Merkle root
+    pub fn allows(&self, taker: &Pubkey, proof: &[[u8; 32]]) -> bool {
+        match self {
+            TakerAllowlist::Open => true,
+            TakerAllowlist::Taker(allowed) => allowed == taker,
+            TakerAllowlist::MerkleRoot(root) => {
+                let node = proof
+                    .iter()
+                    .fold(allowlist_leaf(taker), |node, sibling| allowlist_node(&node, sibling));
+                node == *root
+            }
+        }
+    }
+}
+
+/// Leaf of a taker in an allowlist Merkle tree.
+///
+/// Leaves and inner nodes are hashed with different prefixes so an inner node can
+/// never be passed off as a taker.
+pub fn allowlist_leaf(taker: &Pubkey) -> [u8; 32] {
+    hashv(&[&[0], taker.as_ref()]).to_bytes()
+}
+
+/// Parent of two nodes, the pair is sorted so proofs do not carry left/right flags
+pub fn allowlist_node(a: &[u8; 32], b: &[u8; 32]) -> [u8; 32] {
+    let (left, right) = if a <= b { (a, b) } else { (b, a) };
+    hashv(&[&[1], left, right]).to_bytes()
+}
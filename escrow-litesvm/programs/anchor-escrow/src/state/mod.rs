@@ -1,3 +1,9 @@
+pub mod basket;
+pub mod config;
 pub mod escrow;
+pub mod order_book;
 
+pub use basket::*;
+pub use config::*;
 pub use escrow::*;
+pub use order_book::*;
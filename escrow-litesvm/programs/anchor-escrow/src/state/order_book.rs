@@ -0,0 +1,111 @@
+use anchor_lang::prelude::*;
+
+use crate::{error::EscrowError, state::Escrow};
+
+/// Offers a single order book holds, see [`OrderBook::list`] for a full book
+pub const MAX_OFFERS: usize = 32;
+
+/// Prices are mint B per unit of mint A, in base units and scaled by this
+pub const PRICE_SCALE: u128 = 1_000_000_000;
+
+/// Open escrows selling mint A for mint B, a PDA at `[b"book", mint_a, mint_b]`.
+///
+/// Listing is opt-in at `make`. A listed escrow is kept up to date by `update` and
+/// `take`, and removed once it is filled or refunded.
+#[account]
+#[derive(InitSpace, Debug)]
+pub struct OrderBook {
+    pub mint_a: Pubkey,
+    pub mint_b: Pubkey,
+    /// Not sorted by price, delisting and eviction reorder them
+    #[max_len(MAX_OFFERS)]
+    pub offers: Vec<Offer>,
+    pub bump: u8,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, InitSpace, PartialEq, Eq)]
+pub struct Offer {
+    pub escrow: Pubkey,
+    pub maker: Pubkey,
+    /// Mint A still in the escrow
+    pub remaining: u64,
+    /// Mint B still needed to fill the escrow
+    pub outstanding: u64,
+    /// `outstanding / remaining` scaled by [`PRICE_SCALE`], rounded up and capped at
+    /// `u64::MAX`
+    pub price: u64,
+}
+
+impl Offer {
+    pub fn new(key: Pubkey, escrow: &Escrow) -> Result<Self> {
+        let outstanding = escrow.outstanding()?;
+
+        let price = (outstanding as u128)
+            .checked_mul(PRICE_SCALE)
+            .filter(|_| escrow.remaining > 0)
+            .map(|scaled| scaled.div_ceil(escrow.remaining as u128))
+            .ok_or(error!(EscrowError::MathOverflow))?;
+
+        Ok(Self {
+            escrow: key,
+            maker: escrow.maker,
+            remaining: escrow.remaining,
+            outstanding,
+            price: u64::try_from(price).unwrap_or(u64::MAX),
+        })
+    }
+}
+
+impl OrderBook {
+    /// A full book takes the offer in place of its most expensive one, as long as it
+    /// is strictly cheaper, so dust offers can't keep better prices out. The evicted
+    /// escrow stays open and still passes the book, it just isn't listed anymore
+    pub fn list(&mut self, key: Pubkey, escrow: &Escrow) -> Result<()> {
+        let offer = Offer::new(key, escrow)?;
+
+        if self.offers.len() < MAX_OFFERS {
+            self.offers.push(offer);
+            return Ok(());
+        }
+
+        let most_expensive = self
+            .offers
+            .iter_mut()
+            .max_by_key(|listed| listed.price)
+            .filter(|listed| offer.price < listed.price)
+            .ok_or(error!(EscrowError::OrderBookFull))?;
+        *most_expensive = offer;
+
+        Ok(())
+    }
+
+    /// Refreshes the offer of `key` after a partial fill or an update
+    pub fn relist(&mut self, key: Pubkey, escrow: &Escrow) -> Result<()> {
+        if let Some(index) = self.position(&key) {
+            self.offers[index] = Offer::new(key, escrow)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn delist(&mut self, key: &Pubkey) {
+        if let Some(index) = self.position(key) {
+            self.offers.swap_remove(index);
+        }
+    }
+
+    /// `None` once the escrow was evicted
+    fn position(&self, key: &Pubkey) -> Option<usize> {
+        self.offers.iter().position(|offer| offer.escrow == *key)
+    }
+}
+
+/// A listed escrow must always be given its order book and an unlisted one never is
+pub fn check_order_book(escrow: &Escrow, has_order_book: bool) -> Result<()> {
+    require!(
+        escrow.listed == has_order_book,
+        EscrowError::OrderBookMismatch
+    );
+
+    Ok(())
+}
@@ -0,0 +1,333 @@
+//! Shared setup for the tests: the program with its config, a maker holding mint A
+//! and a taker holding mint B, and helpers to send instructions and read state back.
+//!
+//! Instructions are built with `escrow_client`, so the tests exercise the same
+//! encoding as an off-chain caller.
+
+use {
+    crate::error::EscrowError,
+    anchor_lang::solana_program::{bpf_loader_upgradeable, clock::Clock, program_pack::Pack},
+    anchor_spl::token::spl_token,
+    escrow_client::anchor::{
+        self as client, config_address, AccountState, Config, Escrow, EscrowKeys,
+    },
+    litesvm::{
+        types::{FailedTransactionMetadata, TransactionResult},
+        LiteSVM,
+    },
+    litesvm_token::{
+        spl_token::ID as TOKEN_PROGRAM_ID, CreateAssociatedTokenAccount, CreateMint, MintTo,
+    },
+    solana_account::Account,
+    solana_instruction::{AccountMeta, Instruction},
+    solana_keypair::Keypair,
+    solana_message::Message,
+    solana_native_token::LAMPORTS_PER_SOL,
+    solana_pubkey::Pubkey,
+    solana_signer::Signer,
+    solana_transaction::Transaction,
+    std::{fmt::Debug, path::PathBuf},
+};
+
+/// What the maker starts with of mint A and the taker of mint B
+pub const INITIAL_BALANCE: u64 = 1_000_000_000;
+
+pub struct Fixture {
+    pub program: LiteSVM,
+    /// Admin of the config and authority of every mint
+    pub payer: Keypair,
+    pub maker: Keypair,
+    pub taker: Keypair,
+    pub mint_a: Pubkey,
+    pub mint_b: Pubkey,
+}
+
+impl Fixture {
+    /// Two mints with 6 decimals
+    pub fn new() -> Self {
+        Self::with_decimals(6, 6)
+    }
+
+    pub fn with_decimals(decimals_a: u8, decimals_b: u8) -> Self {
+        let mut fixture = Self::deploy(decimals_a, decimals_b);
+
+        // The payer is the upgrade authority, start without a fee
+        let payer = fixture.payer.pubkey();
+        fixture
+            .send_as_payer(client::initialize_config_ix(&payer, 0, &payer))
+            .unwrap();
+
+        fixture
+    }
+
+    /// [`Fixture::new`] before the config is initialized
+    pub fn without_config() -> Self {
+        Self::deploy(6, 6)
+    }
+
+    fn deploy(decimals_a: u8, decimals_b: u8) -> Self {
+        let mut program = LiteSVM::new();
+        let payer = Keypair::new();
+        program
+            .airdrop(&payer.pubkey(), 10 * LAMPORTS_PER_SOL)
+            .unwrap();
+
+        let so_path =
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../../target/deploy/anchor_escrow.so");
+        let program_data = std::fs::read(so_path).expect("Failed to read program SO file");
+        add_upgradeable_program(&mut program, &program_data, &payer.pubkey());
+        assert_eq!(client::PROGRAM_ID, crate::ID);
+
+        let mut fixture = Fixture {
+            program,
+            payer,
+            maker: Keypair::new(),
+            taker: Keypair::new(),
+            mint_a: Pubkey::default(),
+            mint_b: Pubkey::default(),
+        };
+
+        let (maker, taker) = (fixture.maker.pubkey(), fixture.taker.pubkey());
+        fixture.airdrop(&maker);
+        fixture.airdrop(&taker);
+
+        let mint_a = fixture.create_mint(decimals_a);
+        let mint_b = fixture.create_mint(decimals_b);
+        fixture.mint_to(&maker, &mint_a, INITIAL_BALANCE);
+        fixture.mint_to(&taker, &mint_b, INITIAL_BALANCE);
+
+        Fixture {
+            mint_a,
+            mint_b,
+            ..fixture
+        }
+    }
+
+    /// Escrow of the maker trading mint A for mint B
+    pub fn keys(&self, seed: u64) -> EscrowKeys {
+        EscrowKeys::new(self.maker.pubkey(), self.mint_a, self.mint_b, seed)
+    }
+
+    /// A new account with 10 SOL
+    pub fn keypair(&mut self) -> Keypair {
+        let keypair = Keypair::new();
+        self.airdrop(&keypair.pubkey());
+        keypair
+    }
+
+    pub fn airdrop(&mut self, address: &Pubkey) {
+        self.program
+            .airdrop(address, 10 * LAMPORTS_PER_SOL)
+            .unwrap();
+    }
+
+    pub fn create_mint(&mut self, decimals: u8) -> Pubkey {
+        CreateMint::new(&mut self.program, &self.payer)
+            .decimals(decimals)
+            .authority(&self.payer.pubkey())
+            .send()
+            .unwrap()
+    }
+
+    /// Token-2022 mint with 6 decimals that withholds `basis_points` of every
+    /// transfer, up to `maximum_fee`
+    pub fn create_transfer_fee_mint(&mut self, basis_points: u16, maximum_fee: u64) -> Pubkey {
+        use spl_token_2022::{
+            extension::{transfer_fee::instruction::initialize_transfer_fee_config, ExtensionType},
+            instruction::initialize_mint2,
+            state::Mint,
+        };
+
+        let mint = Keypair::new();
+        let payer = self.payer.pubkey();
+        let space =
+            ExtensionType::try_calculate_account_len::<Mint>(&[ExtensionType::TransferFeeConfig])
+                .unwrap();
+        let lamports = self.program.minimum_balance_for_rent_exemption(space);
+
+        // System program CreateAccount, laid out by hand
+        let create_account = Instruction {
+            program_id: solana_sdk_ids::system_program::ID,
+            accounts: vec![
+                AccountMeta::new(payer, true),
+                AccountMeta::new(mint.pubkey(), true),
+            ],
+            data: [
+                &0u32.to_le_bytes()[..],
+                &lamports.to_le_bytes(),
+                &(space as u64).to_le_bytes(),
+                spl_token_2022::ID.as_ref(),
+            ]
+            .concat(),
+        };
+
+        let instructions = [
+            create_account,
+            initialize_transfer_fee_config(
+                &spl_token_2022::ID,
+                &mint.pubkey(),
+                Some(&payer),
+                Some(&payer),
+                basis_points,
+                maximum_fee,
+            )
+            .unwrap(),
+            initialize_mint2(&spl_token_2022::ID, &mint.pubkey(), &payer, None, 6).unwrap(),
+        ];
+
+        self.program.expire_blockhash();
+        let message = Message::new(&instructions, Some(&payer));
+        let transaction = Transaction::new(
+            &[&self.payer, &mint],
+            message,
+            self.program.latest_blockhash(),
+        );
+        self.program.send_transaction(transaction).unwrap();
+
+        mint.pubkey()
+    }
+
+    /// Creates the ATA of `owner` for `mint` and mints `amount` into it
+    pub fn mint_to(&mut self, owner: &Pubkey, mint: &Pubkey, amount: u64) -> Pubkey {
+        self.mint_to_with(owner, mint, &TOKEN_PROGRAM_ID, amount)
+    }
+
+    /// [`Fixture::mint_to`] for a mint of `token_program`
+    pub fn mint_to_with(
+        &mut self,
+        owner: &Pubkey,
+        mint: &Pubkey,
+        token_program: &Pubkey,
+        amount: u64,
+    ) -> Pubkey {
+        let ata = CreateAssociatedTokenAccount::new(&mut self.program, &self.payer, mint)
+            .owner(owner)
+            .token_program_id(token_program)
+            .send()
+            .unwrap();
+
+        if amount > 0 {
+            MintTo::new(&mut self.program, &self.payer, mint, &ata, amount)
+                .token_program_id(token_program)
+                .send()
+                .unwrap();
+        }
+
+        ata
+    }
+
+    /// Sends `ix` signed and paid for by `signer`, on a fresh blockhash so the
+    /// same instruction can be sent twice
+    pub fn send(&mut self, ix: Instruction, signer: &Keypair) -> TransactionResult {
+        send(&mut self.program, ix, signer)
+    }
+
+    pub fn send_as_maker(&mut self, ix: Instruction) -> TransactionResult {
+        send(&mut self.program, ix, &self.maker)
+    }
+
+    pub fn send_as_taker(&mut self, ix: Instruction) -> TransactionResult {
+        send(&mut self.program, ix, &self.taker)
+    }
+
+    pub fn send_as_payer(&mut self, ix: Instruction) -> TransactionResult {
+        send(&mut self.program, ix, &self.payer)
+    }
+
+    pub fn now(&self) -> i64 {
+        self.program.get_sysvar::<Clock>().unix_timestamp
+    }
+
+    pub fn warp_to(&mut self, unix_timestamp: i64) {
+        let mut clock: Clock = self.program.get_sysvar();
+        clock.unix_timestamp = unix_timestamp;
+        self.program.set_sysvar(&clock);
+    }
+
+    /// Current config, take instructions read the fee and treasury from it
+    pub fn config(&self) -> Config {
+        let account = self.program.get_account(&config_address()).unwrap();
+        Config::decode(&account.data).unwrap()
+    }
+
+    pub fn escrow(&self, keys: &EscrowKeys) -> Escrow {
+        let account = self.program.get_account(&keys.escrow()).unwrap();
+        Escrow::decode(&account.data).unwrap()
+    }
+
+    /// Zero for a token account that does not exist (yet or anymore). Token-2022
+    /// accounts start with the SPL Token layout, extensions follow it.
+    pub fn balance(&self, address: &Pubkey) -> u64 {
+        match self.program.get_account(address) {
+            Some(account) if !account.data.is_empty() => {
+                spl_token::state::Account::unpack(&account.data[..spl_token::state::Account::LEN])
+                    .unwrap()
+                    .amount
+            }
+            _ => 0,
+        }
+    }
+
+    pub fn lamports(&self, address: &Pubkey) -> u64 {
+        self.program
+            .get_account(address)
+            .map_or(0, |account| account.lamports)
+    }
+
+    pub fn is_closed(&self, address: &Pubkey) -> bool {
+        let account = self.program.get_account(address);
+        account.is_none() || account.unwrap().lamports == 0
+    }
+}
+
+// Deploys the program through the upgradeable loader like `anchor deploy` does, so
+// the config can check the upgrade authority
+fn add_upgradeable_program(program: &mut LiteSVM, elf: &[u8], upgrade_authority: &Pubkey) {
+    let program_data_address = client::program_data_address();
+
+    // UpgradeableLoaderState::ProgramData { slot: 0, upgrade_authority_address }
+    let program_data = [
+        &3u32.to_le_bytes()[..],
+        &0u64.to_le_bytes(),
+        &[1],
+        upgrade_authority.as_ref(),
+        elf,
+    ]
+    .concat();
+    // UpgradeableLoaderState::Program { programdata_address }
+    let program_account = [&2u32.to_le_bytes()[..], program_data_address.as_ref()].concat();
+
+    // The program data has to be in place before the program is loaded
+    for (address, data, executable) in [
+        (program_data_address, program_data, false),
+        (crate::ID, program_account, true),
+    ] {
+        let account = Account {
+            lamports: program.minimum_balance_for_rent_exemption(data.len()),
+            data,
+            owner: bpf_loader_upgradeable::ID,
+            executable,
+            rent_epoch: 0,
+        };
+        program.set_account(address, account).unwrap();
+    }
+}
+
+fn send(program: &mut LiteSVM, ix: Instruction, signer: &Keypair) -> TransactionResult {
+    program.expire_blockhash();
+    let message = Message::new(&[ix], Some(&signer.pubkey()));
+    let transaction = Transaction::new(&[signer], message, program.latest_blockhash());
+    program.send_transaction(transaction)
+}
+
+/// Asserts the first instruction of the transaction failed with `error`
+#[track_caller]
+pub fn assert_error<T: Debug>(result: Result<T, FailedTransactionMetadata>, error: EscrowError) {
+    let name = format!("{error:?}");
+    let code = u32::from(error);
+    assert_eq!(
+        format!("{:?}", result.unwrap_err().err),
+        format!("InstructionError(0, Custom({code}))"),
+        "expected {name}"
+    );
+}
@@ -0,0 +1,319 @@
+//! Property-based tests: random sequences of make, take, refund, fee changes and
+//! clock warps are driven through LiteSVM, and the escrow invariants are checked
+//! after every step.
+//!
+//! - the supply of both mints is conserved across every token account, the
+//!   treasury's included
+//! - every take splits the fill between maker and treasury at the fee the escrow
+//!   was made with, later fee changes leave open escrows alone
+//! - the vault of every open escrow holds exactly what the escrow still owes
+//! - closing an escrow hands the rent of the escrow and the vault to the maker
+//! - no take goes through before the lock time
+
+use {
+    super::fixture::{assert_error, Fixture},
+    crate::{error::EscrowError, state::MAX_FEE_BPS},
+    anchor_lang::solana_program::program_pack::Pack,
+    anchor_spl::token::spl_token,
+    escrow_client::anchor::{ata, update_config_ix, EscrowKeys, MakeArgs, TOKEN_PROGRAM_ID},
+    litesvm::types::TransactionResult,
+    proptest::prelude::*,
+    solana_instruction::Instruction,
+    solana_keypair::Keypair,
+    solana_pubkey::Pubkey,
+    solana_signer::Signer,
+};
+
+const MAKERS: usize = 2;
+const MAKER_MINT_A: u64 = 100_000;
+const MAX_LOCK: i64 = 100;
+const TX_FEE: u64 = 5000;
+
+#[derive(Debug, Clone)]
+enum Step {
+    Make {
+        maker: usize,
+        deposit: u64,
+        receive: u64,
+        unlock_after: i64,
+    },
+    /// `escrow` and `amount` are reduced to an open escrow and a valid fill when run
+    Take {
+        escrow: usize,
+        amount: u64,
+    },
+    Refund {
+        escrow: usize,
+    },
+    Warp {
+        seconds: i64,
+    },
+    /// Fee of the escrows made from then on
+    SetFee {
+        fee_bps: u16,
+    },
+}
+
+fn step() -> impl Strategy<Value = Step> {
+    prop_oneof![
+        2 => (0..MAKERS, 1..=500u64, 1..=500u64, 0..=MAX_LOCK).prop_map(
+            |(maker, deposit, receive, unlock_after)| Step::Make {
+                maker,
+                deposit,
+                receive,
+                unlock_after,
+            }
+        ),
+        4 => (any::<usize>(), any::<u64>())
+            .prop_map(|(escrow, amount)| Step::Take { escrow, amount }),
+        1 => any::<usize>().prop_map(|escrow| Step::Refund { escrow }),
+        2 => (0..=MAX_LOCK).prop_map(|seconds| Step::Warp { seconds }),
+        1 => (0..=MAX_FEE_BPS).prop_map(|fee_bps| Step::SetFee { fee_bps }),
+    ]
+}
+
+struct OpenEscrow {
+    maker: usize,
+    keys: EscrowKeys,
+    /// The config's fee when the escrow was made
+    fee_bps: u16,
+}
+
+/// The fixture's maker and taker, plus more makers so escrows of different
+/// makers are open side by side
+struct World {
+    fixture: Fixture,
+    makers: Vec<Keypair>,
+    open: Vec<OpenEscrow>,
+    next_seed: u64,
+    /// Receives the fees, holds nothing else
+    treasury: Pubkey,
+    fee_bps: u16,
+}
+
+impl World {
+    fn new() -> Self {
+        let mut fixture = Fixture::new();
+        let mint_a = fixture.mint_a;
+
+        let mut makers = vec![fixture.maker.insecure_clone()];
+        for _ in 1..MAKERS {
+            let maker = fixture.keypair();
+            fixture.mint_to(&maker.pubkey(), &mint_a, MAKER_MINT_A);
+            makers.push(maker);
+        }
+
+        let treasury = Pubkey::new_unique();
+        let ix = update_config_ix(&fixture.payer.pubkey(), None, None, Some(treasury), None);
+        fixture.send_as_payer(ix).unwrap();
+
+        World {
+            fixture,
+            makers,
+            open: Vec::new(),
+            next_seed: 0,
+            treasury,
+            fee_bps: 0,
+        }
+    }
+
+    fn run(&mut self, step: &Step) {
+        match *step {
+            Step::Make {
+                maker,
+                deposit,
+                receive,
+                unlock_after,
+            } => self.make(maker, deposit, receive, unlock_after),
+            Step::Take { escrow, amount } => self.take(escrow, amount),
+            Step::Refund { escrow } => self.refund(escrow),
+            Step::Warp { seconds } => self.fixture.warp_to(self.fixture.now() + seconds),
+            Step::SetFee { fee_bps } => self.set_fee(fee_bps),
+        }
+
+        self.check_invariants();
+    }
+
+    fn make(&mut self, maker: usize, deposit: u64, receive: u64, unlock_after: i64) {
+        let seed = self.next_seed;
+        self.next_seed += 1;
+
+        let keys = EscrowKeys::new(
+            self.makers[maker].pubkey(),
+            self.fixture.mint_a,
+            self.fixture.mint_b,
+            seed,
+        );
+        let make_ix = keys.make_ix(MakeArgs {
+            unlock_after,
+            ..MakeArgs::new(deposit, receive)
+        });
+
+        self.send(make_ix, maker).unwrap();
+
+        self.open.push(OpenEscrow {
+            maker,
+            keys,
+            fee_bps: self.fee_bps,
+        });
+    }
+
+    fn set_fee(&mut self, fee_bps: u16) {
+        let payer = self.fixture.payer.pubkey();
+        let ix = update_config_ix(&payer, None, Some(fee_bps), None, None);
+        self.fixture.send_as_payer(ix).unwrap();
+        self.fee_bps = fee_bps;
+    }
+
+    fn take(&mut self, index: usize, amount: u64) {
+        if self.open.is_empty() {
+            return;
+        }
+        let index = index % self.open.len();
+        let OpenEscrow {
+            maker,
+            ref keys,
+            fee_bps,
+        } = self.open[index];
+        let (escrow, vault) = (keys.escrow(), keys.vault().unwrap());
+        let maker_key = self.makers[maker].pubkey();
+        let mint_b = self.fixture.mint_b;
+        let maker_ata_b = ata(&maker_key, &mint_b, &TOKEN_PROGRAM_ID);
+        let treasury_ata_b = ata(&self.treasury, &mint_b, &TOKEN_PROGRAM_ID);
+
+        let state = self.fixture.escrow(keys);
+        let outstanding = state.receive - state.filled;
+        let amount = 1 + amount % outstanding;
+
+        let take_ix = keys.take_partial_ix(
+            &self.fixture.taker.pubkey(),
+            Some(&self.fixture.config()),
+            vec![],
+            amount,
+        );
+
+        let maker_lamports = self.fixture.lamports(&maker_key);
+        let rent = self.fixture.lamports(&escrow) + self.fixture.lamports(&vault);
+        let maker_b = self.fixture.balance(&maker_ata_b);
+        let treasury_b = self.fixture.balance(&treasury_ata_b);
+        let now = self.fixture.now();
+
+        let result = self.fixture.send_as_taker(take_ix);
+
+        if now < state.lock_until {
+            assert_error(result, EscrowError::TakeTooEarly);
+            return;
+        }
+
+        let released = amount as u128 * state.remaining as u128 / outstanding as u128;
+        if amount < outstanding && released == 0 {
+            assert_error(result, EscrowError::FillTooSmall);
+            return;
+        }
+
+        result.unwrap();
+
+        let fee = (amount as u128 * fee_bps as u128 / 10_000) as u64;
+        assert_eq!(self.fixture.balance(&treasury_ata_b), treasury_b + fee);
+        assert_eq!(self.fixture.balance(&maker_ata_b), maker_b + amount - fee);
+
+        if amount == outstanding {
+            self.assert_closed(&escrow, &vault);
+            assert_eq!(self.fixture.lamports(&maker_key), maker_lamports + rent);
+            self.open.remove(index);
+        }
+    }
+
+    fn refund(&mut self, index: usize) {
+        if self.open.is_empty() {
+            return;
+        }
+        let index = index % self.open.len();
+        let OpenEscrow {
+            maker, ref keys, ..
+        } = self.open[index];
+        let (escrow, vault) = (keys.escrow(), keys.vault().unwrap());
+        let maker_key = self.makers[maker].pubkey();
+
+        let refund_ix = keys.refund_ix();
+
+        let maker_lamports = self.fixture.lamports(&maker_key);
+        let rent = self.fixture.lamports(&escrow) + self.fixture.lamports(&vault);
+
+        self.send(refund_ix, maker).unwrap();
+
+        self.assert_closed(&escrow, &vault);
+        assert_eq!(
+            self.fixture.lamports(&maker_key),
+            maker_lamports + rent - TX_FEE
+        );
+        self.open.remove(index);
+    }
+
+    fn check_invariants(&self) {
+        let fixture = &self.fixture;
+
+        for mint in [fixture.mint_a, fixture.mint_b] {
+            let supply =
+                spl_token::state::Mint::unpack(&fixture.program.get_account(&mint).unwrap().data)
+                    .unwrap()
+                    .supply;
+
+            let mut owners: Vec<Pubkey> = self.makers.iter().map(Keypair::pubkey).collect();
+            owners.push(fixture.taker.pubkey());
+            owners.push(self.treasury);
+            owners.extend(self.open.iter().map(|open| open.keys.escrow()));
+
+            let held: u64 = owners
+                .iter()
+                .map(|owner| fixture.balance(&ata(owner, &mint, &TOKEN_PROGRAM_ID)))
+                .sum();
+
+            assert_eq!(held, supply, "supply of {mint} is not conserved");
+        }
+
+        for open in &self.open {
+            let state = fixture.escrow(&open.keys);
+            assert_eq!(
+                state.fee_bps, open.fee_bps,
+                "the fee of an open escrow changed"
+            );
+            assert!(state.remaining > 0);
+            assert!(state.filled < state.receive);
+            assert_eq!(
+                fixture.balance(&open.keys.vault().unwrap()),
+                state.remaining,
+                "vault does not match the escrow deposit"
+            );
+        }
+    }
+
+    fn assert_closed(&self, escrow: &Pubkey, vault: &Pubkey) {
+        assert!(self.fixture.is_closed(escrow));
+        assert!(self.fixture.is_closed(vault));
+    }
+
+    fn send(&mut self, ix: Instruction, maker: usize) -> TransactionResult {
+        self.fixture.send(ix, &self.makers[maker])
+    }
+}
+
+proptest! {
+    // Every case boots a fresh LiteSVM with the program loaded
+    #![proptest_config(ProptestConfig::with_cases(32))]
+
+    #[test]
+    fn escrow_invariants_hold(steps in prop::collection::vec(step(), 1..40)) {
+        let mut world = World::new();
+
+        for step in &steps {
+            world.run(step);
+        }
+
+        // Whatever is still open can always be refunded
+        while !world.open.is_empty() {
+            world.refund(0);
+            world.check_invariants();
+        }
+    }
+}
@@ -1,893 +1,1898 @@
+// LiteSVM hands back the whole transaction metadata as the error
+#![allow(clippy::result_large_err)]
+
+#[cfg(test)]
+mod fixture;
+
+#[cfg(test)]
+mod invariants;
+
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod tests {
 
     use {
         anchor_lang::{
-            prelude::msg, solana_program::program_pack::Pack, AccountDeserialize, InstructionData,
-            ToAccountMetas,
-        },
-        anchor_spl::{
-            associated_token::{self, spl_associated_token_account},
-            token::spl_token,
+            solana_program::{hash::hash, program_pack::Pack},
+            AccountDeserialize, AccountSerialize, AnchorDeserialize, Discriminator,
         },
+        anchor_spl::token::spl_token,
+        base64::{prelude::BASE64_STANDARD, Engine},
         litesvm::LiteSVM,
-        litesvm_token::{
-            spl_token::ID as TOKEN_PROGRAM_ID, CreateAssociatedTokenAccount, CreateMint, MintTo,
-        },
+        litesvm_token::spl_token::ID as TOKEN_PROGRAM_ID,
         solana_account::Account,
-        solana_address::Address,
-        solana_instruction::Instruction,
+        solana_instruction::{AccountMeta, Instruction},
         solana_keypair::Keypair,
         solana_message::Message,
         solana_native_token::LAMPORTS_PER_SOL,
         solana_pubkey::Pubkey,
-        solana_rpc_client::rpc_client::RpcClient,
-        solana_sdk_ids::system_program::ID as SYSTEM_PROGRAM_ID,
         solana_signer::Signer,
         solana_transaction::Transaction,
         std::{path::PathBuf, str::FromStr},
     };
 
-    // Re-export Clock from anchor_lang
-    use anchor_lang::solana_program::clock::Clock;
+    use escrow_client::anchor::{
+        self as client, config_address, AccountState, BasketKeys, BasketLeg, DutchAuction,
+        EscrowKeys, MakeArgs, OraclePricing, TakerAllowlist, UpdateArgs, NATIVE_MINT,
+    };
+
+    use super::fixture::{assert_error, Fixture, INITIAL_BALANCE};
+
+    use crate::{
+        error::EscrowError,
+        events::{EscrowCreated, EscrowRefunded, EscrowTaken, EscrowUpdated},
+        state::{allowlist_leaf, allowlist_node, MAX_OFFERS},
+    };
 
     static PROGRAM_ID: Pubkey = crate::ID;
 
     // 5 days in seconds
     const FIVE_DAYS_IN_SECONDS: i64 = 5 * 24 * 60 * 60;
 
-    // Setup function to initialize LiteSVM and create a payer keypair
-    // Also loads an account from devnet into the LiteSVM environment (for testing purposes)
-    fn setup() -> (LiteSVM, Keypair) {
-        // Initialize LiteSVM and payer
-        let mut program = LiteSVM::new();
-        let payer = Keypair::new();
-
-        // Airdrop some SOL to the payer keypair
-        program
-            .airdrop(&payer.pubkey(), 10u64.checked_mul(LAMPORTS_PER_SOL).expect("Overflow in airdrop amount"))
-            .expect("Failed to airdrop SOL to payer");
+    // Devnet task queue of the tuktuk-counter example, refresh it together with the
+    // tuktuk program with `cargo run --example refresh_fixtures --
+    // CMreFdKxT5oeZhiX8nWTGz9PtXM1AMYTh6dGR2UzdtrA --program tuktukUrfhXT6ZT77QTU8RQtvgL967uRuVagWF57zVA`
+    const TASK_QUEUE_FIXTURE: &str = "CMreFdKxT5oeZhiX8nWTGz9PtXM1AMYTh6dGR2UzdtrA";
+
+    // Reads an account saved with `solana account <address> --output json`
+    fn load_fixture(address: &str) -> (Pubkey, Account) {
+        let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("fixtures")
+            .join(format!("{address}.json"));
+        let json = std::fs::read_to_string(&path)
+            .unwrap_or_else(|err| panic!("Failed to read fixture {}: {err}", path.display()));
+        let fixture: serde_json::Value =
+            serde_json::from_str(&json).expect("Fixture is not valid JSON");
+
+        let pubkey = Pubkey::from_str(fixture["pubkey"].as_str().expect("Fixture has no pubkey"))
+            .expect("Fixture pubkey is not valid");
+        let account = &fixture["account"];
+
+        let data = match account["data"].as_array().map(Vec::as_slice) {
+            Some([data, encoding]) if encoding == "base64" => BASE64_STANDARD
+                .decode(data.as_str().expect("Fixture data is not a string"))
+                .expect("Fixture data is not valid base64"),
+            _ => panic!("Fixture data must be [\"<data>\", \"base64\"]"),
+        };
 
-        // Load program SO file
-        let so_path =
-            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../../target/deploy/anchor_escrow.so");
+        let account = Account {
+            lamports: account["lamports"].as_u64().expect("Fixture has no lamports"),
+            data,
+            owner: Pubkey::from_str(account["owner"].as_str().expect("Fixture has no owner"))
+                .expect("Fixture owner is not valid"),
+            executable: account["executable"].as_bool().unwrap_or(false),
+            rent_epoch: account["rentEpoch"].as_u64().unwrap_or(u64::MAX),
+        };
 
-        let program_data = std::fs::read(so_path).expect("Failed to read program SO file");
+        (pubkey, account)
+    }
 
-        program.add_program(PROGRAM_ID, &program_data);
+    // Decodes the `emit!` events of type T from the "Program data: " lines of a transaction
+    fn parse_events<T: AnchorDeserialize + Discriminator>(logs: &[String]) -> Vec<T> {
+        logs.iter()
+            .filter_map(|log| log.strip_prefix("Program data: "))
+            .filter_map(|data| BASE64_STANDARD.decode(data).ok())
+            .filter(|data| data.starts_with(T::DISCRIMINATOR))
+            .map(|data| T::deserialize(&mut &data[T::DISCRIMINATOR.len()..]).unwrap())
+            .collect()
+    }
 
-        // Example on how to Load an account from devnet
-        // LiteSVM does not have access to real Solana network data since it does not have network access,
-        // so we use an RPC client to fetch account data from devnet
-        let rpc_client = RpcClient::new("https://api.devnet.solana.com");
-        let account_address =
-            Address::from_str("DRYvf71cbF2s5wgaJQvAGkghMkRcp5arvsK2w97vXhi2").unwrap();
-        let fetched_account = rpc_client
-            .get_account(&account_address)
-            .expect("Failed to fetch account from devnet");
+    // Writes the wrapped SOL mint so escrows can name it as mint A or mint B
+    fn add_native_mint(program: &mut LiteSVM) {
+        let mut data = vec![0u8; spl_token::state::Mint::LEN];
+        spl_token::state::Mint {
+            decimals: 9,
+            is_initialized: true,
+            ..Default::default()
+        }
+        .pack_into_slice(&mut data);
 
-        // Set the fetched account in the LiteSVM environment
-        // This allows us to simulate interactions with this account during testing
         program
             .set_account(
-                payer.pubkey(),
+                NATIVE_MINT,
                 Account {
-                    lamports: fetched_account.lamports,
-                    data: fetched_account.data,
-                    owner: Pubkey::from(fetched_account.owner.to_bytes()),
-                    executable: fetched_account.executable,
-                    rent_epoch: fetched_account.rent_epoch,
+                    lamports: program.minimum_balance_for_rent_exemption(data.len()),
+                    data,
+                    owner: TOKEN_PROGRAM_ID,
+                    executable: false,
+                    rent_epoch: 0,
                 },
             )
             .unwrap();
+    }
+
+    #[test]
+    fn test_make() {
+        let mut f = Fixture::new();
+        let keys = f.keys(123);
+        let vault = keys.vault().unwrap();
 
-        msg!("Lamports of fetched account: {}", fetched_account.lamports);
+        let ix = keys.make_ix(MakeArgs {
+            unlock_after: FIVE_DAYS_IN_SECONDS,
+            ..MakeArgs::new(10, 10)
+        });
+        f.send_as_maker(ix).unwrap();
 
-        // Return the LiteSVM instance and payer keypair
-        (program, payer)
+        let vault_account = f.program.get_account(&vault).unwrap();
+        let vault_data = spl_token::state::Account::unpack(&vault_account.data).unwrap();
+        assert_eq!(vault_data.amount, 10);
+        assert_eq!(vault_data.owner, keys.escrow());
+        assert_eq!(vault_data.mint, f.mint_a);
+
+        let escrow = f.escrow(&keys);
+        assert_eq!(escrow.seed, 123);
+        assert_eq!(escrow.maker, f.maker.pubkey());
+        assert_eq!(escrow.mint_a, f.mint_a);
+        assert_eq!(escrow.mint_b, f.mint_b);
+        assert_eq!(escrow.receive, 10);
     }
 
     #[test]
-    fn test_make() {
-        // Setup the test environment by initializing LiteSVM and creating a payer keypair
-        let (mut program, payer) = setup();
-
-        // Get the maker's public key from the payer keypair
-        let maker = payer.pubkey();
-
-        // Create two mints (Mint A and Mint B) with 6 decimal places and the maker as the authority
-        // This done using litesvm-token's CreateMint utility which creates the mint in the LiteSVM environment
-        let mint_a = CreateMint::new(&mut program, &payer)
-            .decimals(6)
-            .authority(&maker)
-            .send()
-            .unwrap();
-        msg!("Mint A: {}\n", mint_a);
+    fn test_take() {
+        let mut f = Fixture::new();
+        let keys = f.keys(123);
+        let vault = keys.vault().unwrap();
+        let taker_ata_a = keys.token_account(&f.taker.pubkey(), &f.mint_a).unwrap();
+        let maker_ata_b = keys.token_account(&f.maker.pubkey(), &f.mint_b).unwrap();
+
+        let ix = keys.make_ix(MakeArgs {
+            unlock_after: FIVE_DAYS_IN_SECONDS,
+            ..MakeArgs::new(100, 50)
+        });
+        f.send_as_maker(ix).unwrap();
+        assert_eq!(f.balance(&vault), 100);
+
+        f.warp_to(f.now() + FIVE_DAYS_IN_SECONDS + 1);
+
+        // The maker's and taker's ATAs are created on the fly
+        let ix = keys.take_ix(&f.taker.pubkey(), Some(&f.config()), vec![]);
+        f.send_as_taker(ix).unwrap();
+
+        assert_eq!(f.balance(&taker_ata_a), 100);
+        assert_eq!(f.balance(&maker_ata_b), 50);
+        assert!(f.is_closed(&vault));
+        assert!(f.is_closed(&keys.escrow()));
+    }
 
-        let mint_b = CreateMint::new(&mut program, &payer)
-            .decimals(6)
-            .authority(&maker)
-            .send()
-            .unwrap();
-        msg!("Mint B: {}\n", mint_b);
+    #[test]
+    fn test_refund() {
+        let mut f = Fixture::new();
+        let keys = f.keys(456);
+        let vault = keys.vault().unwrap();
+        let maker_ata_a = keys.token_account(&f.maker.pubkey(), &f.mint_a).unwrap();
+
+        let ix = keys.make_ix(MakeArgs {
+            unlock_after: FIVE_DAYS_IN_SECONDS,
+            ..MakeArgs::new(200, 100)
+        });
+        f.send_as_maker(ix).unwrap();
+        assert_eq!(f.balance(&vault), 200);
+
+        f.send_as_maker(keys.refund_ix()).unwrap();
+
+        assert_eq!(f.balance(&maker_ata_a), INITIAL_BALANCE);
+        assert!(f.is_closed(&vault));
+        assert!(f.is_closed(&keys.escrow()));
+    }
 
-        // Create the maker's associated token account for Mint A
-        // This is done using litesvm-token's CreateAssociatedTokenAccount utility
-        let maker_ata_a = CreateAssociatedTokenAccount::new(&mut program, &payer, &mint_a)
-            .owner(&maker)
-            .send()
-            .unwrap();
-        msg!("Maker ATA A: {}\n", maker_ata_a);
+    #[test]
+    fn test_take_fails_before_5_days() {
+        let mut f = Fixture::new();
+        let keys = f.keys(789);
 
-        // Derive the PDA for the escrow account using the maker's public key and a seed value
-        let escrow = Pubkey::find_program_address(
-            &[b"escrow", maker.as_ref(), &123u64.to_le_bytes()],
-            &PROGRAM_ID,
-        )
-        .0;
-        msg!("Escrow PDA: {}\n", escrow);
+        let ix = keys.make_ix(MakeArgs {
+            unlock_after: FIVE_DAYS_IN_SECONDS,
+            ..MakeArgs::new(100, 50)
+        });
+        f.send_as_maker(ix).unwrap();
 
-        // Derive the PDA for the vault associated token account using the escrow PDA and Mint A
-        let vault = associated_token::get_associated_token_address(&escrow, &mint_a);
-        msg!("Vault PDA: {}\n", vault);
+        let ix = keys.take_ix(&f.taker.pubkey(), Some(&f.config()), vec![]);
+        assert_error(f.send_as_taker(ix), EscrowError::TakeTooEarly);
+    }
 
-        // Define program IDs for associated token program, token program, and system program
-        let asspciated_token_program = spl_associated_token_account::ID;
-        let token_program = TOKEN_PROGRAM_ID;
-        let system_program = SYSTEM_PROGRAM_ID;
+    #[test]
+    fn test_take_succeeds_after_5_days() {
+        let mut f = Fixture::new();
+        let keys = f.keys(999);
+        let taker_ata_a = keys.token_account(&f.taker.pubkey(), &f.mint_a).unwrap();
+        let maker_ata_b = keys.token_account(&f.maker.pubkey(), &f.mint_b).unwrap();
 
-        // Mint 1,000 tokens (with 6 decimal places) of Mint A to the maker's associated token account
-        MintTo::new(&mut program, &payer, &mint_a, &maker_ata_a, 1000000000)
-            .send()
-            .unwrap();
+        let ix = keys.make_ix(MakeArgs {
+            unlock_after: FIVE_DAYS_IN_SECONDS,
+            ..MakeArgs::new(100, 50)
+        });
+        f.send_as_maker(ix).unwrap();
 
-        // Create the "Make" instruction to deposit tokens into the escrow
-        let make_ix = Instruction {
-            program_id: PROGRAM_ID,
-            accounts: crate::accounts::Make {
-                maker: maker,
-                mint_a: mint_a,
-                mint_b: mint_b,
-                maker_ata_a: maker_ata_a,
-                escrow: escrow,
-                vault: vault,
-                associated_token_program: asspciated_token_program,
-                token_program: token_program,
-                system_program: system_program,
-            }
-            .to_account_metas(None),
-            data: crate::instruction::Make {
-                deposit: 10,
-                seed: 123u64,
-                receive: 10,
-            }
-            .data(),
+        f.warp_to(f.now() + FIVE_DAYS_IN_SECONDS + 1);
+
+        let ix = keys.take_ix(&f.taker.pubkey(), Some(&f.config()), vec![]);
+        f.send_as_taker(ix).unwrap();
+
+        assert_eq!(f.balance(&taker_ata_a), 100);
+        assert_eq!(f.balance(&maker_ata_b), 50);
+    }
+
+    #[test]
+    fn test_take_partial_fills_until_closed() {
+        let mut f = Fixture::new();
+
+        // 100 of mint_a for 30 of mint_b, so no fill divides evenly
+        let keys = f.keys(321);
+        let ix = keys.make_ix(MakeArgs {
+            unlock_after: FIVE_DAYS_IN_SECONDS,
+            ..MakeArgs::new(100, 30)
+        });
+        f.send_as_maker(ix).unwrap();
+
+        let vault = keys.vault().unwrap();
+        let taker_ata_a = keys.token_account(&f.taker.pubkey(), &f.mint_a).unwrap();
+        let maker_ata_b = keys.token_account(&f.maker.pubkey(), &f.mint_b).unwrap();
+
+        f.warp_to(f.now() + FIVE_DAYS_IN_SECONDS + 1);
+
+        let take_partial = |f: &mut Fixture, amount: u64| {
+            let ix = keys.take_partial_ix(&f.taker.pubkey(), Some(&f.config()), vec![], amount);
+            f.send_as_taker(ix)
         };
 
-        // Create and send the transaction containing the "Make" instruction
-        let message = Message::new(&[make_ix], Some(&payer.pubkey()));
-        let recent_blockhash = program.latest_blockhash();
+        // ==================== FIRST FILL ====================
+        // 10 of 30 releases floor(10 * 100 / 30) = 33
+        take_partial(&mut f, 10).unwrap();
 
-        let transaction = Transaction::new(&[&payer], message, recent_blockhash);
+        assert_eq!(f.balance(&taker_ata_a), 33);
+        assert_eq!(f.balance(&maker_ata_b), 10);
+        assert_eq!(f.balance(&vault), 67);
 
-        // Send the transaction and capture the result
-        let tx = program.send_transaction(transaction).unwrap();
+        let escrow = f.escrow(&keys);
+        assert_eq!(escrow.filled, 10);
+        assert_eq!(escrow.remaining, 67);
 
-        // Log transaction details
-        msg!("\n\nMake transaction sucessfull");
-        msg!("CUs Consumed: {}", tx.compute_units_consumed);
-        msg!("Tx Signature: {}", tx.signature);
+        // ==================== OVERFILL ====================
+        // Only 20 is outstanding
+        assert_error(take_partial(&mut f, 21), EscrowError::InvalidFillAmount);
 
-        // Verify the vault account and escrow account data after the "Make" instruction
-        let vault_account = program.get_account(&vault).unwrap();
-        let vault_data = spl_token::state::Account::unpack(&vault_account.data).unwrap();
-        assert_eq!(vault_data.amount, 10);
-        assert_eq!(vault_data.owner, escrow);
-        assert_eq!(vault_data.mint, mint_a);
-
-        let escrow_account = program.get_account(&escrow).unwrap();
-        let escrow_data =
-            crate::state::Escrow::try_deserialize(&mut escrow_account.data.as_ref()).unwrap();
-        assert_eq!(escrow_data.seed, 123u64);
-        assert_eq!(escrow_data.maker, maker);
-        assert_eq!(escrow_data.mint_a, mint_a);
-        assert_eq!(escrow_data.mint_b, mint_b);
-        assert_eq!(escrow_data.receive, 10);
+        // ==================== FINAL FILL ====================
+        // The last fill sweeps the vault and closes both accounts
+        take_partial(&mut f, 20).unwrap();
+
+        assert_eq!(f.balance(&taker_ata_a), 100);
+        assert_eq!(f.balance(&maker_ata_b), 30);
+        assert!(f.is_closed(&vault));
+        assert!(f.is_closed(&keys.escrow()));
     }
 
     #[test]
-    fn test_take() {
-        // Setup the test environment by initializing LiteSVM and creating a payer keypair
-        let (mut program, payer) = setup();
+    fn test_take_respects_lock_and_expiry_boundaries() {
+        let mut f = Fixture::new();
+
+        let make = |f: &mut Fixture, seed: u64, unlock_after: i64, expires_at: Option<i64>| {
+            let keys = f.keys(seed);
+            let ix = keys.make_ix(MakeArgs {
+                unlock_after,
+                expires_at,
+                ..MakeArgs::new(100, 100)
+            });
+            f.send_as_maker(ix).map(|_| keys)
+        };
 
-        // Create separate maker and taker keypairs
-        let maker = Keypair::new();
-        let taker = Keypair::new();
+        // Moves the clock to `timestamp` and fills 10 of the escrow
+        let take_at = |f: &mut Fixture, keys: &EscrowKeys, timestamp: i64| {
+            f.warp_to(timestamp);
+            let ix = keys.take_partial_ix(&f.taker.pubkey(), Some(&f.config()), vec![], 10);
+            f.send_as_taker(ix)
+        };
 
-        // Airdrop SOL to both maker and taker
-        program
-            .airdrop(
-                &maker.pubkey(),
-                10u64.checked_mul(LAMPORTS_PER_SOL).expect("Overflow in airdrop amount"),
-            )
-            .unwrap();
-        program
-            .airdrop(
-                &taker.pubkey(),
-                10u64
-                    .checked_mul(LAMPORTS_PER_SOL)
-                    .expect("Overflow in airdrop amount"),
-            )
-            .unwrap();
+        let created_at = f.now();
 
-        // Create two mints (Mint A and Mint B) with 6 decimal places
-        let mint_a = CreateMint::new(&mut program, &payer)
-            .decimals(6)
-            .authority(&payer.pubkey())
-            .send()
-            .unwrap();
-        msg!("Mint A: {}\n", mint_a);
+        // ==================== INVALID WINDOWS ====================
+        assert_error(make(&mut f, 1, -1, None), EscrowError::InvalidLockPeriod);
+        assert_error(
+            make(&mut f, 1, 100, Some(created_at + 99)),
+            EscrowError::InvalidLockPeriod,
+        );
 
-        let mint_b = CreateMint::new(&mut program, &payer)
-            .decimals(6)
-            .authority(&payer.pubkey())
-            .send()
-            .unwrap();
-        msg!("Mint B: {}\n", mint_b);
+        // ==================== LOCK AND EXPIRY ====================
+        let keys = make(&mut f, 1, 100, Some(created_at + 200)).unwrap();
 
-        // Create the maker's associated token account for Mint A
-        let maker_ata_a = CreateAssociatedTokenAccount::new(&mut program, &payer, &mint_a)
-            .owner(&maker.pubkey())
-            .send()
-            .unwrap();
-        msg!("Maker ATA A: {}\n", maker_ata_a);
+        let escrow = f.escrow(&keys);
+        assert_eq!(escrow.created_at, created_at);
+        assert_eq!(escrow.lock_until, created_at + 100);
+        assert_eq!(escrow.expires_at, Some(created_at + 200));
 
-        // Create the taker's associated token account for Mint B (taker needs mint_b to send to maker)
-        let taker_ata_b = CreateAssociatedTokenAccount::new(&mut program, &payer, &mint_b)
-            .owner(&taker.pubkey())
-            .send()
-            .unwrap();
-        msg!("Taker ATA B: {}\n", taker_ata_b);
+        assert_error(
+            take_at(&mut f, &keys, created_at + 99),
+            EscrowError::TakeTooEarly,
+        );
 
-        // Derive the PDA for the escrow account using the maker's public key and a seed value
-        let escrow = Pubkey::find_program_address(
-            &[b"escrow", maker.pubkey().as_ref(), &123u64.to_le_bytes()],
-            &PROGRAM_ID,
-        )
-        .0;
-        msg!("Escrow PDA: {}\n", escrow);
+        // Both ends of the window are inclusive
+        take_at(&mut f, &keys, created_at + 100).unwrap();
+        take_at(&mut f, &keys, created_at + 200).unwrap();
 
-        // Derive the PDA for the vault associated token account using the escrow PDA and Mint A
-        let vault = associated_token::get_associated_token_address(&escrow, &mint_a);
-        msg!("Vault PDA: {}\n", vault);
+        assert_error(
+            take_at(&mut f, &keys, created_at + 201),
+            EscrowError::EscrowExpired,
+        );
 
-        // Define program IDs for associated token program, token program, and system program
-        let associated_token_program = spl_associated_token_account::ID;
-        let token_program = TOKEN_PROGRAM_ID;
-        let system_program = SYSTEM_PROGRAM_ID;
+        // The maker can still get the rest of the deposit back
+        f.send_as_maker(keys.refund_ix()).unwrap();
+        assert!(f.is_closed(&keys.escrow()));
 
-        // Mint tokens of Mint A to the maker's ATA (maker will deposit these into escrow)
-        MintTo::new(&mut program, &payer, &mint_a, &maker_ata_a, 1000000000)
-            .send()
-            .unwrap();
+        // ==================== NO LOCK, NO EXPIRY ====================
+        f.warp_to(created_at);
+        let keys = make(&mut f, 2, 0, None).unwrap();
 
-        // Mint tokens of Mint B to the taker's ATA (taker will send these to maker during take)
-        MintTo::new(&mut program, &payer, &mint_b, &taker_ata_b, 1000000000)
-            .send()
-            .unwrap();
+        take_at(&mut f, &keys, created_at).unwrap();
+        take_at(&mut f, &keys, i64::MAX).unwrap();
+    }
 
-        // ==================== MAKE INSTRUCTION ====================
-        // Create the "Make" instruction to deposit tokens into the escrow
-        let make_ix = Instruction {
-            program_id: PROGRAM_ID,
-            accounts: crate::accounts::Make {
-                maker: maker.pubkey(),
-                mint_a: mint_a,
-                mint_b: mint_b,
-                maker_ata_a: maker_ata_a,
-                escrow: escrow,
-                vault: vault,
-                associated_token_program: associated_token_program,
-                token_program: token_program,
-                system_program: system_program,
-            }
-            .to_account_metas(None),
-            data: crate::instruction::Make {
-                deposit: 100,
-                seed: 123u64,
-                receive: 50,
-            }
-            .data(),
+    #[test]
+    fn test_take_checks_allowlist() {
+        let mut f = Fixture::new();
+        let (mint_a, mint_b) = (f.mint_a, f.mint_b);
+
+        let taker = f.taker.insecure_clone();
+        let outsider = f.keypair();
+        f.mint_to(&outsider.pubkey(), &mint_b, INITIAL_BALANCE);
+
+        let make = |f: &mut Fixture, seed: u64, allowlist: TakerAllowlist| {
+            let keys = f.keys(seed);
+            let ix = keys.make_ix(MakeArgs {
+                allowlist,
+                ..MakeArgs::new(100, 50)
+            });
+            f.send_as_maker(ix).unwrap();
+            keys
         };
 
-        // Create and send the Make transaction
-        let message = Message::new(&[make_ix], Some(&maker.pubkey()));
-        let recent_blockhash = program.latest_blockhash();
-        let transaction = Transaction::new(&[&maker], message, recent_blockhash);
-        let tx = program.send_transaction(transaction).unwrap();
+        let take = |f: &mut Fixture, keys: &EscrowKeys, taker: &Keypair, proof: Vec<[u8; 32]>| {
+            let ix = keys.take_ix(&taker.pubkey(), Some(&f.config()), proof);
+            f.send(ix, taker)
+        };
 
-        msg!("\n\nMake transaction successful");
-        msg!("CUs Consumed: {}", tx.compute_units_consumed);
-        msg!("Tx Signature: {}", tx.signature);
+        // ==================== NAMED TAKER ====================
+        let keys = make(&mut f, 1, TakerAllowlist::Taker(taker.pubkey()));
 
-        // Verify the vault has the deposited tokens
-        let vault_account = program.get_account(&vault).unwrap();
-        let vault_data = spl_token::state::Account::unpack(&vault_account.data).unwrap();
-        assert_eq!(vault_data.amount, 100);
-        msg!("Vault balance after Make: {}", vault_data.amount);
-
-        // ==================== WARP TIME FORWARD 5 DAYS ====================
-        // Update the clock timestamp
-        let mut clock: Clock = program.get_sysvar();
-        clock.unix_timestamp = clock
-            .unix_timestamp
-            .checked_add(FIVE_DAYS_IN_SECONDS.checked_add(1).unwrap())
-            .expect("Overflow in clock timestamp addition");
-        program.set_sysvar(&clock);
-
-        // Also warp the slot forward and expire the blockhash
-        program.warp_to_slot(clock.slot.checked_add(1_000_000).unwrap());
-        program.expire_blockhash();
-
-        // ==================== TAKE INSTRUCTION ====================
-        // Derive the taker's ATA for mint_a (will receive tokens from vault)
-        let taker_ata_a = associated_token::get_associated_token_address(&taker.pubkey(), &mint_a);
-
-        // Derive the maker's ATA for mint_b (will receive tokens from taker)
-        let maker_ata_b = associated_token::get_associated_token_address(&maker.pubkey(), &mint_b);
-
-        // Create the "Take" instruction
-        let take_ix = Instruction {
-            program_id: PROGRAM_ID,
-            accounts: crate::accounts::Take {
-                taker: taker.pubkey(),
-                maker: maker.pubkey(),
-                mint_a: mint_a,
-                mint_b: mint_b,
-                taker_ata_a: taker_ata_a,
-                taker_ata_b: taker_ata_b,
-                maker_ata_b: maker_ata_b,
-                escrow: escrow,
-                vault: vault,
-                associated_token_program: associated_token_program,
-                token_program: token_program,
-                system_program: system_program,
-            }
-            .to_account_metas(None),
-            data: crate::instruction::Take {}.data(),
-        };
+        assert_error(
+            take(&mut f, &keys, &outsider, vec![]),
+            EscrowError::TakerNotAllowed,
+        );
+        take(&mut f, &keys, &taker, vec![]).unwrap();
+
+        // ==================== MERKLE ROOT ====================
+        // Four takers, `taker` is the first leaf
+        let leaves = [
+            allowlist_leaf(&taker.pubkey()),
+            allowlist_leaf(&Pubkey::new_unique()),
+            allowlist_leaf(&Pubkey::new_unique()),
+            allowlist_leaf(&Pubkey::new_unique()),
+        ];
+        let left = allowlist_node(&leaves[0], &leaves[1]);
+        let right = allowlist_node(&leaves[2], &leaves[3]);
+        let root = allowlist_node(&left, &right);
+        let proof = vec![leaves[1], right];
+
+        let keys = make(&mut f, 2, TakerAllowlist::MerkleRoot(root));
+
+        // Someone else's proof does not work for the outsider
+        assert_error(
+            take(&mut f, &keys, &outsider, proof.clone()),
+            EscrowError::TakerNotAllowed,
+        );
 
-        // Create and send the Take transaction
-        let message = Message::new(&[take_ix], Some(&taker.pubkey()));
-        let recent_blockhash = program.latest_blockhash();
-        let transaction = Transaction::new(&[&taker], message, recent_blockhash);
-        let tx = program.send_transaction(transaction).unwrap();
-
-        msg!("\n\nTake transaction successful");
-        msg!("CUs Consumed: {}", tx.compute_units_consumed);
-        msg!("Tx Signature: {}", tx.signature);
-
-        // ==================== VERIFY FINAL STATE ====================
-        // Verify taker received mint_a tokens from vault
-        let taker_ata_a_account = program.get_account(&taker_ata_a).unwrap();
-        let taker_ata_a_data =
-            spl_token::state::Account::unpack(&taker_ata_a_account.data).unwrap();
-        assert_eq!(taker_ata_a_data.amount, 100);
-        msg!("Taker ATA A balance: {}", taker_ata_a_data.amount);
-
-        // Verify maker received mint_b tokens from taker
-        let maker_ata_b_account = program.get_account(&maker_ata_b).unwrap();
-        let maker_ata_b_data =
-            spl_token::state::Account::unpack(&maker_ata_b_account.data).unwrap();
-        assert_eq!(maker_ata_b_data.amount, 50);
-        msg!("Maker ATA B balance: {}", maker_ata_b_data.amount);
-
-        // Verify vault is closed (should have 0 lamports or not exist)
-        match program.get_account(&vault) {
-            Some(acc) => {
-                // If account exists, it should have 0 lamports (closed)
-                assert_eq!(
-                    acc.lamports, 0,
-                    "Vault should have 0 lamports after closing"
-                );
-                msg!("Vault closed (0 lamports)");
-            }
-            None => {
-                msg!("Vault closed successfully (account removed)");
-            }
+        // Missing, truncated and tampered proofs are rejected
+        let mut bad_proof = proof.clone();
+        bad_proof[1][0] ^= 1;
+        for proof in [vec![], vec![leaves[1]], bad_proof] {
+            assert_error(
+                take(&mut f, &keys, &taker, proof),
+                EscrowError::TakerNotAllowed,
+            );
         }
 
-        // Verify escrow is closed
-        match program.get_account(&escrow) {
-            Some(acc) => {
-                assert_eq!(
-                    acc.lamports, 0,
-                    "Escrow should have 0 lamports after closing"
-                );
-                msg!("Escrow closed (0 lamports)");
-            }
-            None => {
-                msg!("Escrow closed successfully (account removed)");
-            }
-        }
+        take(&mut f, &keys, &taker, proof).unwrap();
+
+        let taker_ata_a = keys.token_account(&taker.pubkey(), &mint_a).unwrap();
+        assert_eq!(f.balance(&taker_ata_a), 200);
     }
 
     #[test]
-    fn test_refund() {
-        // Setup the test environment
-        let (mut program, payer) = setup();
+    fn test_events_are_emitted() {
+        let mut f = Fixture::new();
+        let (maker, taker) = (f.maker.pubkey(), f.taker.pubkey());
 
-        // Create maker keypair
-        let maker = Keypair::new();
-        program
-            .airdrop(
-                &maker.pubkey(),
-                10u64
-                    .checked_mul(LAMPORTS_PER_SOL)
-                    .expect("Overflow in airdrop amount"),
-            )
-            .unwrap();
+        let keys = f.keys(42);
+        let escrow = keys.escrow();
 
-        // Create mint A
-        let mint_a = CreateMint::new(&mut program, &payer)
-            .decimals(6)
-            .authority(&payer.pubkey())
-            .send()
-            .unwrap();
-        msg!("Mint A: {}\n", mint_a);
+        // ==================== MAKE ====================
+        let timestamp = f.now();
+        let logs = f
+            .send_as_maker(keys.make_ix(MakeArgs::new(100, 30)))
+            .unwrap()
+            .logs;
+
+        let created = parse_events::<EscrowCreated>(&logs);
+        assert_eq!(created.len(), 1);
+        assert_eq!(created[0].escrow, escrow);
+        assert_eq!(created[0].maker, maker);
+        assert_eq!(created[0].mint_a, f.mint_a);
+        assert_eq!(created[0].mint_b, f.mint_b);
+        assert_eq!(created[0].seed, 42);
+        assert_eq!(created[0].deposit, 100);
+        assert_eq!(created[0].receive, 30);
+        assert_eq!(created[0].timestamp, timestamp);
+
+        // ==================== PARTIAL TAKE ====================
+        let ix = keys.take_partial_ix(&taker, Some(&f.config()), vec![], 10);
+        let logs = f.send_as_taker(ix).unwrap().logs;
+
+        assert!(parse_events::<EscrowCreated>(&logs).is_empty());
+        let taken = parse_events::<EscrowTaken>(&logs);
+        assert_eq!(taken.len(), 1);
+        assert_eq!(taken[0].escrow, escrow);
+        assert_eq!(taken[0].maker, maker);
+        assert_eq!(taken[0].taker, taker);
+        assert_eq!(taken[0].mint_a, f.mint_a);
+        assert_eq!(taken[0].mint_b, f.mint_b);
+        assert_eq!(taken[0].seed, 42);
+        assert_eq!(taken[0].amount_paid, 10);
+        assert_eq!(taken[0].amount_released, 33);
+        assert_eq!(taken[0].outstanding, 20);
+        assert_eq!(taken[0].timestamp, timestamp);
+
+        // ==================== REFUND ====================
+        let logs = f.send_as_maker(keys.refund_ix()).unwrap().logs;
+
+        let refunded = parse_events::<EscrowRefunded>(&logs);
+        assert_eq!(refunded.len(), 1);
+        assert_eq!(refunded[0].escrow, escrow);
+        assert_eq!(refunded[0].maker, maker);
+        assert_eq!(refunded[0].mint_a, f.mint_a);
+        assert_eq!(refunded[0].mint_b, f.mint_b);
+        assert_eq!(refunded[0].seed, 42);
+        assert_eq!(refunded[0].amount, 67);
+        assert_eq!(refunded[0].timestamp, timestamp);
+    }
 
-        // Create mint B (needed for escrow creation)
-        let mint_b = CreateMint::new(&mut program, &payer)
-            .decimals(6)
-            .authority(&payer.pubkey())
-            .send()
-            .unwrap();
-        msg!("Mint B: {}\n", mint_b);
+    #[test]
+    fn test_update_changes_every_field() {
+        let mut f = Fixture::new();
+        let mint_a = f.mint_a;
+
+        let impostor = f.keypair();
+        let impostor_ata_a = f.mint_to(&impostor.pubkey(), &mint_a, 0);
+
+        let keys = f.keys(7);
+        let vault = keys.vault().unwrap();
+        let maker_ata_a = keys.token_account(&f.maker.pubkey(), &mint_a).unwrap();
+        let created_at = f.now();
+
+        let ix = keys.make_ix(MakeArgs {
+            expires_at: Some(created_at + 1000),
+            ..MakeArgs::new(100, 50)
+        });
+        f.send_as_maker(ix).unwrap();
+
+        let update = |f: &mut Fixture, args: UpdateArgs| f.send_as_maker(keys.update_ix(args));
+
+        // ==================== RECEIVE ====================
+        let tx = update(
+            &mut f,
+            UpdateArgs {
+                receive: Some(80),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(f.escrow(&keys).receive, 80);
+        assert_eq!(f.escrow(&keys).expires_at, Some(created_at + 1000));
+
+        let updated = parse_events::<EscrowUpdated>(&tx.logs);
+        assert_eq!(updated.len(), 1);
+        assert_eq!(updated[0].escrow, keys.escrow());
+        assert_eq!(updated[0].receive, 80);
+        assert_eq!(updated[0].remaining, 100);
+
+        // ==================== TOP UP ====================
+        update(
+            &mut f,
+            UpdateArgs {
+                top_up: 50,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(f.escrow(&keys).remaining, 150);
+        assert_eq!(f.balance(&vault), 150);
+        assert_eq!(f.balance(&maker_ata_a), INITIAL_BALANCE - 150);
+
+        // ==================== WITHDRAW ====================
+        update(
+            &mut f,
+            UpdateArgs {
+                withdraw: 30,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(f.escrow(&keys).remaining, 120);
+        assert_eq!(f.balance(&vault), 120);
+        assert_eq!(f.balance(&maker_ata_a), INITIAL_BALANCE - 120);
+
+        // ==================== EXPIRY ====================
+        update(
+            &mut f,
+            UpdateArgs {
+                expires_at: Some(Some(created_at + 5000)),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(f.escrow(&keys).expires_at, Some(created_at + 5000));
+
+        update(
+            &mut f,
+            UpdateArgs {
+                expires_at: Some(None),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(f.escrow(&keys).expires_at, None);
+
+        // ==================== INVALID UPDATES ====================
+        // The whole deposit cannot be withdrawn, refund is the way to close the escrow
+        let invalid = [
+            (
+                UpdateArgs {
+                    withdraw: 120,
+                    ..Default::default()
+                },
+                EscrowError::InvalidUpdate,
+            ),
+            (
+                UpdateArgs {
+                    receive: Some(0),
+                    ..Default::default()
+                },
+                EscrowError::InvalidUpdate,
+            ),
+            (
+                UpdateArgs {
+                    expires_at: Some(Some(created_at - 1)),
+                    ..Default::default()
+                },
+                EscrowError::InvalidLockPeriod,
+            ),
+        ];
+        for (args, error) in invalid {
+            assert_error(update(&mut f, args), error);
+        }
 
-        // Create the maker's associated token account for Mint A
-        let maker_ata_a = CreateAssociatedTokenAccount::new(&mut program, &payer, &mint_a)
-            .owner(&maker.pubkey())
-            .send()
-            .unwrap();
-        msg!("Maker ATA A: {}\n", maker_ata_a);
+        // Only the maker can update, an impostor signing as maker fails the PDA check
+        let mut ix = keys.update_ix(UpdateArgs {
+            receive: Some(1),
+            withdraw: 60,
+            ..Default::default()
+        });
+        ix.accounts[0] = AccountMeta::new(impostor.pubkey(), true);
+        ix.accounts[2] = AccountMeta::new(impostor_ata_a, false);
+        assert!(f.send(ix, &impostor).is_err());
+
+        let escrow = f.escrow(&keys);
+        assert_eq!(escrow.receive, 80);
+        assert_eq!(escrow.remaining, 120);
+        assert_eq!(escrow.maker, f.maker.pubkey());
+
+        // ==================== TAKE AT THE NEW TERMS ====================
+        // Past the original expiry, which the update removed
+        f.warp_to(created_at + 10000);
+
+        let ix = keys.take_ix(&f.taker.pubkey(), Some(&f.config()), vec![]);
+        f.send_as_taker(ix).unwrap();
+
+        let taker_ata_a = keys.token_account(&f.taker.pubkey(), &mint_a).unwrap();
+        let maker_ata_b = keys.token_account(&f.maker.pubkey(), &f.mint_b).unwrap();
+        assert_eq!(f.balance(&taker_ata_a), 120);
+        assert_eq!(f.balance(&maker_ata_b), 80);
+    }
 
-        // Derive the PDA for the escrow account
-        let escrow = Pubkey::find_program_address(
-            &[b"escrow", maker.pubkey().as_ref(), &456u64.to_le_bytes()],
-            &PROGRAM_ID,
-        )
-        .0;
-        msg!("Escrow PDA: {}\n", escrow);
+    #[test]
+    fn test_make_and_top_up_credit_what_a_transfer_fee_mint_delivers() {
+        let mut f = Fixture::new();
+        let maker = f.maker.pubkey();
 
-        // Derive the vault ATA
-        let vault = associated_token::get_associated_token_address(&escrow, &mint_a);
-        msg!("Vault PDA: {}\n", vault);
+        // 1% of every transfer is withheld, capped well above what the test moves
+        let mint_a = f.create_transfer_fee_mint(100, 1_000_000);
+        let maker_ata_a = f.mint_to_with(&maker, &mint_a, &spl_token_2022::ID, 10_000);
 
-        let associated_token_program = spl_associated_token_account::ID;
-        let token_program = TOKEN_PROGRAM_ID;
-        let system_program = SYSTEM_PROGRAM_ID;
+        let keys =
+            EscrowKeys::new(maker, mint_a, f.mint_b, 1).with_token_program(spl_token_2022::ID);
+        let vault = keys.vault().unwrap();
 
-        // Mint tokens to maker's ATA
-        MintTo::new(&mut program, &payer, &mint_a, &maker_ata_a, 1000000000)
-            .send()
+        // ==================== MAKE 1000, THE VAULT GETS 990 ====================
+        let tx = f
+            .send_as_maker(keys.make_ix(MakeArgs::new(1000, 50)))
             .unwrap();
 
-        // Check initial maker balance
-        let maker_ata_account = program.get_account(&maker_ata_a).unwrap();
-        let maker_ata_data = spl_token::state::Account::unpack(&maker_ata_account.data).unwrap();
-        let initial_balance = maker_ata_data.amount;
-        msg!("Maker initial balance: {}", initial_balance);
+        assert_eq!(f.balance(&vault), 990);
+        assert_eq!(f.escrow(&keys).remaining, 990);
+        assert_eq!(parse_events::<EscrowCreated>(&tx.logs)[0].deposit, 990);
 
-        // ==================== MAKE INSTRUCTION ====================
-        let make_ix = Instruction {
-            program_id: PROGRAM_ID,
-            accounts: crate::accounts::Make {
-                maker: maker.pubkey(),
-                mint_a: mint_a,
-                mint_b: mint_b,
-                maker_ata_a: maker_ata_a,
-                escrow: escrow,
-                vault: vault,
-                associated_token_program: associated_token_program,
-                token_program: token_program,
-                system_program: system_program,
-            }
-            .to_account_metas(None),
-            data: crate::instruction::Make {
-                deposit: 200,
-                seed: 456u64,
-                receive: 100,
+        // ==================== TOP UP 500, THE VAULT GETS 495 ====================
+        let ix = keys.update_ix(UpdateArgs {
+            top_up: 500,
+            ..Default::default()
+        });
+        f.send_as_maker(ix).unwrap();
+
+        assert_eq!(f.balance(&vault), 1485);
+        assert_eq!(f.escrow(&keys).remaining, 1485);
+        assert_eq!(f.balance(&maker_ata_a), 10_000 - 1500);
+    }
+
+    #[test]
+    fn test_native_sol_as_mint_b() {
+        let mut f = Fixture::new();
+        add_native_mint(&mut f.program);
+        let (maker, taker) = (f.maker.pubkey(), f.taker.pubkey());
+
+        let keys = EscrowKeys::new(maker, f.mint_a, NATIVE_MINT, 1);
+        let escrow = keys.escrow();
+        let vault = keys.vault().unwrap();
+        let taker_ata_a = keys.token_account(&taker, &f.mint_a).unwrap();
+
+        // ==================== MAKE: 100 TOKENS FOR 2 SOL ====================
+        f.send_as_maker(keys.make_ix(MakeArgs::new(100, 2 * LAMPORTS_PER_SOL)))
+            .unwrap();
+
+        let take = |f: &mut Fixture, amount: u64, token_accounts: bool| {
+            let mut ix = keys.take_partial_ix(&taker, Some(&f.config()), vec![], amount);
+            if token_accounts {
+                let taker_ata_b = client::ata(&taker, &NATIVE_MINT, &TOKEN_PROGRAM_ID);
+                let maker_ata_b = client::ata(&maker, &NATIVE_MINT, &TOKEN_PROGRAM_ID);
+                ix.accounts[5] = AccountMeta::new(taker_ata_b, false);
+                ix.accounts[6] = AccountMeta::new(maker_ata_b, false);
             }
-            .data(),
+            f.send_as_taker(ix)
         };
 
-        let message = Message::new(&[make_ix], Some(&maker.pubkey()));
-        let recent_blockhash = program.latest_blockhash();
-        let transaction = Transaction::new(&[&maker], message, recent_blockhash);
-        let tx = program.send_transaction(transaction).unwrap();
+        // The native side takes no token accounts
+        assert!(take(&mut f, LAMPORTS_PER_SOL, true).is_err());
 
-        msg!("\n\nMake transaction successful");
-        msg!("CUs Consumed: {}", tx.compute_units_consumed);
+        // ==================== TAKE HALF FOR 1 SOL ====================
+        let maker_lamports = f.lamports(&maker);
+        take(&mut f, LAMPORTS_PER_SOL, false).unwrap();
 
-        // Verify vault has tokens
-        let vault_account = program.get_account(&vault).unwrap();
-        let vault_data = spl_token::state::Account::unpack(&vault_account.data).unwrap();
-        assert_eq!(vault_data.amount, 200);
-        msg!("Vault balance after Make: {}", vault_data.amount);
+        assert_eq!(f.lamports(&maker), maker_lamports + LAMPORTS_PER_SOL);
+        assert_eq!(f.balance(&taker_ata_a), 50);
 
-        // ==================== REFUND INSTRUCTION ====================
-        let refund_ix = Instruction {
-            program_id: PROGRAM_ID,
-            accounts: crate::accounts::Refund {
-                maker: maker.pubkey(),
-                mint_a: mint_a,
-                maker_ata_a: maker_ata_a,
-                escrow: escrow,
-                vault: vault,
-                token_program: token_program,
-                system_program: system_program,
+        // ==================== TAKE THE REST ====================
+        let maker_lamports = f.lamports(&maker);
+        let rent = f.lamports(&escrow) + f.lamports(&vault);
+        take(&mut f, LAMPORTS_PER_SOL, false).unwrap();
+
+        // The maker gets the SOL plus the rent of the closed escrow and vault
+        assert_eq!(f.lamports(&maker), maker_lamports + LAMPORTS_PER_SOL + rent);
+        assert_eq!(f.balance(&taker_ata_a), 100);
+        assert!(f.is_closed(&escrow));
+    }
+
+    #[test]
+    fn test_native_sol_as_mint_a() {
+        let mut f = Fixture::new();
+        add_native_mint(&mut f.program);
+        let (maker, taker) = (f.maker.pubkey(), f.taker.pubkey());
+
+        let keys = EscrowKeys::new(maker, NATIVE_MINT, f.mint_b, 2);
+        let escrow = keys.escrow();
+        let maker_ata_b = keys.token_account(&maker, &f.mint_b).unwrap();
+
+        let make = |f: &mut Fixture, vault: Option<Pubkey>| {
+            let mut ix = keys.make_ix(MakeArgs::new(3 * LAMPORTS_PER_SOL, 30));
+            if let Some(vault) = vault {
+                ix.accounts[5] = AccountMeta::new(vault, false);
             }
-            .to_account_metas(None),
-            data: crate::instruction::Refund {}.data(),
+            f.send_as_maker(ix)
         };
 
-        let message = Message::new(&[refund_ix], Some(&maker.pubkey()));
-        let recent_blockhash = program.latest_blockhash();
-        let transaction = Transaction::new(&[&maker], message, recent_blockhash);
-        let tx = program.send_transaction(transaction).unwrap();
-
-        msg!("\n\nRefund transaction successful");
-        msg!("CUs Consumed: {}", tx.compute_units_consumed);
-
-        // ==================== VERIFY FINAL STATE ====================
-        // Verify maker received tokens back
-        let maker_ata_account = program.get_account(&maker_ata_a).unwrap();
-        let maker_ata_data = spl_token::state::Account::unpack(&maker_ata_account.data).unwrap();
-        assert_eq!(maker_ata_data.amount, initial_balance);
-        msg!("Maker balance after refund: {}", maker_ata_data.amount);
-
-        // Verify vault is closed
-        match program.get_account(&vault) {
-            Some(acc) => {
-                assert_eq!(
-                    acc.lamports, 0,
-                    "Vault should have 0 lamports after closing"
-                );
-                msg!("Vault closed (0 lamports)");
-            }
-            None => {
-                msg!("Vault closed successfully");
-            }
-        }
+        // A vault for native SOL is rejected
+        let native_vault = client::ata(&escrow, &NATIVE_MINT, &TOKEN_PROGRAM_ID);
+        assert_error(
+            make(&mut f, Some(native_vault)),
+            EscrowError::InvalidTokenAccounts,
+        );
 
-        // Verify escrow is closed
-        match program.get_account(&escrow) {
-            Some(acc) => {
-                assert_eq!(
-                    acc.lamports, 0,
-                    "Escrow should have 0 lamports after closing"
-                );
-                msg!("Escrow closed (0 lamports)");
-            }
-            None => {
-                msg!("Escrow closed successfully");
-            }
-        }
+        // ==================== MAKE: 3 SOL FOR 30 TOKENS ====================
+        make(&mut f, None).unwrap();
+
+        let escrow_lamports = f.lamports(&escrow);
+        let escrow_len = f.program.get_account(&escrow).unwrap().data.len();
+        assert_eq!(
+            escrow_lamports,
+            f.program.minimum_balance_for_rent_exemption(escrow_len) + 3 * LAMPORTS_PER_SOL
+        );
+
+        // ==================== TAKE A THIRD ====================
+        let taker_lamports = f.lamports(&taker);
+        let ix = keys.take_partial_ix(&taker, Some(&f.config()), vec![], 10);
+        f.send_as_taker(ix).unwrap();
+
+        // The SOL comes straight out of the escrow account, the taker also pays for its
+        // transaction and the maker's new token account
+        assert_eq!(f.lamports(&escrow), escrow_lamports - LAMPORTS_PER_SOL);
+        let taker_spent = f.lamports(&maker_ata_b) + 5000;
+        assert_eq!(
+            f.lamports(&taker),
+            taker_lamports + LAMPORTS_PER_SOL - taker_spent
+        );
+        assert_eq!(f.balance(&maker_ata_b), 10);
+
+        // ==================== REFUND THE REST ====================
+        let maker_lamports = f.lamports(&maker);
+        let escrow_lamports = f.lamports(&escrow);
+        let tx = f.send_as_maker(keys.refund_ix()).unwrap();
+
+        let refunded = parse_events::<EscrowRefunded>(&tx.logs);
+        assert_eq!(refunded[0].amount, 2 * LAMPORTS_PER_SOL);
+        assert_eq!(f.lamports(&maker), maker_lamports + escrow_lamports - 5000);
+        assert!(f.is_closed(&escrow));
     }
 
     #[test]
-    fn test_take_fails_before_5_days() {
-        // Setup the test environment
-        let (mut program, payer) = setup();
+    fn test_basket_make_take_and_refund() {
+        let mut f = Fixture::new();
+        let (maker, taker) = (f.maker.pubkey(), f.taker.pubkey());
+
+        // Three mints the maker gives, two the maker wants back
+        let give_mints: Vec<Pubkey> = (0..3)
+            .map(|_| {
+                let mint = f.create_mint(6);
+                f.mint_to(&maker, &mint, 1000);
+                mint
+            })
+            .collect();
+
+        let receive_mints: Vec<Pubkey> = (0..2)
+            .map(|_| {
+                let mint = f.create_mint(9);
+                f.mint_to(&taker, &mint, 1000);
+                mint
+            })
+            .collect();
+
+        let give: Vec<BasketLeg> = give_mints
+            .iter()
+            .zip([100, 200, 300])
+            .map(|(&mint, amount)| BasketLeg { mint, amount })
+            .collect();
+        let receive: Vec<BasketLeg> = receive_mints
+            .iter()
+            .zip([40, 50])
+            .map(|(&mint, amount)| BasketLeg { mint, amount })
+            .collect();
+
+        let ata = |owner: &Pubkey, mint: &Pubkey| client::ata(owner, mint, &TOKEN_PROGRAM_ID);
+        let basket_data = |f: &Fixture, keys: &BasketKeys| {
+            let account = f.program.get_account(&keys.basket()).unwrap();
+            client::Basket::decode(&account.data).unwrap()
+        };
 
-        // Create separate maker and taker keypairs
-        let maker = Keypair::new();
-        let taker = Keypair::new();
+        // ==================== INVALID LEGS ====================
+        let keys = BasketKeys::new(maker, 0);
 
-        program
-            .airdrop(
-                &maker.pubkey(),
-                10u64
-                    .checked_mul(LAMPORTS_PER_SOL)
-                    .expect("Overflow in airdrop amount"),
-            )
-            .unwrap();
-        program
-            .airdrop(
-                &taker.pubkey(),
-                10u64
-                    .checked_mul(LAMPORTS_PER_SOL)
-                    .expect("Overflow in airdrop amount"),
-            )
+        let mut duplicated = give.clone();
+        duplicated[1].mint = duplicated[0].mint;
+        assert_error(
+            f.send_as_maker(keys.make_ix(duplicated, receive.clone())),
+            EscrowError::InvalidBasket,
+        );
+        assert_error(
+            f.send_as_maker(keys.make_ix(give.clone(), vec![])),
+            EscrowError::InvalidBasket,
+        );
+
+        // A vault that is not the basket's ATA for the leg's mint, the remaining
+        // accounts start after the five named ones
+        let mut ix = keys.make_ix(give.clone(), receive.clone());
+        ix.accounts[5 + 2].pubkey = ata(&maker, &give_mints[1]);
+        assert_error(f.send_as_maker(ix), EscrowError::BasketAccountMismatch);
+
+        // ==================== MAKE AND TAKE ====================
+        f.send_as_maker(keys.make_ix(give.clone(), receive.clone()))
             .unwrap();
 
-        // Create mints
-        let mint_a = CreateMint::new(&mut program, &payer)
-            .decimals(6)
-            .authority(&payer.pubkey())
-            .send()
+        let basket = basket_data(&f, &keys);
+        assert_eq!(basket.maker, maker);
+        assert_eq!(basket.give, give);
+        assert_eq!(basket.receive, receive);
+
+        for leg in &give {
+            assert_eq!(f.balance(&keys.vault(&leg.mint)), leg.amount);
+            assert_eq!(f.balance(&ata(&maker, &leg.mint)), 1000 - leg.amount);
+        }
+
+        let take_ix = keys.take_ix(&taker, &basket);
+
+        // Dropping a leg fails the whole take
+        let mut dropped_leg = take_ix.clone();
+        dropped_leg.accounts.truncate(take_ix.accounts.len() - 3);
+        assert_error(
+            f.send_as_taker(dropped_leg),
+            EscrowError::BasketAccountMismatch,
+        );
+
+        // So does paying into someone else's account
+        let mut wrong_maker_ata = take_ix.clone();
+        wrong_maker_ata.accounts.last_mut().unwrap().pubkey = ata(&taker, &receive_mints[1]);
+        assert_error(
+            f.send_as_taker(wrong_maker_ata),
+            EscrowError::BasketAccountMismatch,
+        );
+
+        f.send_as_taker(take_ix).unwrap();
+
+        for leg in &give {
+            assert_eq!(f.balance(&ata(&taker, &leg.mint)), leg.amount);
+            assert!(f.is_closed(&keys.vault(&leg.mint)));
+        }
+        for leg in &receive {
+            assert_eq!(f.balance(&ata(&maker, &leg.mint)), leg.amount);
+            assert_eq!(f.balance(&ata(&taker, &leg.mint)), 1000 - leg.amount);
+        }
+        assert!(f.is_closed(&keys.basket()));
+
+        // ==================== MAKE AND REFUND ====================
+        let keys = BasketKeys::new(maker, 1);
+
+        // Anyone can open the basket's ATA ahead of the make, which must not block it
+        f.mint_to(&keys.basket(), &give_mints[0], 0);
+
+        f.send_as_maker(keys.make_ix(give.clone(), receive.clone()))
             .unwrap();
 
-        let mint_b = CreateMint::new(&mut program, &payer)
-            .decimals(6)
-            .authority(&payer.pubkey())
-            .send()
+        let basket = basket_data(&f, &keys);
+        f.send_as_maker(keys.refund_ix(&basket)).unwrap();
+
+        for leg in &give {
+            // The first basket went to the taker, this one came back
+            assert_eq!(f.balance(&ata(&maker, &leg.mint)), 1000 - leg.amount);
+            assert!(f.is_closed(&keys.vault(&leg.mint)));
+        }
+        assert!(f.is_closed(&keys.basket()));
+    }
+
+    #[test]
+    fn test_only_the_upgrade_authority_initializes_the_config() {
+        let mut f = Fixture::without_config();
+        let payer = f.payer.pubkey();
+        let intruder = f.keypair();
+
+        let ix = client::initialize_config_ix(&intruder.pubkey(), 0, &intruder.pubkey());
+        assert_error(f.send(ix, &intruder), EscrowError::Unauthorized);
+        assert!(f.program.get_account(&config_address()).is_none());
+
+        // Until then escrows are made without a fee and taken without the config
+        let keys = f.keys(1);
+        let maker_ata_b = keys.token_account(&f.maker.pubkey(), &f.mint_b).unwrap();
+        f.send_as_maker(keys.make_ix(MakeArgs::new(100, 50)))
+            .unwrap();
+        assert_eq!(f.escrow(&keys).fee_bps, 0);
+        f.send_as_taker(keys.take_ix(&f.taker.pubkey(), None, vec![]))
             .unwrap();
+        assert_eq!(f.balance(&maker_ata_b), 50);
 
-        // Create maker's ATA for mint_a
-        let maker_ata_a = CreateAssociatedTokenAccount::new(&mut program, &payer, &mint_a)
-            .owner(&maker.pubkey())
-            .send()
+        // At most 10%
+        assert_error(
+            f.send_as_payer(client::initialize_config_ix(&payer, 1_001, &payer)),
+            EscrowError::InvalidFee,
+        );
+
+        f.send_as_payer(client::initialize_config_ix(&payer, 1_000, &payer))
             .unwrap();
+        assert_eq!(f.config().admin, payer);
+        assert_eq!(f.config().fee_bps, 1_000);
+    }
 
-        // Create taker's ATA for mint_b
-        let taker_ata_b = CreateAssociatedTokenAccount::new(&mut program, &payer, &mint_b)
-            .owner(&taker.pubkey())
-            .send()
+    #[test]
+    fn test_take_splits_the_fee_with_the_treasury() {
+        let mut f = Fixture::new();
+        let mint_b = f.mint_b;
+        let payer = f.payer.pubkey();
+
+        let intruder = f.keypair();
+        let treasury = f.keypair();
+
+        // ==================== CONFIG IS ADMIN ONLY ====================
+        // The config already exists, it cannot be initialized again
+        assert!(f
+            .send_as_payer(client::initialize_config_ix(&payer, 0, &payer))
+            .is_err());
+
+        let ix = client::update_config_ix(&intruder.pubkey(), None, Some(1_000), None, None);
+        assert_error(f.send(ix, &intruder), EscrowError::Unauthorized);
+        let ix = client::update_config_ix(
+            &intruder.pubkey(),
+            Some(intruder.pubkey()),
+            None,
+            Some(intruder.pubkey()),
+            None,
+        );
+        assert_error(f.send(ix, &intruder), EscrowError::Unauthorized);
+
+        let ix = client::update_config_ix(&payer, None, Some(1_001), None, None);
+        assert_error(f.send_as_payer(ix), EscrowError::InvalidFee);
+
+        // ==================== MADE WITHOUT A FEE ====================
+        let free = f.keys(8);
+        f.send_as_maker(free.make_ix(MakeArgs::new(100, 1000)))
             .unwrap();
 
-        // Derive escrow and vault
-        let escrow = Pubkey::find_program_address(
-            &[b"escrow", maker.pubkey().as_ref(), &789u64.to_le_bytes()],
-            &PROGRAM_ID,
-        )
-        .0;
+        // 2.5% to a new treasury
+        let ix = client::update_config_ix(&payer, None, Some(250), Some(treasury.pubkey()), None);
+        f.send_as_payer(ix).unwrap();
 
-        let vault = associated_token::get_associated_token_address(&escrow, &mint_a);
+        let config = f.config();
+        assert_eq!(config.admin, f.payer.pubkey());
+        assert_eq!(config.fee_bps, 250);
+        assert_eq!(config.treasury, treasury.pubkey());
 
-        let associated_token_program = spl_associated_token_account::ID;
-        let token_program = TOKEN_PROGRAM_ID;
-        let system_program = SYSTEM_PROGRAM_ID;
+        // ==================== MAKE: 100 A FOR 1001 B AT 2.5% ====================
+        let keys = f.keys(9);
+        let taker_ata_a = keys.token_account(&f.taker.pubkey(), &f.mint_a).unwrap();
+        let taker_ata_b = keys.token_account(&f.taker.pubkey(), &mint_b).unwrap();
+        let maker_ata_b = keys.token_account(&f.maker.pubkey(), &mint_b).unwrap();
+        let treasury_ata_b = keys.token_account(&treasury.pubkey(), &mint_b).unwrap();
 
-        // Mint tokens
-        MintTo::new(&mut program, &payer, &mint_a, &maker_ata_a, 1000000000)
-            .send()
+        f.send_as_maker(keys.make_ix(MakeArgs::new(100, 1001)))
             .unwrap();
+        assert_eq!(f.escrow(&keys).fee_bps, 250);
+        assert_eq!(f.escrow(&free).fee_bps, 0);
 
-        MintTo::new(&mut program, &payer, &mint_b, &taker_ata_b, 1000000000)
-            .send()
-            .unwrap();
+        // The escrow keeps the fee it was made with
+        let ix = client::update_config_ix(&payer, None, Some(1_000), None, None);
+        f.send_as_payer(ix).unwrap();
 
-        // ==================== MAKE INSTRUCTION ====================
-        let make_ix = Instruction {
-            program_id: PROGRAM_ID,
-            accounts: crate::accounts::Make {
-                maker: maker.pubkey(),
-                mint_a: mint_a,
-                mint_b: mint_b,
-                maker_ata_a: maker_ata_a,
-                escrow: escrow,
-                vault: vault,
-                associated_token_program: associated_token_program,
-                token_program: token_program,
-                system_program: system_program,
-            }
-            .to_account_metas(None),
-            data: crate::instruction::Make {
-                deposit: 100,
-                seed: 789u64,
-                receive: 50,
-            }
-            .data(),
+        let take_ix = |f: &Fixture, amount: u64| {
+            keys.take_partial_ix(&f.taker.pubkey(), Some(&f.config()), vec![], amount)
         };
 
-        let message = Message::new(&[make_ix], Some(&maker.pubkey()));
-        let recent_blockhash = program.latest_blockhash();
-        let transaction = Transaction::new(&[&maker], message, recent_blockhash);
-        program.send_transaction(transaction).unwrap();
+        // The fee cannot be sent anywhere but the configured treasury, which with
+        // its ATA sits at 10 and 11 in the take accounts
+        let mut wrong_treasury = take_ix(&f, 399);
+        wrong_treasury.accounts[10].pubkey = f.taker.pubkey();
+        wrong_treasury.accounts[11].pubkey = taker_ata_b;
+        assert_error(
+            f.send_as_taker(wrong_treasury),
+            EscrowError::InvalidTreasury,
+        );
 
-        msg!("Make transaction successful");
+        // And must be paid when it is not zero
+        let mut no_treasury_ata = take_ix(&f, 399);
+        no_treasury_ata.accounts[11] = AccountMeta::new_readonly(PROGRAM_ID, false);
+        assert_error(
+            f.send_as_taker(no_treasury_ata),
+            EscrowError::InvalidTokenAccounts,
+        );
 
-        // ==================== TAKE INSTRUCTION (should fail - too early) ====================
-        let taker_ata_a = associated_token::get_associated_token_address(&taker.pubkey(), &mint_a);
-        let maker_ata_b = associated_token::get_associated_token_address(&maker.pubkey(), &mint_b);
+        // ==================== TAKE 399: FEE 9.975 ROUNDS DOWN TO 9 ====================
+        // The treasury has no ATA for mint B yet, the take creates it
+        assert!(f.program.get_account(&treasury_ata_b).is_none());
+        let tx = f.send_as_taker(take_ix(&f, 399)).unwrap();
 
-        let take_ix = Instruction {
-            program_id: PROGRAM_ID,
-            accounts: crate::accounts::Take {
-                taker: taker.pubkey(),
-                maker: maker.pubkey(),
-                mint_a: mint_a,
-                mint_b: mint_b,
-                taker_ata_a: taker_ata_a,
-                taker_ata_b: taker_ata_b,
-                maker_ata_b: maker_ata_b,
-                escrow: escrow,
-                vault: vault,
-                associated_token_program: associated_token_program,
-                token_program: token_program,
-                system_program: system_program,
-            }
-            .to_account_metas(None),
-            data: crate::instruction::Take {}.data(),
-        };
+        assert_eq!(f.balance(&maker_ata_b), 390);
+        assert_eq!(f.balance(&treasury_ata_b), 9);
+        assert_eq!(f.balance(&taker_ata_b), INITIAL_BALANCE - 399);
 
-        let message = Message::new(&[take_ix], Some(&taker.pubkey()));
-        let recent_blockhash = program.latest_blockhash();
-        let transaction = Transaction::new(&[&taker], message, recent_blockhash);
+        let taken = parse_events::<EscrowTaken>(&tx.logs);
+        assert_eq!(taken[0].amount_paid, 399);
+        assert_eq!(taken[0].fee, 9);
 
-        // This should fail because 5 days haven't passed
-        let result = program.send_transaction(transaction);
-        assert!(
-            result.is_err(),
-            "Take should fail before 5 days have passed"
-        );
-        msg!("Take correctly failed before 5 days: {:?}", result.err());
-    }
+        // ==================== TAKE THE LAST 602: FEE 15.05 ROUNDS DOWN TO 15 ====================
+        let tx = f.send_as_taker(take_ix(&f, 602)).unwrap();
 
-    #[test]
-    fn test_take_succeeds_after_5_days() {
-        // Setup the test environment
-        let (mut program, payer) = setup();
+        assert_eq!(f.balance(&maker_ata_b), 390 + 587);
+        assert_eq!(f.balance(&treasury_ata_b), 9 + 15);
+        assert_eq!(f.balance(&taker_ata_b), INITIAL_BALANCE - 1001);
+        assert_eq!(f.balance(&taker_ata_a), 100);
 
-        // Create separate maker and taker keypairs
-        let maker = Keypair::new();
-        let taker = Keypair::new();
+        let taken = parse_events::<EscrowTaken>(&tx.logs);
+        assert_eq!(taken[0].amount_paid, 602);
+        assert_eq!(taken[0].fee, 15);
 
-        program
-            .airdrop(
-                &maker.pubkey(),
-                10u64
-                    .checked_mul(LAMPORTS_PER_SOL)
-                    .expect("Overflow in airdrop amount"),
-            )
+        // ==================== A FREE ESCROW NEEDS NO CONFIG ====================
+        let ix = free.take_ix(&f.taker.pubkey(), None, vec![]);
+        let tx = f.send_as_taker(ix).unwrap();
+
+        assert_eq!(f.balance(&maker_ata_b), 977 + 1000);
+        assert_eq!(f.balance(&treasury_ata_b), 24);
+        assert_eq!(parse_events::<EscrowTaken>(&tx.logs)[0].fee, 0);
+
+        // ==================== HAND OVER THE CONFIG ====================
+        let ix = client::update_config_ix(&payer, Some(intruder.pubkey()), None, None, None);
+        f.send_as_payer(ix).unwrap();
+
+        let ix = client::update_config_ix(&payer, None, Some(0), None, None);
+        assert_error(f.send_as_payer(ix), EscrowError::Unauthorized);
+        let ix = client::update_config_ix(&intruder.pubkey(), None, Some(0), None, None);
+        f.send(ix, &intruder).unwrap();
+    }
+
+    #[test]
+    fn test_order_book_tracks_make_take_update_and_refund() {
+        let mut f = Fixture::new();
+        let (mint_a, mint_b) = (f.mint_a, f.mint_b);
+        let (maker, taker) = (f.maker.pubkey(), f.taker.pubkey());
+
+        let book = |f: &Fixture| client::order_book(&f.program, &mint_a, &mint_b).unwrap();
+
+        // ==================== OPEN THE BOOK ====================
+        assert!(client::order_book(&f.program, &mint_a, &mint_b).is_none());
+        let ix = client::initialize_order_book_ix(&f.payer.pubkey(), &mint_a, &mint_b);
+        f.send_as_payer(ix).unwrap();
+        assert!(book(&f).offers.is_empty());
+
+        // ==================== MAKE ====================
+        // 100 of mint_a for 30 of mint_b and for 20 of mint_b, both listed
+        let expensive = f.keys(1).with_order_book();
+        let cheap = f.keys(2).with_order_book();
+        let unlisted = f.keys(3);
+
+        f.send_as_maker(expensive.make_ix(MakeArgs::new(100, 30)))
             .unwrap();
-        program
-            .airdrop(
-                &taker.pubkey(),
-                10u64
-                    .checked_mul(LAMPORTS_PER_SOL)
-                    .expect("Overflow in airdrop amount"),
-            )
+        f.send_as_maker(cheap.make_ix(MakeArgs::new(100, 20)))
+            .unwrap();
+        f.send_as_maker(unlisted.make_ix(MakeArgs::new(100, 10)))
             .unwrap();
 
-        // Create mints
-        let mint_a = CreateMint::new(&mut program, &payer)
-            .decimals(6)
-            .authority(&payer.pubkey())
-            .send()
+        let order_book = book(&f);
+        assert_eq!(order_book.mint_a, mint_a);
+        assert_eq!(order_book.mint_b, mint_b);
+        assert_eq!(order_book.offers.len(), 2);
+
+        let best = order_book.best_offer().unwrap();
+        assert_eq!(best.escrow, cheap.escrow());
+        assert_eq!(best.maker, maker);
+        assert_eq!((best.remaining, best.outstanding), (100, 20));
+        assert_eq!(best.price as u128, 20 * client::PRICE_SCALE / 100);
+
+        assert!(f.escrow(&cheap).listed);
+        assert!(!f.escrow(&unlisted).listed);
+
+        // A moving price can't be listed
+        let now = f.now();
+        let ix = f.keys(4).with_order_book().make_ix(MakeArgs {
+            auction: Some(DutchAuction {
+                start_price: 10,
+                end_price: 5,
+                start_time: now,
+                end_time: now + 100,
+            }),
+            ..MakeArgs::new(100, 0)
+        });
+        assert_error(f.send_as_maker(ix), EscrowError::InvalidAuction);
+
+        // ==================== MISMATCHED BOOK ====================
+        // A listed escrow can't be taken around its book, an unlisted one not through it
+        let mut without_book = cheap.clone();
+        without_book.listed = false;
+        let ix = without_book.take_ix(&taker, Some(&f.config()), vec![]);
+        assert_error(f.send_as_taker(ix), EscrowError::OrderBookMismatch);
+
+        let ix = unlisted.clone().with_order_book().refund_ix();
+        assert_error(f.send_as_maker(ix), EscrowError::OrderBookMismatch);
+
+        // ==================== PARTIAL TAKE ====================
+        // 10 of 20 releases 50, the price per unit stays the same
+        let ix = cheap.take_partial_ix(&taker, Some(&f.config()), vec![], 10);
+        f.send_as_taker(ix).unwrap();
+
+        let order_book = book(&f);
+        let best = order_book.best_offer().unwrap();
+        assert_eq!(best.escrow, cheap.escrow());
+        assert_eq!((best.remaining, best.outstanding), (50, 10));
+        assert_eq!(best.price as u128, 20 * client::PRICE_SCALE / 100);
+
+        // ==================== UPDATE ====================
+        // Repricing to 10 makes the other escrow the cheapest
+        let ix = expensive.update_ix(UpdateArgs {
+            receive: Some(10),
+            ..UpdateArgs::default()
+        });
+        f.send_as_maker(ix).unwrap();
+
+        let escrows: Vec<_> = book(&f)
+            .offers_by_price()
+            .into_iter()
+            .map(|offer| offer.escrow)
+            .collect();
+        assert_eq!(escrows, vec![expensive.escrow(), cheap.escrow()]);
+
+        // ==================== FINAL TAKE ====================
+        let ix = cheap.take_ix(&taker, Some(&f.config()), vec![]);
+        f.send_as_taker(ix).unwrap();
+
+        let order_book = book(&f);
+        assert_eq!(order_book.offers.len(), 1);
+        assert_eq!(order_book.best_offer().unwrap().escrow, expensive.escrow());
+
+        // ==================== REFUND ====================
+        f.send_as_maker(expensive.refund_ix()).unwrap();
+        assert!(book(&f).offers.is_empty());
+
+        // The unlisted escrow was never in the book
+        f.send_as_maker(unlisted.refund_ix()).unwrap();
+    }
+
+    #[test]
+    fn test_a_full_order_book_evicts_its_most_expensive_offer() {
+        let mut f = Fixture::new();
+        let (mint_a, mint_b) = (f.mint_a, f.mint_b);
+        let taker = f.taker.pubkey();
+
+        let book = |f: &Fixture| client::order_book(&f.program, &mint_a, &mint_b).unwrap();
+        let ix = client::initialize_order_book_ix(&f.payer.pubkey(), &mint_a, &mint_b);
+        f.send_as_payer(ix).unwrap();
+
+        // ==================== FILL THE BOOK ====================
+        // 10 of mint_a each, for 10 to 10 + MAX_OFFERS - 1 of mint_b
+        let listed: Vec<_> = (0..MAX_OFFERS as u64)
+            .map(|seed| f.keys(seed).with_order_book())
+            .collect();
+        for (price, keys) in (10..).zip(&listed) {
+            f.send_as_maker(keys.make_ix(MakeArgs::new(10, price)))
+                .unwrap();
+        }
+        assert_eq!(book(&f).offers.len(), MAX_OFFERS);
+
+        // ==================== NOT CHEAPER ====================
+        let most_expensive = listed.last().unwrap();
+        let top_price = 10 + MAX_OFFERS as u64 - 1;
+
+        let ix = f
+            .keys(100)
+            .with_order_book()
+            .make_ix(MakeArgs::new(10, top_price));
+        assert_error(f.send_as_maker(ix), EscrowError::OrderBookFull);
+
+        // ==================== EVICTION ====================
+        let cheapest = f.keys(101).with_order_book();
+        f.send_as_maker(cheapest.make_ix(MakeArgs::new(10, 1)))
             .unwrap();
 
-        let mint_b = CreateMint::new(&mut program, &payer)
-            .decimals(6)
-            .authority(&payer.pubkey())
-            .send()
+        let order_book = book(&f);
+        assert_eq!(order_book.offers.len(), MAX_OFFERS);
+        assert_eq!(order_book.best_offer().unwrap().escrow, cheapest.escrow());
+        assert!(order_book
+            .offers
+            .iter()
+            .all(|offer| offer.escrow != most_expensive.escrow()));
+
+        // The evicted escrow stays open and still passes the book
+        assert!(f.escrow(most_expensive).listed);
+        let ix = most_expensive.take_partial_ix(&taker, Some(&f.config()), vec![], top_price / 2);
+        f.send_as_taker(ix).unwrap();
+        assert_eq!(book(&f).offers.len(), MAX_OFFERS);
+
+        f.send_as_maker(most_expensive.refund_ix()).unwrap();
+        assert_eq!(book(&f).offers.len(), MAX_OFFERS);
+
+        // ==================== ROOM AGAIN ====================
+        f.send_as_maker(cheapest.refund_ix()).unwrap();
+        assert_eq!(book(&f).offers.len(), MAX_OFFERS - 1);
+    }
+
+    #[test]
+    fn test_refund_expired_is_permissionless_and_pays_the_cranker() {
+        let mut f = Fixture::new();
+        let maker = f.maker.insecure_clone();
+        let cranker = f.keypair();
+        let maker_ata_a = f.keys(0).token_account(&maker.pubkey(), &f.mint_a).unwrap();
+
+        // The payer covers the fees so balances only move by the refund and reward
+        let send = |f: &mut Fixture, ix: Instruction, signers: &[&Keypair]| {
+            f.program.expire_blockhash();
+            let message = Message::new(&[ix], Some(&f.payer.pubkey()));
+            let signers = [&[&f.payer][..], signers].concat();
+            let transaction = Transaction::new(&signers, message, f.program.latest_blockhash());
+            f.program.send_transaction(transaction)
+        };
+
+        let expires_at = f.now() + 100;
+        let crank_reward = 100_000;
+
+        // ==================== MAKE ====================
+        // The reward has to come out of the escrow's rent
+        let keys = f.keys(1);
+        let ix = keys.make_ix(MakeArgs {
+            expires_at: Some(expires_at),
+            crank_reward: LAMPORTS_PER_SOL,
+            ..MakeArgs::new(600, 30)
+        });
+        assert_error(send(&mut f, ix, &[&maker]), EscrowError::InvalidCrankReward);
+
+        let ix = keys.make_ix(MakeArgs {
+            expires_at: Some(expires_at),
+            crank_reward,
+            ..MakeArgs::new(600, 30)
+        });
+        send(&mut f, ix, &[&maker]).unwrap();
+        assert_eq!(f.escrow(&keys).crank_reward, crank_reward);
+
+        // Never expires, so only the maker can refund it
+        let forever = f.keys(2);
+        send(&mut f, forever.make_ix(MakeArgs::new(400, 20)), &[&maker]).unwrap();
+
+        // ==================== NOT EXPIRED ====================
+        // Take stays open up to and including the expiry
+        f.warp_to(expires_at);
+
+        let ix = keys.refund_expired_ix(Some(cranker.pubkey()));
+        assert_error(send(&mut f, ix, &[&cranker]), EscrowError::EscrowNotExpired);
+
+        // ==================== EXPIRED ====================
+        f.warp_to(expires_at + 1);
+
+        let maker_lamports = f.lamports(&maker.pubkey());
+        let cranker_lamports = f.lamports(&cranker.pubkey());
+        let rent = f.lamports(&keys.escrow()) + f.lamports(&keys.vault().unwrap());
+
+        let ix = keys.refund_expired_ix(Some(cranker.pubkey()));
+        send(&mut f, ix, &[&cranker]).unwrap();
+
+        assert_eq!(f.balance(&maker_ata_a), INITIAL_BALANCE - 400);
+        assert_eq!(
+            f.lamports(&cranker.pubkey()),
+            cranker_lamports + crank_reward
+        );
+        assert_eq!(
+            f.lamports(&maker.pubkey()),
+            maker_lamports + rent - crank_reward
+        );
+        assert!(f.is_closed(&keys.escrow()));
+
+        assert_error(
+            send(&mut f, forever.refund_expired_ix(None), &[]),
+            EscrowError::EscrowNotExpired,
+        );
+    }
+
+    #[test]
+    #[ignore = "needs the tuktuk program and task queue fixtures, see TASK_QUEUE_FIXTURE"]
+    fn test_schedule_refund_queues_a_tuktuk_task() {
+        let mut f = Fixture::new();
+
+        let so_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("fixtures")
+            .join(format!("{}.so", client::TUKTUK_PROGRAM_ID));
+        let tuktuk = std::fs::read(&so_path)
+            .unwrap_or_else(|err| panic!("Failed to read {}: {err}", so_path.display()));
+        f.program.add_program(client::TUKTUK_PROGRAM_ID, &tuktuk);
+
+        let (task_queue, account) = load_fixture(TASK_QUEUE_FIXTURE);
+        f.program.set_account(task_queue, account).unwrap();
+
+        // What `add_queue_authority_v0`, signed by the queue's update authority,
+        // creates for the escrow's queue authority:
+        // TaskQueueAuthorityV0 { task_queue, queue_authority, bump_seed }
+        let queue_authority = client::queue_authority_address();
+        let (task_queue_authority, bump) = Pubkey::find_program_address(
+            &[
+                b"task_queue_authority",
+                task_queue.as_ref(),
+                queue_authority.as_ref(),
+            ],
+            &client::TUKTUK_PROGRAM_ID,
+        );
+        assert_eq!(
+            task_queue_authority,
+            client::task_queue_authority_address(&task_queue)
+        );
+        let data = [
+            &hash(b"account:TaskQueueAuthorityV0").to_bytes()[..8],
+            task_queue.as_ref(),
+            queue_authority.as_ref(),
+            &[bump],
+        ]
+        .concat();
+        let account = Account {
+            lamports: f.program.minimum_balance_for_rent_exemption(data.len()),
+            data,
+            owner: client::TUKTUK_PROGRAM_ID,
+            executable: false,
+            rent_epoch: 0,
+        };
+        f.program
+            .set_account(task_queue_authority, account)
             .unwrap();
 
-        // Create maker's ATA for mint_a
-        let maker_ata_a = CreateAssociatedTokenAccount::new(&mut program, &payer, &mint_a)
-            .owner(&maker.pubkey())
-            .send()
+        // ==================== MAKE ====================
+        let expires_at = f.now() + 100;
+        let keys = f.keys(1);
+        let never_expires = f.keys(2);
+
+        f.send_as_maker(keys.make_ix(MakeArgs {
+            expires_at: Some(expires_at),
+            ..MakeArgs::new(600, 30)
+        }))
+        .unwrap();
+        f.send_as_maker(never_expires.make_ix(MakeArgs::new(400, 20)))
             .unwrap();
 
-        // Create taker's ATA for mint_b
-        let taker_ata_b = CreateAssociatedTokenAccount::new(&mut program, &payer, &mint_b)
-            .owner(&taker.pubkey())
-            .send()
+        // ==================== SCHEDULE ====================
+        assert_error(
+            f.send_as_maker(never_expires.schedule_refund_ix(&task_queue, 1)),
+            EscrowError::MissingExpiry,
+        );
+
+        f.send_as_maker(keys.schedule_refund_ix(&task_queue, 0))
             .unwrap();
 
-        // Derive escrow and vault
-        let escrow = Pubkey::find_program_address(
-            &[b"escrow", maker.pubkey().as_ref(), &999u64.to_le_bytes()],
-            &PROGRAM_ID,
-        )
-        .0;
+        let task = client::task_address(&task_queue, 0);
+        assert_eq!(
+            f.program.get_account(&task).unwrap().owner,
+            client::TUKTUK_PROGRAM_ID
+        );
+        assert_eq!(f.escrow(&keys).refund_task, Some(task));
 
-        let vault = associated_token::get_associated_token_address(&escrow, &mint_a);
+        // Once is enough
+        assert_error(
+            f.send_as_maker(keys.schedule_refund_ix(&task_queue, 1)),
+            EscrowError::RefundScheduled,
+        );
 
-        let associated_token_program = spl_associated_token_account::ID;
-        let token_program = TOKEN_PROGRAM_ID;
-        let system_program = SYSTEM_PROGRAM_ID;
+        // ==================== REFUND DEQUEUES ====================
+        let task_rent = f.lamports(&task);
+        let maker_lamports = f.lamports(&f.maker.pubkey());
 
-        // Mint tokens
-        MintTo::new(&mut program, &payer, &mint_a, &maker_ata_a, 1000000000)
-            .send()
+        f.send_as_maker(keys.clone().with_refund_task(task_queue, 0).refund_ix())
             .unwrap();
 
-        MintTo::new(&mut program, &payer, &mint_b, &taker_ata_b, 1000000000)
-            .send()
+        assert!(f.is_closed(&keys.escrow()));
+        assert!(f.is_closed(&task));
+        assert!(f.lamports(&f.maker.pubkey()) > maker_lamports + task_rent);
+    }
+
+    #[test]
+    fn test_a_scheduled_refund_fixes_the_expiry() {
+        let mut f = Fixture::new();
+
+        let expires_at = f.now() + 100;
+        let keys = f.keys(1);
+        f.send_as_maker(keys.make_ix(MakeArgs {
+            expires_at: Some(expires_at),
+            ..MakeArgs::new(600, 30)
+        }))
+        .unwrap();
+
+        // Stands in for `schedule_refund`, which needs tuktuk
+        let mut account = f.program.get_account(&keys.escrow()).unwrap();
+        let mut escrow =
+            crate::state::Escrow::try_deserialize(&mut account.data.as_slice()).unwrap();
+        escrow.refund_task = Some(Pubkey::new_unique());
+        escrow
+            .try_serialize(&mut account.data.as_mut_slice())
             .unwrap();
+        f.program.set_account(keys.escrow(), account).unwrap();
+
+        // ==================== EXPIRY IS FIXED ====================
+        for expires_at in [Some(expires_at + 100), Some(expires_at - 1), None] {
+            let ix = keys.update_ix(UpdateArgs {
+                expires_at: Some(expires_at),
+                ..UpdateArgs::default()
+            });
+            assert_error(f.send_as_maker(ix), EscrowError::RefundScheduled);
+        }
 
-        // ==================== MAKE INSTRUCTION ====================
-        let make_ix = Instruction {
-            program_id: PROGRAM_ID,
-            accounts: crate::accounts::Make {
-                maker: maker.pubkey(),
-                mint_a: mint_a,
-                mint_b: mint_b,
-                maker_ata_a: maker_ata_a,
-                escrow: escrow,
-                vault: vault,
-                associated_token_program: associated_token_program,
-                token_program: token_program,
-                system_program: system_program,
+        // ==================== THE REST CAN CHANGE ====================
+        let ix = keys.update_ix(UpdateArgs {
+            receive: Some(40),
+            expires_at: Some(Some(expires_at)),
+            ..UpdateArgs::default()
+        });
+        f.send_as_maker(ix).unwrap();
+
+        let escrow = f.escrow(&keys);
+        assert_eq!(escrow.receive, 40);
+        assert_eq!(escrow.expires_at, Some(expires_at));
+
+        // ==================== CLOSING NEEDS THE TASK ====================
+        assert_error(
+            f.send_as_maker(keys.refund_ix()),
+            EscrowError::MissingRefundTask,
+        );
+        assert_error(
+            f.send_as_taker(keys.take_ix(&f.taker.pubkey(), Some(&f.config()), vec![])),
+            EscrowError::MissingRefundTask,
+        );
+        assert!(!f.is_closed(&keys.escrow()));
+    }
+
+    #[test]
+    fn test_dutch_auction_price_decays_linearly() {
+        let mut f = Fixture::new();
+        let taker_ata_b = f
+            .keys(0)
+            .token_account(&f.taker.pubkey(), &f.mint_b)
+            .unwrap();
+        let maker_ata_b = f
+            .keys(0)
+            .token_account(&f.maker.pubkey(), &f.mint_b)
+            .unwrap();
+
+        // 1000 of mint_b down to 200 over 1000 seconds, starting in 100 seconds
+        let now = f.now();
+        let auction = DutchAuction {
+            start_price: 1000,
+            end_price: 200,
+            start_time: now + 100,
+            end_time: now + 1100,
+        };
+
+        // ==================== INVALID AUCTIONS ====================
+        let keys = f.keys(0);
+        for invalid in [
+            // The price goes up
+            DutchAuction {
+                end_price: 2000,
+                ..auction.clone()
+            },
+            // Ends at zero
+            DutchAuction {
+                end_price: 0,
+                ..auction.clone()
+            },
+            // Empty period
+            DutchAuction {
+                end_time: auction.start_time,
+                ..auction.clone()
+            },
+        ] {
+            let ix = keys.make_ix(MakeArgs {
+                auction: Some(invalid),
+                ..MakeArgs::new(100, 1000)
+            });
+            assert_error(f.send_as_maker(ix), EscrowError::InvalidAuction);
+        }
+
+        // ==================== MAKE ====================
+        // One escrow per point of the curve, `receive` is ignored
+        let escrows: Vec<_> = (1..=4)
+            .map(|seed| {
+                let keys = f.keys(seed);
+                let ix = keys.make_ix(MakeArgs {
+                    auction: Some(auction.clone()),
+                    ..MakeArgs::new(100, 1)
+                });
+                f.send_as_maker(ix).unwrap();
+                keys
+            })
+            .collect();
+
+        let escrow = f.escrow(&escrows[0]);
+        assert_eq!(escrow.receive, 1000);
+        assert_eq!(escrow.auction, Some(auction.clone()));
+
+        // ==================== WHOLE FILLS AND FIXED CURVE ONLY ====================
+        let ix = escrows[0].take_partial_ix(&f.taker.pubkey(), Some(&f.config()), vec![], 500);
+        assert_error(f.send_as_taker(ix), EscrowError::WholeFillOnly);
+
+        let ix = escrows[0].update_ix(UpdateArgs {
+            receive: Some(500),
+            ..UpdateArgs::default()
+        });
+        assert_error(f.send_as_maker(ix), EscrowError::InvalidUpdate);
+
+        // ==================== TAKE ALONG THE CURVE ====================
+        let points = [
+            // Start
+            (auction.start_time, 1000),
+            // Midpoint
+            (auction.start_time + 500, 600),
+            // End
+            (auction.end_time, 200),
+            // Holds at the end price afterwards
+            (auction.end_time + 1000, 200),
+        ];
+
+        for (keys, (unix_timestamp, price)) in escrows.iter().zip(points) {
+            f.warp_to(unix_timestamp);
+
+            let paid_before = f.balance(&maker_ata_b);
+            let ix = keys.take_ix(&f.taker.pubkey(), Some(&f.config()), vec![]);
+            f.send_as_taker(ix).unwrap();
+
+            assert_eq!(f.balance(&maker_ata_b) - paid_before, price);
+        }
+
+        assert_eq!(f.balance(&maker_ata_b), 2000);
+        assert_eq!(f.balance(&taker_ata_b), INITIAL_BALANCE - 2000);
+    }
+
+    // Stand-in for a Pyth `PriceUpdateV2` account, fully verified
+    fn price_update(price: i64, conf: u64, exponent: i32, publish_time: i64) -> Vec<u8> {
+        let fields: [&[u8]; 12] = [
+            &anchor_lang::solana_program::hash::hash(b"account:PriceUpdateV2").to_bytes()[..8],
+            // Write authority
+            &[0; 32],
+            // VerificationLevel::Full
+            &[1],
+            // Feed id
+            &[0; 32],
+            &price.to_le_bytes(),
+            &conf.to_le_bytes(),
+            &exponent.to_le_bytes(),
+            &publish_time.to_le_bytes(),
+            // Previous publish time, EMA price and EMA confidence
+            &(publish_time - 1).to_le_bytes(),
+            &price.to_le_bytes(),
+            &conf.to_le_bytes(),
+            // Posted slot
+            &0u64.to_le_bytes(),
+        ];
+        fields.concat()
+    }
+
+    #[test]
+    fn test_take_at_the_oracle_price() {
+        // Mint A has 9 decimals like SOL and mint B 6 like USDC
+        let mut f = Fixture::with_decimals(9, 6);
+        let oracle_program = Pubkey::new_unique();
+        let feed = Pubkey::new_unique();
+
+        let set_feed = |f: &mut Fixture, address: Pubkey, owner: Pubkey, data: Vec<u8>| {
+            let lamports = f.program.minimum_balance_for_rent_exemption(data.len());
+            f.program
+                .set_account(
+                    address,
+                    Account {
+                        lamports,
+                        data,
+                        owner,
+                        executable: false,
+                        rent_epoch: 0,
+                    },
+                )
+                .unwrap();
+        };
+
+        // 150.00 of mint B per mint A, sold at 1% below
+        let now = f.now();
+        set_feed(
+            &mut f,
+            feed,
+            oracle_program,
+            price_update(15000, 50, -2, now),
+        );
+
+        let pricing = OraclePricing {
+            feed,
+            spread_bps: -100,
+            max_age: 60,
+            max_conf_bps: 100,
+        };
+
+        // ==================== INVALID PRICING ====================
+        let keys = f.keys(1).with_oracle_feed(feed);
+        let ix = keys.make_ix(MakeArgs {
+            oracle: Some(OraclePricing {
+                spread_bps: -10_000,
+                ..pricing.clone()
+            }),
+            ..MakeArgs::new(1_000_000_000, 1)
+        });
+        assert_error(f.send_as_maker(ix), EscrowError::InvalidOraclePricing);
+
+        // ==================== MAKE ====================
+        let ix = keys.make_ix(MakeArgs {
+            oracle: Some(pricing.clone()),
+            ..MakeArgs::new(1_000_000_000, 1)
+        });
+        f.send_as_maker(ix).unwrap();
+
+        let take = |f: &mut Fixture, keys: &EscrowKeys, max_pay: u64| {
+            let ix =
+                keys.take_with_max_pay_ix(&f.taker.pubkey(), Some(&f.config()), vec![], max_pay);
+            f.send_as_taker(ix)
+        };
+
+        // ==================== ORACLE PROGRAM ====================
+        // No oracle program is configured yet
+        assert_error(
+            take(&mut f, &keys, u64::MAX),
+            EscrowError::InvalidOracleFeed,
+        );
+
+        let ix = client::update_config_ix(
+            &f.payer.pubkey(),
+            None,
+            None,
+            None,
+            Some(Some(oracle_program)),
+        );
+        f.send_as_payer(ix).unwrap();
+        assert_eq!(f.config().oracle_program, Some(oracle_program));
+
+        // ==================== WRONG FEED ====================
+        // Another account with a valid price
+        let other_feed = Pubkey::new_unique();
+        set_feed(
+            &mut f,
+            other_feed,
+            oracle_program,
+            price_update(100, 0, -2, now),
+        );
+        let wrong_feed = keys.clone().with_oracle_feed(other_feed);
+        assert_error(
+            take(&mut f, &wrong_feed, u64::MAX),
+            EscrowError::InvalidOracleFeed,
+        );
+
+        // The escrow's feed written by another program
+        set_feed(
+            &mut f,
+            feed,
+            Pubkey::new_unique(),
+            price_update(15000, 50, -2, now),
+        );
+        assert_error(
+            take(&mut f, &keys, u64::MAX),
+            EscrowError::InvalidOracleFeed,
+        );
+
+        // A price that is not positive
+        set_feed(&mut f, feed, oracle_program, price_update(0, 0, -2, now));
+        assert_error(
+            take(&mut f, &keys, u64::MAX),
+            EscrowError::InvalidOracleFeed,
+        );
+
+        // ==================== STALENESS AND CONFIDENCE ====================
+        set_feed(
+            &mut f,
+            feed,
+            oracle_program,
+            price_update(15000, 50, -2, now - 61),
+        );
+        assert_error(take(&mut f, &keys, u64::MAX), EscrowError::StaleOraclePrice);
+
+        // 1.51 on 150.00 is just over 1%
+        set_feed(
+            &mut f,
+            feed,
+            oracle_program,
+            price_update(15000, 151, -2, now),
+        );
+        assert_error(
+            take(&mut f, &keys, u64::MAX),
+            EscrowError::OracleConfidenceTooWide,
+        );
+
+        // ==================== WHOLE FILLS ONLY ====================
+        set_feed(
+            &mut f,
+            feed,
+            oracle_program,
+            price_update(15000, 150, -2, now - 60),
+        );
+        let ix = keys.take_partial_ix(&f.taker.pubkey(), Some(&f.config()), vec![], 1_000_000);
+        assert_error(f.send_as_taker(ix), EscrowError::WholeFillOnly);
+
+        // ==================== MAX PAY ====================
+        // 1 of mint A at 150.00 minus 1% is 148.500000 of mint B
+        assert_error(
+            take(&mut f, &keys, 148_499_999),
+            EscrowError::MaxPayExceeded,
+        );
+
+        take(&mut f, &keys, 148_500_000).unwrap();
+
+        let maker_ata_b = keys.token_account(&f.maker.pubkey(), &f.mint_b).unwrap();
+        let taker_ata_a = keys.token_account(&f.taker.pubkey(), &f.mint_a).unwrap();
+        assert_eq!(f.balance(&maker_ata_b), 148_500_000);
+        assert_eq!(f.balance(&taker_ata_a), 1_000_000_000);
+    }
+
+    #[test]
+    fn test_take_with_route_swaps_into_mint_b() {
+        use anchor_lang::{InstructionData, ToAccountMetas};
+
+        let mut f = Fixture::new();
+        let (mint_a, mint_b) = (f.mint_a, f.mint_b);
+
+        // Outside the deployable workspace, built with
+        // `cargo build-sbf --manifest-path tests/mock-amm/Cargo.toml`
+        let so_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("../../tests/mock-amm/target/deploy/mock_amm.so");
+        let amm_data = std::fs::read(so_path).expect("Failed to read mock AMM SO file");
+        f.program.add_program(mock_amm::ID, &amm_data);
+
+        let pool = Pubkey::find_program_address(&[b"pool"], &mock_amm::ID).0;
+
+        // This taker only holds mint C, which the AMM sells mint B for
+        let taker = f.keypair();
+        let mint_c = f.create_mint(6);
+        let taker_ata_b = f.mint_to(&taker.pubkey(), &mint_b, 0);
+        let taker_ata_c = f.mint_to(&taker.pubkey(), &mint_c, 1000);
+        let pool_b = f.mint_to(&pool, &mint_b, 1000);
+        let pool_c = f.mint_to(&pool, &mint_c, 0);
+
+        // Buys exactly `amount_out` of mint B with mint C
+        let swap = |amount_out: u64| Instruction {
+            program_id: mock_amm::ID,
+            accounts: mock_amm::accounts::Swap {
+                user: taker.pubkey(),
+                pool,
+                mint_in: mint_c,
+                mint_out: mint_b,
+                user_in: taker_ata_c,
+                user_out: taker_ata_b,
+                pool_in: pool_c,
+                pool_out: pool_b,
+                token_program: TOKEN_PROGRAM_ID,
             }
             .to_account_metas(None),
-            data: crate::instruction::Make {
-                deposit: 100,
-                seed: 999u64,
-                receive: 50,
+            data: mock_amm::instruction::SwapExactOut {
+                amount_out,
+                max_in: amount_out * mock_amm::RATE,
             }
             .data(),
         };
 
-        let message = Message::new(&[make_ix], Some(&maker.pubkey()));
-        let recent_blockhash = program.latest_blockhash();
-        let transaction = Transaction::new(&[&maker], message, recent_blockhash);
-        program.send_transaction(transaction).unwrap();
+        // ==================== MAKE ====================
+        let keys = f.keys(1);
+        f.send_as_maker(keys.make_ix(MakeArgs::new(100, 30)))
+            .unwrap();
 
-        msg!("Make transaction successful");
+        let take_with_route = |f: &mut Fixture, route: Instruction| {
+            let ix =
+                keys.take_with_route_ix(&taker.pubkey(), Some(&f.config()), vec![], None, route);
+            f.send(ix, &taker)
+        };
 
-        // ==================== WARP TIME FORWARD 5 DAYS ====================
-        // Update the clock timestamp
-        let mut clock: Clock = program.get_sysvar();
-        msg!("Current slot: {}, timestamp: {}", clock.slot, clock.unix_timestamp);
+        // ==================== INVALID ROUTES ====================
+        // The escrow can't route into itself
+        let route = Instruction {
+            program_id: PROGRAM_ID,
+            accounts: vec![],
+            data: vec![],
+        };
+        assert_error(take_with_route(&mut f, route), EscrowError::InvalidRoute);
 
-        clock.unix_timestamp = clock.unix_timestamp
-            .checked_add(FIVE_DAYS_IN_SECONDS)
-            .unwrap()
-            .checked_add(1)
-            .unwrap();
-        
-        program.set_sysvar(&clock);
+        // Nor through a program the config does not allow
+        assert_error(take_with_route(&mut f, swap(35)), EscrowError::InvalidRoute);
 
-        // Also warp the slot forward and expire the blockhash
-        program.warp_to_slot(clock.slot.checked_add(1_000_000).unwrap());
-        program.expire_blockhash();
+        // Which only the admin can change, for at most four programs
+        let intruder = f.keypair();
+        let ix = client::set_swap_programs_ix(&intruder.pubkey(), vec![mock_amm::ID]);
+        assert_error(f.send(ix, &intruder), EscrowError::Unauthorized);
 
-        let new_clock: Clock = program.get_sysvar();
-        msg!("New slot: {}, timestamp: {}", new_clock.slot, new_clock.unix_timestamp);
+        let ix = client::set_swap_programs_ix(&f.payer.pubkey(), vec![mock_amm::ID; 5]);
+        assert_error(f.send_as_payer(ix), EscrowError::TooManySwapPrograms);
 
-        // ==================== TAKE INSTRUCTION (should succeed now) ====================
-        let taker_ata_a = associated_token::get_associated_token_address(&taker.pubkey(), &mint_a);
-        let maker_ata_b = associated_token::get_associated_token_address(&maker.pubkey(), &mint_b);
+        let ix = client::set_swap_programs_ix(&f.payer.pubkey(), vec![mock_amm::ID]);
+        f.send_as_payer(ix).unwrap();
+        assert_eq!(f.config().swap_programs, vec![mock_amm::ID]);
 
-        let take_ix = Instruction {
-            program_id: PROGRAM_ID,
-            accounts: crate::accounts::Take {
-                taker: taker.pubkey(),
-                maker: maker.pubkey(),
-                mint_a: mint_a,
-                mint_b: mint_b,
-                taker_ata_a: taker_ata_a,
-                taker_ata_b: taker_ata_b,
-                maker_ata_b: maker_ata_b,
-                escrow: escrow,
-                vault: vault,
-                associated_token_program: associated_token_program,
-                token_program: token_program,
-                system_program: system_program,
-            }
-            .to_account_metas(None),
-            data: crate::instruction::Take {}.data(),
-        };
+        // 29 of the 30 outstanding, the swap is rolled back with the take
+        assert_error(
+            take_with_route(&mut f, swap(29)),
+            EscrowError::RouteShortfall,
+        );
+        assert_eq!(f.balance(&taker_ata_c), 1000);
+        assert_eq!(f.balance(&taker_ata_b), 0);
+
+        // ==================== ROUTED TAKE ====================
+        // 35 of mint B for 70 of mint C, the 5 not needed stay with the taker
+        take_with_route(&mut f, swap(35)).unwrap();
+
+        let maker_ata_b = keys.token_account(&f.maker.pubkey(), &mint_b).unwrap();
+        let taker_ata_a = keys.token_account(&taker.pubkey(), &mint_a).unwrap();
+        assert_eq!(f.balance(&taker_ata_c), 930);
+        assert_eq!(f.balance(&taker_ata_b), 5);
+        assert_eq!(f.balance(&maker_ata_b), 30);
+        assert_eq!(f.balance(&taker_ata_a), 100);
+        assert_eq!(f.balance(&pool_b), 965);
+        assert!(f.is_closed(&keys.escrow()));
+    }
 
-        let message = Message::new(&[take_ix], Some(&taker.pubkey()));
-        let recent_blockhash = program.latest_blockhash();
-        let transaction = Transaction::new(&[&taker], message, recent_blockhash);
-
-        // This should succeed because 5 days have passed
-        let tx = program.send_transaction(transaction).unwrap();
-        msg!("\n\nTake transaction successful after 5 days");
-        msg!("CUs Consumed: {}", tx.compute_units_consumed);
-
-        // Verify final state
-        let taker_ata_a_account = program.get_account(&taker_ata_a).unwrap();
-        let taker_ata_a_data =
-            spl_token::state::Account::unpack(&taker_ata_a_account.data).unwrap();
-        assert_eq!(taker_ata_a_data.amount, 100);
-        msg!(
-            "Taker received {} tokens of mint_a",
-            taker_ata_a_data.amount
-        );
-
-        let maker_ata_b_account = program.get_account(&maker_ata_b).unwrap();
-        let maker_ata_b_data =
-            spl_token::state::Account::unpack(&maker_ata_b_account.data).unwrap();
-        assert_eq!(maker_ata_b_data.amount, 50);
-        msg!(
-            "Maker received {} tokens of mint_b",
-            maker_ata_b_data.amount
+    fn escrow_state(receive: u64, filled: u64, remaining: u64) -> crate::state::Escrow {
+        crate::state::Escrow {
+            seed: 0,
+            maker: Pubkey::default(),
+            mint_a: Pubkey::default(),
+            mint_b: Pubkey::default(),
+            receive,
+            filled,
+            remaining,
+            created_at: 0,
+            lock_until: 0,
+            expires_at: None,
+            allowlist: crate::state::TakerAllowlist::Open,
+            crank_reward: 0,
+            refund_task: None,
+            fee_bps: 0,
+            auction: None,
+            oracle: None,
+            listed: false,
+            bump: 0,
+        }
+    }
+
+    #[test]
+    fn test_fill_amount_rounds_down_and_sweeps_dust() {
+        let escrow = escrow_state(30, 0, 100);
+        assert_eq!(escrow.fill_amount(10).unwrap(), 33);
+        assert_eq!(escrow.fill_amount(30).unwrap(), 100);
+
+        // Whatever rounding left behind goes to the fill that completes the escrow
+        let escrow = escrow_state(30, 29, 4);
+        assert_eq!(escrow.fill_amount(1).unwrap(), 4);
+
+        // A fill that would release nothing is rejected instead of taking payment for free
+        let escrow = escrow_state(1000, 0, 10);
+        assert_eq!(
+            escrow.fill_amount(99).unwrap_err(),
+            EscrowError::FillTooSmall.into()
         );
+        assert_eq!(escrow.fill_amount(100).unwrap(), 1);
+
+        assert_eq!(
+            escrow.fill_amount(0).unwrap_err(),
+            EscrowError::InvalidFillAmount.into()
+        );
+        assert_eq!(
+            escrow.fill_amount(1001).unwrap_err(),
+            EscrowError::InvalidFillAmount.into()
+        );
+
+        // The product is taken in u128, so large amounts do not overflow
+        let escrow = escrow_state(u64::MAX, 0, u64::MAX);
+        assert_eq!(escrow.fill_amount(u64::MAX - 1).unwrap(), u64::MAX - 1);
+    }
+
+    #[test]
+    fn test_auction_price_rounds_the_decay_down() {
+        let auction = crate::state::DutchAuction {
+            start_price: 1000,
+            end_price: 200,
+            start_time: 10,
+            end_time: 13,
+        };
+
+        assert_eq!(auction.price_at(0), 1000);
+        // 800 / 3 decays 266 after a second, not 267
+        assert_eq!(auction.price_at(11), 734);
+        assert_eq!(auction.price_at(12), 467);
+        assert_eq!(auction.price_at(13), 200);
+        assert_eq!(auction.price_at(i64::MAX), 200);
+
+        // The whole i64 range does not overflow
+        let auction = crate::state::DutchAuction {
+            start_price: u64::MAX,
+            end_price: 1,
+            start_time: i64::MIN,
+            end_time: i64::MAX,
+        };
+        assert_eq!(auction.price_at(0), u64::MAX / 2 + 1);
     }
 }
@@ -0,0 +1,101 @@
+#![allow(unexpected_cfgs)]
+#![allow(deprecated)]
+
+//! Minimal AMM for the escrow's `take_with_route` tests.
+//!
+//! A single pool PDA at `[b"pool"]` sells any mint it holds for any other at a
+//! fixed `RATE` of input per unit of output. Output only comes out of the pool's
+//! own token accounts, so the tests fund it up front.
+
+use anchor_lang::prelude::*;
+use anchor_spl::token_interface::{
+    transfer_checked, Mint, TokenAccount, TokenInterface, TransferChecked,
+};
+
+declare_id!("EGocGG8qPrKvEKmvXiPhG8hsZPPAcLBedUVCn61rjZgP");
+
+/// Input paid per unit of output, in base units
+pub const RATE: u64 = 2;
+
+#[program]
+pub mod mock_amm {
+    use super::*;
+
+    /// Pays out exactly `amount_out`, failing if it costs more than `max_in`
+    pub fn swap_exact_out(ctx: Context<Swap>, amount_out: u64, max_in: u64) -> Result<()> {
+        ctx.accounts.swap(amount_out, max_in, ctx.bumps.pool)
+    }
+}
+
+#[error_code]
+pub enum AmmError {
+    #[msg("The swap costs more than max_in")]
+    SlippageExceeded,
+    #[msg("Arithmetic overflow")]
+    MathOverflow,
+}
+
+#[derive(Accounts)]
+pub struct Swap<'info> {
+    pub user: Signer<'info>,
+    /// CHECK: Only signs for the pool's token accounts
+    #[account(seeds = [b"pool"], bump)]
+    pub pool: UncheckedAccount<'info>,
+    pub mint_in: InterfaceAccount<'info, Mint>,
+    pub mint_out: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        token::mint = mint_in,
+        token::authority = user,
+    )]
+    pub user_in: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = mint_out,
+    )]
+    pub user_out: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = mint_in,
+        token::authority = pool,
+    )]
+    pub pool_in: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        token::mint = mint_out,
+        token::authority = pool,
+    )]
+    pub pool_out: InterfaceAccount<'info, TokenAccount>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+impl<'info> Swap<'info> {
+    pub fn swap(&mut self, amount_out: u64, max_in: u64, pool_bump: u8) -> Result<()> {
+        let amount_in = amount_out.checked_mul(RATE).ok_or(AmmError::MathOverflow)?;
+        require!(amount_in <= max_in, AmmError::SlippageExceeded);
+
+        let cpi_accounts = TransferChecked {
+            from: self.user_in.to_account_info(),
+            to: self.pool_in.to_account_info(),
+            authority: self.user.to_account_info(),
+            mint: self.mint_in.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new(self.token_program.to_account_info(), cpi_accounts);
+        transfer_checked(cpi_ctx, amount_in, self.mint_in.decimals)?;
+
+        let signer_seeds: [&[&[u8]]; 1] = [&[b"pool", &[pool_bump]]];
+
+        let cpi_accounts = TransferChecked {
+            from: self.pool_out.to_account_info(),
+            to: self.user_out.to_account_info(),
+            authority: self.pool.to_account_info(),
+            mint: self.mint_out.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            self.token_program.to_account_info(),
+            cpi_accounts,
+            &signer_seeds,
+        );
+        transfer_checked(cpi_ctx, amount_out, self.mint_out.decimals)
+    }
+}